@@ -20,7 +20,14 @@ pkgs.rustPlatform.buildRustPackage rec {
     libadwaita
   ];
 
+  KAEDE_HELPER_PATH = "${placeholder "out"}/libexec/kaede-helper";
+
   postInstall = ''
+    install -Dm755 $out/bin/kaede-helper $out/libexec/kaede-helper
+    rm $out/bin/kaede-helper
+    install -Dm644 com.kaede.gpu-manager.policy $out/share/polkit-1/actions/com.kaede.gpu-manager.policy
+    substituteInPlace $out/share/polkit-1/actions/com.kaede.gpu-manager.policy \
+      --replace-fail /usr/libexec/kaede-helper $out/libexec/kaede-helper
     install -Dm644 com.kaede.gpu-manager.desktop $out/share/applications/com.kaede.gpu-manager.desktop
     install -Dm644 src/icons/icon.png $out/share/icons/hicolor/256x256/apps/com.kaede.gpu-manager.png
   '';
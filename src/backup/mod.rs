@@ -0,0 +1,344 @@
+//! Timestamped copies of every file Kaede changes, grouped into change sets
+//! under `$XDG_STATE_HOME/kaede/backups/<id>/` with a `manifest.json` each.
+//! The helper runs as root without the user's environment, so its change sets
+//! go to [`SYSTEM_BACKUPS_DIR`], which the GUI and CLI list as well.
+//!
+//! Writers call [`snapshot`] before touching a file. Everything snapshotted
+//! between [`begin`] and dropping its guard lands in one change set; a
+//! snapshot outside of one gets a change set of its own.
+//!
+//! The oldest change set holding a file is its copy from before Kaede first
+//! touched it, and is never pruned.
+
+use crate::transaction::Transaction;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fs;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+const MANIFEST: &str = "manifest.json";
+const SYSTEM_BACKUPS_DIR: &str = "/var/lib/kaede/backups";
+/// Change sets beyond this count are pruned, oldest first. The first backup of
+/// each file doesn't count.
+const KEEP_CHANGE_SETS: usize = 50;
+/// Change sets older than this are pruned, except for the newest few and
+/// first backups.
+const KEEP_DAYS: u64 = 90;
+const KEEP_ALWAYS: usize = 5;
+/// The single copy each integration kept next to the file before change sets.
+const LEGACY_SUFFIX: &str = ".kaede.bak";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeSet {
+    pub id: String,
+    /// Unix time in seconds.
+    pub created: u64,
+    pub description: String,
+    pub files: Vec<BackupFile>,
+    /// Stored under [`SYSTEM_BACKUPS_DIR`] by the helper.
+    #[serde(skip)]
+    pub system: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub original: PathBuf,
+    /// Copy inside the change set directory; `None` when the file didn't exist,
+    /// so restoring removes it.
+    pub stored: Option<String>,
+    #[serde(default)]
+    pub mode: Option<u32>,
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<ChangeSet>> = const { RefCell::new(None) };
+}
+
+/// Ends the active change set when dropped.
+pub struct ChangeSetGuard {
+    owner: bool,
+}
+
+impl Drop for ChangeSetGuard {
+    fn drop(&mut self) {
+        if !self.owner {
+            return;
+        }
+        let finished = ACTIVE.with(|active| active.borrow_mut().take());
+        if finished.is_some_and(|set| !set.files.is_empty()) {
+            prune();
+        }
+    }
+}
+
+/// Starts a change set. Calls nested inside another change set join it.
+pub fn begin(description: &str) -> ChangeSetGuard {
+    let owner = ACTIVE.with(|active| {
+        let mut active = active.borrow_mut();
+        if active.is_some() {
+            return false;
+        }
+        *active = Some(new_change_set(description));
+        true
+    });
+    ChangeSetGuard { owner }
+}
+
+/// Records the current content of `path` (or that it doesn't exist) before it
+/// is changed. Files already recorded in the active change set are skipped.
+pub fn snapshot(path: &Path) -> Result<()> {
+    let _guard = begin(&format!("Edit {}", path.display()));
+    ACTIVE.with(|active| {
+        let mut active = active.borrow_mut();
+        let set = active.as_mut().context("no active backup change set")?;
+        if set.files.iter().any(|file| file.original == path) {
+            return Ok(());
+        }
+        import_legacy_backup(path)?;
+
+        let dir = set_dir(set);
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create backup directory {}", dir.display()))?;
+        let file = match fs::metadata(path) {
+            Ok(metadata) => {
+                let stored = format!("{:03}-{}", set.files.len(), file_name(path));
+                fs::copy(path, dir.join(&stored))
+                    .with_context(|| format!("failed to back up {}", path.display()))?;
+                BackupFile {
+                    original: path.to_path_buf(),
+                    stored: Some(stored),
+                    mode: Some(metadata.permissions().mode()),
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BackupFile {
+                original: path.to_path_buf(),
+                stored: None,
+                mode: None,
+            },
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", path.display()));
+            }
+        };
+
+        set.files.push(file);
+        write_manifest(&dir, set)?;
+        debug!(path = %path.display(), change_set = %set.id, "file backed up");
+        Ok(())
+    })
+}
+
+/// Every change set, the user's and the helper's, newest first.
+pub fn list() -> Vec<ChangeSet> {
+    let mut dirs = vec![(backups_dir(true), true)];
+    if !running_as_root() {
+        dirs.push((backups_dir(false), false));
+    }
+    let mut sets = dirs
+        .into_iter()
+        .filter_map(|(dir, system)| Some((fs::read_dir(dir).ok()?, system)))
+        .flat_map(|(entries, system)| entries.flatten().map(move |entry| (entry, system)))
+        .filter_map(|(entry, system)| {
+            let raw = fs::read_to_string(entry.path().join(MANIFEST)).ok()?;
+            match serde_json::from_str::<ChangeSet>(&raw) {
+                Ok(set) => Some(ChangeSet { system, ..set }),
+                Err(err) => {
+                    warn!(path = %entry.path().display(), error = %err, "unreadable backup manifest");
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    sets.sort_by(|a, b| b.id.cmp(&a.id));
+    sets
+}
+
+/// Puts back the files of change set `id`, or only `only` when given, all of
+/// them or none. The current state is backed up first, so a restore can be
+/// undone the same way. Change sets of the helper have to be restored as root.
+pub fn restore(id: &str, only: Option<&Path>) -> Result<Vec<PathBuf>> {
+    let set = list()
+        .into_iter()
+        .find(|set| set.id == id)
+        .with_context(|| format!("no backup change set '{id}'"))?;
+    if set.system && !running_as_root() {
+        anyhow::bail!("change set '{id}' holds system files; restore it through kaede-helper");
+    }
+    let files = set
+        .files
+        .iter()
+        .filter(|file| only.is_none_or(|path| file.original == path))
+        .collect::<Vec<_>>();
+    if files.is_empty() {
+        anyhow::bail!(
+            "change set '{id}' has no backup of {}",
+            only.map(|p| p.display().to_string()).unwrap_or_default()
+        );
+    }
+
+    let _guard = begin(&format!("Before restoring {}", set.description));
+    let dir = set_dir(&set);
+    let mut tx = Transaction::new();
+    for file in &files {
+        match &file.stored {
+            Some(stored) => {
+                let content = fs::read(dir.join(stored)).with_context(|| {
+                    format!("failed to read the backup of {}", file.original.display())
+                })?;
+                match file.mode {
+                    Some(mode) => tx.write_with_mode(&file.original, content, mode & 0o7777),
+                    None => tx.write(&file.original, content),
+                }
+            }
+            None => tx.remove(&file.original),
+        }
+    }
+    // The transaction snapshots every file it changes into the guard's change set.
+    tx.commit()?;
+
+    let restored = files
+        .iter()
+        .map(|file| file.original.clone())
+        .collect::<Vec<_>>();
+    for path in &restored {
+        info!(path = %path.display(), change_set = %set.id, "file restored from backup");
+    }
+    Ok(restored)
+}
+
+/// Turns a `<name>.kaede.bak` left by an older Kaede into the oldest change set
+/// of `path`, so it is kept like any first backup. The file itself stays.
+fn import_legacy_backup(path: &Path) -> Result<()> {
+    let legacy = path.with_file_name(format!("{}{LEGACY_SUFFIX}", file_name(path)));
+    let Ok(metadata) = fs::metadata(&legacy) else {
+        return Ok(());
+    };
+    if list()
+        .iter()
+        .any(|set| set.files.iter().any(|file| file.original == path))
+    {
+        return Ok(());
+    }
+
+    let written = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    let mut set = change_set_at(
+        &format!("Original {} from before backups", file_name(path)),
+        written,
+    );
+    let dir = set_dir(&set);
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("failed to create backup directory {}", dir.display()))?;
+    let stored = format!("000-{}", file_name(path));
+    fs::copy(&legacy, dir.join(&stored))
+        .with_context(|| format!("failed to import {}", legacy.display()))?;
+    set.files.push(BackupFile {
+        original: path.to_path_buf(),
+        stored: Some(stored),
+        mode: None,
+    });
+    write_manifest(&dir, &set)?;
+    info!(backup = %legacy.display(), change_set = %set.id, "legacy backup imported");
+    Ok(())
+}
+
+fn new_change_set(description: &str) -> ChangeSet {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    change_set_at(description, now)
+}
+
+fn change_set_at(description: &str, time: Duration) -> ChangeSet {
+    // Sortable, and unique within a process even for back-to-back change sets.
+    let id = format!("{:020}-{}", time.as_nanos(), std::process::id());
+    ChangeSet {
+        id,
+        created: time.as_secs(),
+        description: description.to_string(),
+        files: Vec::new(),
+        system: running_as_root(),
+    }
+}
+
+fn write_manifest(dir: &Path, set: &ChangeSet) -> Result<()> {
+    let body = serde_json::to_string_pretty(set).context("failed to serialize backup manifest")?;
+    fs::write(dir.join(MANIFEST), body)
+        .with_context(|| format!("failed to write backup manifest in {}", dir.display()))
+}
+
+fn prune() {
+    let cutoff = SystemTime::now()
+        .checked_sub(Duration::from_secs(KEEP_DAYS * 24 * 60 * 60))
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    // Only the change sets this process could have written.
+    let sets = list()
+        .into_iter()
+        .filter(|set| set.system == running_as_root())
+        .collect::<Vec<_>>();
+    let mut seen = HashSet::new();
+    let mut pinned = HashSet::new();
+    for set in sets.iter().rev() {
+        for file in &set.files {
+            if seen.insert(&file.original) {
+                pinned.insert(&set.id);
+            }
+        }
+    }
+
+    let prunable = sets.iter().filter(|set| !pinned.contains(&set.id));
+    for (index, set) in prunable.enumerate() {
+        let expired = index >= KEEP_CHANGE_SETS || (index >= KEEP_ALWAYS && set.created < cutoff);
+        if !expired {
+            continue;
+        }
+        let dir = set_dir(set);
+        match fs::remove_dir_all(&dir) {
+            Ok(()) => debug!(change_set = %set.id, "old backup change set pruned"),
+            Err(err) => warn!(path = %dir.display(), error = %err, "failed to prune backup"),
+        }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "file".to_string())
+}
+
+fn set_dir(set: &ChangeSet) -> PathBuf {
+    backups_dir(set.system).join(&set.id)
+}
+
+fn backups_dir(system: bool) -> PathBuf {
+    // Keeps tests away from the real backups, which pruning could remove.
+    if cfg!(test) {
+        return std::env::temp_dir()
+            .join(format!("kaede-test-{}", std::process::id()))
+            .join(if system { "system" } else { "user" });
+    }
+    if system {
+        return PathBuf::from(SYSTEM_BACKUPS_DIR);
+    }
+    let base = std::env::var("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string()))
+                .join(".local/state")
+        });
+    base.join("kaede").join("backups")
+}
+
+fn running_as_root() -> bool {
+    fs::metadata("/proc/self").is_ok_and(|metadata| metadata.uid() == 0)
+}
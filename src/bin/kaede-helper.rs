@@ -0,0 +1,74 @@
+//! Privileged helper for NVIDIA mode switching. The GUI starts it through
+//! `pkexec`; it only accepts the commands below, and validates every value.
+
+use kaede::{backup, nvidia};
+
+const USAGE: &str = "Usage: kaede-helper COMMAND
+
+Commands:
+  switch <integrated|hybrid|nvidia>  Switch the NVIDIA graphics mode
+         [--display-manager <gdm|gdm3|sddm|lightdm|greetd|ly|lxdm|cosmic-greeter>]
+         [--force-composition] [--coolbits <0-255>] [--rtd3 <0-3>] [--nvidia-current]
+  reset                              Remove every NVIDIA file Kaede manages
+  reset-sddm                         Restore a minimal SDDM Xsetup script
+  set-initramfs-command [<COMMAND>]  Rebuild the initramfs with COMMAND, given as one
+                                     argument, or with the detected tool when omitted
+  restore <ID> [--file <PATH>]       Restore a backup change set the helper made";
+
+fn main() {
+    let _ = tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_target(false)
+        .with_ansi(false)
+        .without_time()
+        .compact()
+        .try_init();
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let result = match args.split_first() {
+        Some((command, rest)) if command == "switch" => {
+            nvidia::NvidiaSwitchConfig::from_args(rest)
+                .and_then(|config| nvidia::switch_graphics_mode(&config, &mut print_line))
+        }
+        Some((command, [])) if command == "reset" => nvidia::reset_all(&mut print_line),
+        Some((command, [])) if command == "reset-sddm" => nvidia::reset_sddm(),
+        Some((command, [])) if command == "set-initramfs-command" => {
+            nvidia::set_custom_command(None)
+        }
+        Some((command, [initramfs])) if command == "set-initramfs-command" => {
+            nvidia::set_custom_command(Some(initramfs))
+        }
+        Some((command, rest)) if command == "restore" => restore(rest),
+        _ => {
+            eprintln!("{USAGE}");
+            Err(format!("unexpected arguments {args:?}"))
+        }
+    };
+
+    if let Err(err) = result {
+        eprintln!("kaede-helper: {err}");
+        std::process::exit(1);
+    }
+}
+
+/// Only change sets in the helper's own backup directory can be named, and
+/// only files their manifest lists.
+fn restore(args: &[String]) -> Result<(), String> {
+    let (id, only) = match args {
+        [id] => (id, None),
+        [id, flag, path] if flag == "--file" => (id, Some(std::path::Path::new(path))),
+        _ => return Err(format!("unexpected arguments {args:?}")),
+    };
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit() || c == '-') {
+        return Err(format!("invalid change set ID '{id}'"));
+    }
+    for path in backup::restore(id, only).map_err(|e| format!("{e:#}"))? {
+        print_line(&format!("Restored {}", path.display()));
+    }
+    Ok(())
+}
+
+/// The GUI reads the helper's output to show the initramfs rebuild as it runs.
+fn print_line(line: &str) {
+    println!("{line}");
+}
@@ -0,0 +1,228 @@
+use crate::backup;
+use crate::desktop::{data_home, parse_exec_lossy, serialize_exec};
+use crate::launcher::{self, MANAGED_ENV};
+use crate::models::{DesktopApp, OverrideState};
+use crate::yaml;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+const FLATPAK_ID: &str = "com.usebottles.bottles";
+const ENVIRONMENT: [&str; 1] = ["Environment_Variables"];
+
+/// Every bottle plus the programs registered in it, as `bottles:<bottle>` and
+/// `bottles:<bottle>:<program>`. A bottle is identified by its directory name in
+/// `bottle_name`; programs also carry `bottle_program`. A program that already
+/// has a launcher in `known` (Bottles' "Add to menu") takes over that launcher's
+/// desktop ID and file instead.
+pub fn scan_bottles(known: &[DesktopApp]) -> Vec<DesktopApp> {
+    let mut apps = Vec::new();
+    for (root, flatpak) in bottle_roots() {
+        let Ok(entries) = fs::read_dir(&root) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let config = entry.path().join("bottle.yml");
+            let Ok(content) = fs::read_to_string(&config) else {
+                continue;
+            };
+            let dir = entry.file_name().to_string_lossy().to_string();
+            let name = yaml::get(&content, &["Name"])
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| dir.clone());
+
+            apps.push(bottles_app(
+                format!("bottles:{dir}"),
+                &config,
+                name.clone(),
+                launch_command(flatpak, &["bottles", "-b", &name]),
+                &dir,
+                None,
+            ));
+
+            for program_id in yaml::keys(&content, &["External_Programs"]) {
+                let Some(program) = yaml::get(&content, &["External_Programs", &program_id, "name"])
+                    .filter(|program| !program.is_empty())
+                else {
+                    continue;
+                };
+                let desktop_id = format!("bottles:{dir}:{program_id}");
+                let generated = generated_launcher_id(&desktop_id);
+                let target = Some((name.clone(), program.clone()));
+                let launcher = known.iter().find(|app| {
+                    app.desktop_id != generated && program_from_exec(&app.exec) == target
+                });
+
+                let app = bottles_app(
+                    desktop_id,
+                    &config,
+                    format!("{program} ({name})"),
+                    launch_command(
+                        flatpak,
+                        &["bottles-cli", "run", "-b", &name, "-p", &program],
+                    ),
+                    &dir,
+                    Some(program),
+                );
+                apps.push(match launcher {
+                    Some(launcher) => DesktopApp {
+                        desktop_id: launcher.desktop_id.clone(),
+                        path: launcher.path.clone(),
+                        icon: launcher.icon.clone().or(app.icon.clone()),
+                        actions: launcher.actions.clone(),
+                        ..app
+                    },
+                    None => app,
+                });
+            }
+        }
+    }
+    apps
+}
+
+fn bottles_app(
+    desktop_id: String,
+    config: &Path,
+    name: String,
+    exec: String,
+    bottle: &str,
+    program: Option<String>,
+) -> DesktopApp {
+    DesktopApp {
+        desktop_id,
+        path: config.to_path_buf(),
+        name,
+        icon: Some(FLATPAK_ID.to_string()),
+        exec,
+        is_steam_game: false,
+        steam_app_id: None,
+        steam_shortcut_id: None,
+        is_heroic_game: false,
+        heroic_platform: None,
+        heroic_app_name: None,
+        is_lutris_game: false,
+        lutris_slug: None,
+        is_bottles_app: true,
+        bottle_name: Some(bottle.to_string()),
+        bottle_program: program,
+        is_flatpak: false,
+        flatpak_app_id: None,
+        is_snap: false,
+        snap_name: None,
+        override_source: None,
+        override_stale: false,
+        actions: Vec::new(),
+    }
+}
+
+/// `bottles-cli` from Flatpak Bottles runs as `flatpak run --command=bottles-cli`.
+fn launch_command(flatpak: bool, args: &[&str]) -> String {
+    let mut command = Vec::new();
+    if flatpak {
+        command.push("flatpak".to_string());
+        command.push("run".to_string());
+        if args[0] != "bottles" {
+            command.push(format!("--command={}", args[0]));
+        }
+        command.push(FLATPAK_ID.to_string());
+    } else {
+        command.push(args[0].to_string());
+    }
+    command.extend(args[1..].iter().map(|arg| arg.to_string()));
+    serialize_exec(&command)
+}
+
+/// `(bottle, program)` named by a `bottles-cli run -b <bottle> -p <program>` command.
+pub fn program_from_exec(exec: &str) -> Option<(String, String)> {
+    let args = parse_exec_lossy(exec);
+    let cli = args
+        .iter()
+        .position(|arg| arg.ends_with("bottles-cli") || arg == "--command=bottles-cli")?;
+    let run = cli + args[cli..].iter().position(|arg| arg == "run")?;
+
+    let mut bottle = None;
+    let mut program = None;
+    let mut rest = args[run + 1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-b" | "--bottle" => bottle = rest.next().cloned(),
+            "-p" | "--program" => program = rest.next().cloned(),
+            "--" => break,
+            _ => {}
+        }
+    }
+    Some((bottle?, program?))
+}
+
+/// Whether `app` is a program launcher already covered by `bottles_apps`:
+/// one a program took over, or one Kaede wrote for a program.
+pub fn is_program_launcher(app: &DesktopApp, bottles_apps: &[DesktopApp]) -> bool {
+    bottles_apps.iter().any(|known| known.desktop_id == app.desktop_id)
+        || (app.desktop_id.starts_with("bottles-") && program_from_exec(&app.exec).is_some())
+}
+
+/// Whether the program runs from a launcher file rather than only from `bottle.yml`.
+pub fn has_launcher(app: &DesktopApp) -> bool {
+    app.path.extension().is_some_and(|ext| ext == "desktop")
+}
+
+/// Desktop ID of the launcher Kaede writes for a program without one,
+/// e.g. `bottles-Gaming-notepad.desktop`.
+pub fn generated_launcher_id(desktop_id: &str) -> String {
+    format!("{}.desktop", sanitize(&desktop_id.replace(':', "-")))
+}
+
+pub fn apply_bottle_env(bottle: &str, env_vars: &[String]) -> Result<()> {
+    let path = find_bottle_config(bottle)
+        .with_context(|| format!("Bottles config not found for bottle {bottle}"))?;
+    let raw = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read Bottles config {}", path.display()))?;
+    let desired = launcher::managed_env_entries(env_vars);
+    let updated = yaml::set_entries(&raw, &ENVIRONMENT, &MANAGED_ENV, &desired)
+        .with_context(|| format!("failed to update Bottles config {}", path.display()))?;
+    if updated == raw {
+        info!(path = %path.display(), bottle = bottle, "Bottles env already in desired state");
+        return Ok(());
+    }
+
+    backup::snapshot(&path)?;
+    fs::write(&path, updated)
+        .with_context(|| format!("failed to write Bottles config {}", path.display()))?;
+    info!(path = %path.display(), bottle = bottle, "Bottles env updated");
+    Ok(())
+}
+
+pub fn read_override_state(bottle: &str, env_vars: &[String]) -> Result<OverrideState> {
+    let path = find_bottle_config(bottle)
+        .with_context(|| format!("Bottles config not found for bottle {bottle}"))?;
+    let raw = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read Bottles config {}", path.display()))?;
+    Ok(launcher::env_override_state(&yaml::entries(&raw, &ENVIRONMENT), env_vars))
+}
+
+/// Bottle directories with whether they belong to Flatpak Bottles.
+fn bottle_roots() -> Vec<(PathBuf, bool)> {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    vec![
+        (data_home().join("bottles/bottles"), false),
+        (
+            PathBuf::from(&home).join(".var/app/com.usebottles.bottles/data/bottles/bottles"),
+            true,
+        ),
+    ]
+}
+
+fn find_bottle_config(bottle: &str) -> Option<PathBuf> {
+    bottle_roots()
+        .into_iter()
+        .map(|(root, _)| root.join(bottle).join("bottle.yml"))
+        .find(|path| path.is_file())
+}
+
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect()
+}
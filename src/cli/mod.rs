@@ -0,0 +1,693 @@
+use crate::backup;
+use crate::config::ConfigStore;
+use crate::datetime;
+use crate::desktop::scan_desktop_entries;
+use crate::gpu::{detect_gpus, gpu_for_choice};
+use crate::launcher::apply_launcher_override;
+use crate::models::{DesktopApp, GpuChoice, GpuInfo, SteamAccountScope};
+use crate::nvidia::{self, LineKind, NvidiaSwitchConfig};
+use crate::reconcile::{adopt, apply_pending_steam_changes, check_assignments, reapply, DriftStatus};
+use crate::steam::{is_steam_running, parse_account_id, steam_accounts, SteamAccount};
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::time::Duration;
+use tracing::info;
+
+const STEAM_WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+const USAGE: &str = "Usage: kaede [COMMAND]
+
+Starts the graphical interface when no command is given.
+
+Commands:
+  list-gpus                        List detected GPUs with their indexes and PCI slots
+  list-apps [--source <SOURCE>]    List applications (SOURCE: steam, heroic, lutris, bottles, flatpak, snap, native)
+            [--assigned]           Only list applications with a GPU assignment
+  list-steam-accounts              List Steam accounts found in userdata/
+  set <DESKTOP_ID> --gpu <GPU>     Assign an application to a GPU (index or PCI slot)
+      [--action <ACTION>]          Only assign one desktop action (e.g. new-private-window)
+      [--steam-account <ACCOUNT>]  Steam games: account ID, login name, `all` or `recent`
+                                   (repeatable; defaults to the most recent login)
+  unset <DESKTOP_ID>               Restore the default GPU for an application
+        [--action <ACTION>]        Make a desktop action follow the application again
+  check [--reapply | --adopt]      Compare assignments with launcher files on disk;
+                                   re-apply the config or adopt the on-disk state
+  watch-steam                      Keep running and write Steam changes queued while
+                                   Steam was running once it exits
+  backups [<ID>]                   List backup change sets, or the files in one
+  restore <ID> [--file <PATH>]     Restore the files of a change set, or only PATH
+  nvidia-mode [<MODE>]             Show the configured and active NVIDIA graphics mode,
+                                   or switch to MODE
+                                   (integrated, hybrid, nvidia; asks for authentication)
+      [--display-manager <DM>]     gdm, gdm3, sddm, lightdm, greetd, ly, lxdm or
+                                   cosmic-greeter (detected by default)
+      [--force-composition]        Enable ForceCompositionPipeline (nvidia mode)
+      [--coolbits <0-255>]         Set Coolbits (nvidia mode)
+      [--rtd3 <0-3>]               Set RTD3 power management (hybrid mode)
+      [--nvidia-current]           Use nvidia-current* module names
+      [--dry-run]                  Show the files and commands without changing anything
+  initramfs-command [<CMD> | --clear]
+                                   Show or set the command that rebuilds the initramfs
+                                   after a mode switch (asks for authentication)
+  help                             Show this help";
+
+/// Runs a command-line subcommand. Returns `None` when the GUI should start instead.
+pub fn run(args: &[String]) -> Option<i32> {
+    let command = args.first()?;
+    let rest = &args[1..];
+
+    let result = match command.as_str() {
+        "list-gpus" => list_gpus(rest),
+        "list-apps" => list_apps(rest),
+        "list-steam-accounts" => list_steam_accounts(rest),
+        "set" => set(rest),
+        "unset" => unset(rest),
+        "check" => check(rest),
+        "watch-steam" => watch_steam(rest),
+        "backups" => backups(rest),
+        "restore" => restore(rest),
+        "nvidia-mode" => nvidia_mode(rest),
+        "initramfs-command" => initramfs_command(rest),
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        // Leave GApplication options (e.g. --gapplication-service) to GTK.
+        other if other.starts_with('-') => return None,
+        other => Err(anyhow::anyhow!("unknown command '{other}'\n\n{USAGE}")),
+    };
+
+    match result {
+        Ok(()) => Some(0),
+        Err(err) => {
+            eprintln!("kaede: {err:#}");
+            Some(1)
+        }
+    }
+}
+
+fn list_gpus(args: &[String]) -> Result<()> {
+    if let Some(arg) = args.first() {
+        anyhow::bail!("unexpected argument '{arg}'");
+    }
+
+    let gpus = detect_gpus();
+    if gpus.is_empty() {
+        println!("No GPUs detected");
+        return Ok(());
+    }
+
+    let rows = gpus
+        .iter()
+        .map(|gpu| {
+            vec![
+                gpu.dri_prime_index
+                    .map(|i| i.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+                gpu.card.clone(),
+                gpu.driver.clone().unwrap_or_else(|| "-".to_string()),
+                gpu.pci_slot.clone().unwrap_or_else(|| "-".to_string()),
+                gpu_display_name(gpu),
+            ]
+        })
+        .collect::<Vec<_>>();
+    print_table(&["INDEX", "CARD", "DRIVER", "PCI", "NAME"], &rows);
+    Ok(())
+}
+
+fn list_apps(args: &[String]) -> Result<()> {
+    let mut source: Option<String> = None;
+    let mut assigned_only = false;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--source" => {
+                let value = iter.next().context("--source requires a value")?;
+                source = Some(parse_source(value)?);
+            }
+            "--assigned" => assigned_only = true,
+            other => match other.strip_prefix("--source=") {
+                Some(value) => source = Some(parse_source(value)?),
+                None => anyhow::bail!("unexpected argument '{other}'"),
+            },
+        }
+    }
+
+    let config = ConfigStore::load();
+    let mut apps = scan_desktop_entries(config.extra_scan_dirs());
+    apps.sort_by_key(|a| a.name.to_lowercase());
+
+    let rows = apps
+        .iter()
+        .filter(|app| source.as_deref().is_none_or(|s| app_source(app) == s))
+        .filter_map(|app| {
+            let choice = config.get_choice(&app.desktop_id);
+            if assigned_only && choice == GpuChoice::Default {
+                return None;
+            }
+            Some(vec![
+                app.desktop_id.clone(),
+                app_source(app).to_string(),
+                choice.label(),
+                app.name.clone(),
+            ])
+        })
+        .collect::<Vec<_>>();
+
+    print_table(&["DESKTOP ID", "SOURCE", "GPU", "NAME"], &rows);
+    Ok(())
+}
+
+fn list_steam_accounts(args: &[String]) -> Result<()> {
+    if let Some(arg) = args.first() {
+        anyhow::bail!("unexpected argument '{arg}'");
+    }
+
+    let accounts = steam_accounts();
+    if accounts.is_empty() {
+        println!("No Steam accounts found");
+        return Ok(());
+    }
+
+    let rows = accounts
+        .iter()
+        .map(|account| {
+            vec![
+                account.account_id.to_string(),
+                account.account_name.clone().unwrap_or_else(|| "-".to_string()),
+                if account.most_recent { "yes" } else { "" }.to_string(),
+                account.persona_name.clone().unwrap_or_else(|| "-".to_string()),
+            ]
+        })
+        .collect::<Vec<_>>();
+    print_table(&["ACCOUNT ID", "LOGIN", "RECENT", "PERSONA"], &rows);
+    Ok(())
+}
+
+fn set(args: &[String]) -> Result<()> {
+    let mut desktop_id: Option<&str> = None;
+    let mut gpu: Option<&str> = None;
+    let mut action: Option<&str> = None;
+    let mut accounts: Vec<&str> = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--gpu" => gpu = Some(iter.next().context("--gpu requires a value")?),
+            "--action" => action = Some(iter.next().context("--action requires a value")?),
+            "--steam-account" => {
+                accounts.push(iter.next().context("--steam-account requires a value")?)
+            }
+            other => {
+                if let Some(value) = other.strip_prefix("--gpu=") {
+                    gpu = Some(value);
+                } else if let Some(value) = other.strip_prefix("--action=") {
+                    action = Some(value);
+                } else if let Some(value) = other.strip_prefix("--steam-account=") {
+                    accounts.push(value);
+                } else if other.starts_with('-') || desktop_id.is_some() {
+                    anyhow::bail!("unexpected argument '{other}'");
+                } else {
+                    desktop_id = Some(other);
+                }
+            }
+        }
+    }
+
+    let desktop_id = desktop_id.context("missing <DESKTOP_ID>; see `kaede list-apps`")?;
+    let gpu = gpu.context("missing --gpu <GPU>; see `kaede list-gpus`")?;
+    let scope = if accounts.is_empty() {
+        None
+    } else {
+        Some(parse_account_scope(&accounts, &steam_accounts())?)
+    };
+
+    assign(desktop_id, Some(gpu), action, scope)
+}
+
+fn parse_account_scope(values: &[&str], accounts: &[SteamAccount]) -> Result<SteamAccountScope> {
+    match values {
+        ["all"] => return Ok(SteamAccountScope::All),
+        ["recent"] => return Ok(SteamAccountScope::MostRecent),
+        _ => {}
+    }
+
+    let mut ids = Vec::new();
+    for value in values {
+        let account = accounts
+            .iter()
+            .find(|a| {
+                parse_account_id(value) == Some(a.account_id)
+                    || a.account_name.as_deref() == Some(*value)
+            })
+            .with_context(|| {
+                format!("Steam account '{value}' not found; see `kaede list-steam-accounts`")
+            })?;
+        if !ids.contains(&account.account_id) {
+            ids.push(account.account_id);
+        }
+    }
+    Ok(SteamAccountScope::Accounts(ids))
+}
+
+fn unset(args: &[String]) -> Result<()> {
+    let mut desktop_id: Option<&str> = None;
+    let mut action: Option<&str> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--action" => action = Some(iter.next().context("--action requires a value")?),
+            other => {
+                if let Some(value) = other.strip_prefix("--action=") {
+                    action = Some(value);
+                } else if other.starts_with('-') || desktop_id.is_some() {
+                    anyhow::bail!("unexpected argument '{other}'");
+                } else {
+                    desktop_id = Some(other);
+                }
+            }
+        }
+    }
+
+    let desktop_id = desktop_id.context("missing <DESKTOP_ID>; see `kaede list-apps`")?;
+    assign(desktop_id, None, action, None)
+}
+
+fn check(args: &[String]) -> Result<()> {
+    let mut do_reapply = false;
+    let mut do_adopt = false;
+    for arg in args {
+        match arg.as_str() {
+            "--reapply" => do_reapply = true,
+            "--adopt" => do_adopt = true,
+            other => anyhow::bail!("unexpected argument '{other}'"),
+        }
+    }
+    if do_reapply && do_adopt {
+        anyhow::bail!("--reapply and --adopt cannot be combined");
+    }
+
+    let gpus = detect_gpus();
+    let mut config = ConfigStore::load();
+    let apps = scan_desktop_entries(config.extra_scan_dirs());
+    config.migrate_gpu_assignments(&gpus)?;
+
+    let reports = check_assignments(&config, &apps, &gpus);
+    let drifted = reports
+        .iter()
+        .filter(|r| r.needs_attention())
+        .collect::<Vec<_>>();
+    if drifted.is_empty() {
+        println!("All {} assignments match the launcher state on disk", reports.len());
+        return Ok(());
+    }
+
+    let rows = drifted
+        .iter()
+        .map(|r| vec![r.app.desktop_id.clone(), r.describe(&gpus)])
+        .collect::<Vec<_>>();
+    print_table(&["DESKTOP ID", "STATUS"], &rows);
+
+    if !do_reapply && !do_adopt {
+        anyhow::bail!(
+            "{} assignments drifted; run `kaede check --reapply` or `kaede check --adopt`",
+            drifted.len()
+        );
+    }
+
+    let mut failures = 0;
+    for report in &drifted {
+        let result = if do_reapply {
+            match report.status {
+                DriftStatus::GpuMissing => continue,
+                _ => reapply(report, &gpus, config.use_env_wrapper()),
+            }
+        } else {
+            match report.status {
+                DriftStatus::Drifted { .. } => adopt(report, &gpus, &mut config),
+                _ => continue,
+            }
+        };
+        if let Err(err) = result {
+            eprintln!("kaede: {}: {err:#}", report.app.desktop_id);
+            failures += 1;
+        }
+    }
+    if do_adopt {
+        config.save()?;
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{failures} assignments could not be reconciled");
+    }
+    Ok(())
+}
+
+fn watch_steam(args: &[String]) -> Result<()> {
+    if let Some(arg) = args.first() {
+        anyhow::bail!("unexpected argument '{arg}'");
+    }
+
+    println!("Waiting for Steam to close to write queued changes (Ctrl+C to stop)");
+    loop {
+        // Reload every time: the GUI or `kaede set` may have queued more changes.
+        let mut config = ConfigStore::load();
+        if !config.pending_steam_changes().is_empty() && !is_steam_running() {
+            let gpus = detect_gpus();
+            let apps = scan_desktop_entries(config.extra_scan_dirs());
+            let queued = config.pending_steam_changes().len();
+            if let Some(applied) = apply_pending_steam_changes(&mut config, &apps, &gpus) {
+                config.save()?;
+                println!("Steam closed; wrote {applied} of {queued} queued Steam changes");
+            }
+        }
+        std::thread::sleep(STEAM_WATCH_INTERVAL);
+    }
+}
+
+fn backups(args: &[String]) -> Result<()> {
+    let id = match args {
+        [] => None,
+        [id] => Some(id.as_str()),
+        [_, extra, ..] => anyhow::bail!("unexpected argument '{extra}'"),
+    };
+
+    let sets = backup::list();
+    let Some(id) = id else {
+        if sets.is_empty() {
+            println!("No backups yet");
+            return Ok(());
+        }
+        let rows = sets
+            .iter()
+            .map(|set| {
+                vec![
+                    set.id.clone(),
+                    datetime::local_label(set.created),
+                    set.files.len().to_string(),
+                    set.description.clone(),
+                ]
+            })
+            .collect::<Vec<_>>();
+        print_table(&["ID", "DATE", "FILES", "DESCRIPTION"], &rows);
+        return Ok(());
+    };
+
+    let set = sets
+        .iter()
+        .find(|set| set.id == id)
+        .with_context(|| format!("backup '{id}' not found; see `kaede backups`"))?;
+    println!("{} ({})", set.description, datetime::local_label(set.created));
+    let rows = set
+        .files
+        .iter()
+        .map(|file| {
+            let state = if file.stored.is_some() { "saved" } else { "absent" };
+            vec![state.to_string(), file.original.display().to_string()]
+        })
+        .collect::<Vec<_>>();
+    print_table(&["BEFORE", "PATH"], &rows);
+    Ok(())
+}
+
+fn restore(args: &[String]) -> Result<()> {
+    let mut id: Option<&str> = None;
+    let mut file: Option<&str> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--file" => file = Some(iter.next().context("--file requires a value")?),
+            other => {
+                if let Some(value) = other.strip_prefix("--file=") {
+                    file = Some(value);
+                } else if other.starts_with('-') || id.is_some() {
+                    anyhow::bail!("unexpected argument '{other}'");
+                } else {
+                    id = Some(other);
+                }
+            }
+        }
+    }
+
+    let id = id.context("missing <ID>; see `kaede backups`")?;
+    // The helper's change sets hold root-owned files.
+    if backup::list().iter().any(|set| set.id == id && set.system) {
+        let only = file.map(Path::new);
+        return nvidia::helper::restore_backup(id, only, &mut |line| println!("{line}"))
+            .map_err(anyhow::Error::msg);
+    }
+    for path in backup::restore(id, file.map(Path::new))? {
+        println!("Restored {}", path.display());
+    }
+    Ok(())
+}
+
+fn nvidia_mode(args: &[String]) -> Result<()> {
+    if args.is_empty() {
+        let status = nvidia::get_status();
+        println!("{}", status.summary());
+        if !status.kernel_params.is_empty() {
+            println!("kernel parameters: {}", status.kernel_params.join(" "));
+        }
+        for warning in status.warnings() {
+            println!("warning: {warning}");
+        }
+        return Ok(());
+    }
+
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    let args = args
+        .iter()
+        .filter(|arg| *arg != "--dry-run")
+        .cloned()
+        .collect::<Vec<_>>();
+    let config = NvidiaSwitchConfig::from_args(&args).map_err(anyhow::Error::msg)?;
+
+    if dry_run {
+        let plan = nvidia::plan_graphics_mode(&config).map_err(anyhow::Error::msg)?;
+        if plan.is_empty() {
+            println!("The {} mode configuration is already in place", config.mode.as_str());
+            return Ok(());
+        }
+        for (kind, line) in plan.lines() {
+            match kind {
+                LineKind::Heading => println!("=== {line}"),
+                LineKind::Added => println!("+{line}"),
+                LineKind::Removed => println!("-{line}"),
+                LineKind::Unchanged => println!(" {line}"),
+                LineKind::Command => println!("$ {line}"),
+                LineKind::Note => println!("# {line}"),
+            }
+        }
+        return Ok(());
+    }
+
+    nvidia::helper::switch_graphics_mode(&config, &mut |line| println!("{line}"))
+        .map_err(anyhow::Error::msg)?;
+    println!("Switched to {} mode; reboot to apply", config.mode.as_str());
+    Ok(())
+}
+
+fn initramfs_command(args: &[String]) -> Result<()> {
+    let command = match args {
+        [] => {
+            match nvidia::custom_command() {
+                Some(command) => println!("{command}"),
+                None => match nvidia::detect_backend() {
+                    Some(backend) => println!("detected: {}", backend.label()),
+                    None => println!("No initramfs tool detected"),
+                },
+            }
+            return Ok(());
+        }
+        [flag] if flag == "--clear" => None,
+        // Unquoted arguments are joined back into one command.
+        args => Some(args.join(" ")),
+    };
+
+    if let Some(command) = &command {
+        nvidia::validate_custom_command(command).map_err(anyhow::Error::msg)?;
+    }
+    nvidia::helper::set_initramfs_command(command.as_deref(), &mut |line| println!("{line}"))
+        .map_err(anyhow::Error::msg)?;
+    match command {
+        Some(command) => println!("The initramfs is now rebuilt with {command}"),
+        None => println!("The initramfs is now rebuilt with the detected tool"),
+    }
+    Ok(())
+}
+
+fn assign(
+    desktop_id: &str,
+    gpu: Option<&str>,
+    action: Option<&str>,
+    steam_scope: Option<SteamAccountScope>,
+) -> Result<()> {
+    let mut config = ConfigStore::load();
+    let app = find_app(&config, desktop_id)?;
+    if steam_scope.is_some() && !app.is_steam_game {
+        anyhow::bail!("--steam-account only applies to Steam games");
+    }
+    if let Some(action) = action.filter(|id| !app.actions.iter().any(|a| a.id == *id)) {
+        let known = app
+            .actions
+            .iter()
+            .map(|a| a.id.as_str())
+            .collect::<Vec<_>>();
+        anyhow::bail!(
+            "{} has no desktop action '{action}' (available: {})",
+            app.desktop_id,
+            if known.is_empty() { "none".to_string() } else { known.join(", ") }
+        );
+    }
+    let gpus = detect_gpus();
+    let choice = match gpu {
+        Some(selector) => GpuChoice::Gpu(find_gpu_arg(&gpus, selector)?.stable_id()),
+        None => GpuChoice::Default,
+    };
+    let selected_gpu = gpu_for_choice(&gpus, &choice);
+
+    config.migrate_gpu_assignments(&gpus)?;
+    match action {
+        Some(action) => {
+            let action_choice = gpu.map(|_| choice.clone());
+            config.set_action_choice(&app.desktop_id, action, action_choice);
+        }
+        None => config.set_choice(&app.desktop_id, choice.clone()),
+    }
+    if let Some(scope) = steam_scope {
+        config.set_steam_account_scope(&app.desktop_id, scope);
+    }
+    // Steam rewrites localconfig.vdf on exit; write the change once it closes.
+    let steam_busy = app.is_steam_game && is_steam_running();
+    if steam_busy {
+        config.queue_steam_change(&app.desktop_id);
+    } else {
+        config.clear_steam_change(&app.desktop_id);
+    }
+    config.save()?;
+
+    info!(
+        app_name = %app.name,
+        desktop_id = %app.desktop_id,
+        action = ?action,
+        gpu_choice = %choice.label(),
+        "changing GPU assignment from command line"
+    );
+    let target = match (action, gpu) {
+        (Some(_), None) => "same as application".to_string(),
+        _ => selected_gpu
+            .map(gpu_display_name)
+            .unwrap_or_else(|| choice.label()),
+    };
+    let target = match action {
+        Some(action) => format!("{} [{action}] -> {target}", app.desktop_id),
+        None => format!("{} -> {target}", app.desktop_id),
+    };
+    if steam_busy {
+        println!("{target} (pending until Steam closes; keep Kaede open or run `kaede watch-steam`)");
+        return Ok(());
+    }
+
+    let app_choice = config.get_choice(&app.desktop_id);
+    apply_launcher_override(
+        &app,
+        &app_choice,
+        &config.action_choices(&app.desktop_id),
+        &config.steam_account_scope(&app.desktop_id),
+        gpu_for_choice(&gpus, &app_choice),
+        &gpus,
+        config.use_env_wrapper(),
+    )
+    .with_context(|| format!("failed to apply GPU assignment for {}", app.name))?;
+
+    println!("{target}");
+    Ok(())
+}
+
+fn find_app(config: &ConfigStore, desktop_id: &str) -> Result<DesktopApp> {
+    scan_desktop_entries(config.extra_scan_dirs())
+        .into_iter()
+        .find(|app| app.desktop_id == desktop_id)
+        .with_context(|| format!("application '{desktop_id}' not found; see `kaede list-apps`"))
+}
+
+/// Accepts either the index shown by `list-gpus` or a PCI slot such as `0000:01:00.0`.
+fn find_gpu_arg<'a>(gpus: &'a [GpuInfo], selector: &str) -> Result<&'a GpuInfo> {
+    let found = match selector.parse::<usize>() {
+        Ok(index) => gpus.iter().find(|g| g.dri_prime_index == Some(index)),
+        Err(_) => gpus.iter().find(|g| {
+            g.pci_slot.as_deref().is_some_and(|slot| {
+                slot == selector || slot.strip_prefix("0000:") == Some(selector)
+            })
+        }),
+    };
+
+    found.with_context(|| format!("GPU '{selector}' not found; see `kaede list-gpus`"))
+}
+
+fn parse_source(value: &str) -> Result<String> {
+    match value {
+        "steam" | "heroic" | "lutris" | "bottles" | "flatpak" | "snap" | "native" => Ok(value.to_string()),
+        other => anyhow::bail!(
+            "unknown source '{other}' (expected steam, heroic, lutris, bottles, flatpak, snap or native)"
+        ),
+    }
+}
+
+fn app_source(app: &DesktopApp) -> &'static str {
+    if app.is_steam_game {
+        "steam"
+    } else if app.is_heroic_game {
+        "heroic"
+    } else if app.is_lutris_game {
+        "lutris"
+    } else if app.is_bottles_app {
+        "bottles"
+    } else if app.is_flatpak {
+        "flatpak"
+    } else if app.is_snap {
+        "snap"
+    } else {
+        "native"
+    }
+}
+
+fn gpu_display_name(gpu: &GpuInfo) -> String {
+    let name = gpu.name_for_filter();
+    if name.is_empty() {
+        gpu.name.clone()
+    } else {
+        name
+    }
+}
+
+fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths = headers.iter().map(|h| h.len()).collect::<Vec<_>>();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header = headers.iter().map(|h| h.to_string()).collect::<Vec<_>>();
+    for row in std::iter::once(&header).chain(rows) {
+        let last = row.len().saturating_sub(1);
+        let line = row
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                if i == last {
+                    cell.clone()
+                } else {
+                    format!("{cell:<width$}", width = widths[i])
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{line}");
+    }
+}
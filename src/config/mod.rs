@@ -1,7 +1,10 @@
-use crate::models::{AppConfig, GpuChoice};
+use crate::gpu::migrate_assignments;
+use crate::models::{AppConfig, GpuChoice, GpuInfo, SteamAccountScope};
 use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::PathBuf;
+use tracing::info;
 
 #[derive(Debug, Clone)]
 pub struct ConfigStore {
@@ -28,10 +31,99 @@ impl ConfigStore {
             .unwrap_or_default()
     }
 
+    pub fn assignments(&self) -> &BTreeMap<String, GpuChoice> {
+        &self.data.assignments
+    }
+
     pub fn set_choice(&mut self, desktop_id: &str, choice: GpuChoice) {
         self.data.assignments.insert(desktop_id.to_string(), choice);
     }
 
+    pub fn action_choices(&self, desktop_id: &str) -> BTreeMap<String, GpuChoice> {
+        self.data
+            .action_assignments
+            .get(desktop_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn action_assignments(&self) -> &BTreeMap<String, BTreeMap<String, GpuChoice>> {
+        &self.data.action_assignments
+    }
+
+    /// `None` makes the action follow the application's assignment again.
+    pub fn set_action_choice(&mut self, desktop_id: &str, action: &str, choice: Option<GpuChoice>) {
+        match choice {
+            Some(choice) => {
+                self.data
+                    .action_assignments
+                    .entry(desktop_id.to_string())
+                    .or_default()
+                    .insert(action.to_string(), choice);
+            }
+            None => {
+                if let Some(actions) = self.data.action_assignments.get_mut(desktop_id) {
+                    actions.remove(action);
+                    if actions.is_empty() {
+                        self.data.action_assignments.remove(desktop_id);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn steam_account_scope(&self, desktop_id: &str) -> SteamAccountScope {
+        self.data
+            .steam_accounts
+            .get(desktop_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn set_steam_account_scope(&mut self, desktop_id: &str, scope: SteamAccountScope) {
+        if scope == SteamAccountScope::MostRecent {
+            self.data.steam_accounts.remove(desktop_id);
+        } else {
+            self.data
+                .steam_accounts
+                .insert(desktop_id.to_string(), scope);
+        }
+    }
+
+    pub fn pending_steam_changes(&self) -> &BTreeSet<String> {
+        &self.data.pending_steam_changes
+    }
+
+    pub fn is_steam_change_pending(&self, desktop_id: &str) -> bool {
+        self.data.pending_steam_changes.contains(desktop_id)
+    }
+
+    pub fn queue_steam_change(&mut self, desktop_id: &str) {
+        self.data
+            .pending_steam_changes
+            .insert(desktop_id.to_string());
+    }
+
+    pub fn clear_steam_change(&mut self, desktop_id: &str) {
+        self.data.pending_steam_changes.remove(desktop_id);
+    }
+
+    /// Maps saved assignments onto the detected GPUs, converting legacy
+    /// index-based entries. Saves the config when anything changed.
+    pub fn migrate_gpu_assignments(&mut self, gpus: &[GpuInfo]) -> Result<()> {
+        let choices = self.data.assignments.values_mut().chain(
+            self.data
+                .action_assignments
+                .values_mut()
+                .flat_map(|actions| actions.values_mut()),
+        );
+        if migrate_assignments(choices, gpus) {
+            info!("migrated saved GPU assignments to stable GPU ids");
+            self.save()?;
+        }
+        Ok(())
+    }
+
     pub fn save(&self) -> Result<()> {
         if let Some(parent) = self.path.parent() {
             fs::create_dir_all(parent).with_context(|| {
@@ -61,6 +153,14 @@ impl ConfigStore {
         self.data.show_heroic_apps = value;
     }
 
+    pub fn show_lutris_apps(&self) -> bool {
+        self.data.show_lutris_apps
+    }
+
+    pub fn set_show_lutris_apps(&mut self, value: bool) {
+        self.data.show_lutris_apps = value;
+    }
+
     pub fn show_flatpak_apps(&self) -> bool {
         self.data.show_flatpak_apps
     }
@@ -93,6 +193,22 @@ impl ConfigStore {
         self.data.check_updates_at_startup = value;
     }
 
+    pub fn regenerate_stale_overrides(&self) -> bool {
+        self.data.regenerate_stale_overrides
+    }
+
+    pub fn set_regenerate_stale_overrides(&mut self, value: bool) {
+        self.data.regenerate_stale_overrides = value;
+    }
+
+    pub fn extra_scan_dirs(&self) -> &[PathBuf] {
+        &self.data.extra_scan_dirs
+    }
+
+    pub fn set_extra_scan_dirs(&mut self, dirs: Vec<PathBuf>) {
+        self.data.extra_scan_dirs = dirs;
+    }
+
     pub fn reset(&mut self) -> Result<()> {
         self.data = AppConfig::default();
         self.save()
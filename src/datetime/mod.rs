@@ -0,0 +1,7 @@
+/// Local time of a Unix timestamp, e.g. `2024-05-01 14:03`.
+pub fn local_label(unix: u64) -> String {
+    glib::DateTime::from_unix_local(unix as i64)
+        .and_then(|time| time.format("%Y-%m-%d %H:%M"))
+        .map(|label| label.to_string())
+        .unwrap_or_else(|_| unix.to_string())
+}
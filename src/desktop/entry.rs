@@ -0,0 +1,674 @@
+use std::fmt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+pub const DESKTOP_ENTRY: &str = "Desktop Entry";
+
+/// Field codes that expand to arguments at launch time (including the deprecated ones).
+const FIELD_CODES: [&str; 14] = [
+    "%f", "%F", "%u", "%U", "%i", "%c", "%k", "%d", "%D", "%n", "%N", "%v", "%m", "%M",
+];
+
+/// Characters that force an Exec argument to be quoted.
+const RESERVED_EXEC_CHARS: &[char] = &[
+    ' ', '\t', '\n', '"', '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(', ')',
+    '`',
+];
+
+/// A parsed `.desktop` file. Every line of the original is kept so writing it
+/// back only changes the keys that were explicitly set or removed.
+#[derive(Debug, Clone, Default)]
+pub struct DesktopEntry {
+    lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone)]
+enum Line {
+    /// Blank lines, comments and anything we cannot parse, kept verbatim.
+    Other(String),
+    Group {
+        name: String,
+        /// The header as written, `None` for groups added by [`DesktopEntry::set`].
+        raw: Option<String>,
+    },
+    Entry {
+        key: String,
+        locale: Option<String>,
+        /// Raw value with escape sequences intact.
+        value: String,
+        /// The line as written, spacing around `=` included; dropped once the
+        /// value changes.
+        raw: Option<String>,
+    },
+}
+
+impl DesktopEntry {
+    pub fn parse(content: &str) -> Self {
+        let lines = content.lines().map(parse_line).collect();
+        Self { lines }
+    }
+
+    pub fn has_group(&self, group: &str) -> bool {
+        self.lines
+            .iter()
+            .any(|line| matches!(line, Line::Group { name, .. } if name == group))
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Group { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Unlocalized value of `key` in `group`, with escape sequences resolved.
+    pub fn get(&self, group: &str, key: &str) -> Option<String> {
+        self.raw(group, key, None).map(unescape_value)
+    }
+
+    /// Picks the best `key[locale]` match for `locales` (most specific first),
+    /// falling back to the unlocalized value.
+    pub fn get_localized(&self, group: &str, key: &str, locales: &[String]) -> Option<String> {
+        locales
+            .iter()
+            .find_map(|locale| self.raw(group, key, Some(locale)))
+            .or_else(|| self.raw(group, key, None))
+            .map(unescape_value)
+    }
+
+    pub fn get_bool(&self, group: &str, key: &str) -> bool {
+        self.raw(group, key, None)
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("true"))
+    }
+
+    /// Splits a `;`-separated list value, honouring `\;` escapes.
+    pub fn get_list(&self, group: &str, key: &str) -> Vec<String> {
+        let Some(raw) = self.raw(group, key, None) else {
+            return Vec::new();
+        };
+
+        let mut items = Vec::new();
+        let mut current = String::new();
+        let mut chars = raw.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    current.push(c);
+                    if let Some(next) = chars.next() {
+                        current.push(next);
+                    }
+                }
+                ';' => items.push(std::mem::take(&mut current)),
+                _ => current.push(c),
+            }
+        }
+        items.push(current);
+
+        items
+            .iter()
+            .map(|item| unescape_value(&item.replace("\\;", ";")))
+            .filter(|item| !item.is_empty())
+            .collect()
+    }
+
+    /// `OnlyShowIn`/`NotShowIn` for the session's `desktops`. Without a known
+    /// desktop `OnlyShowIn` cannot be evaluated, so only a `NotShowIn` match hides.
+    pub fn shown_in(&self, desktops: &[String]) -> bool {
+        if desktops.is_empty() {
+            return true;
+        }
+        let only_show_in = self.get_list(DESKTOP_ENTRY, "OnlyShowIn");
+        let not_show_in = self.get_list(DESKTOP_ENTRY, "NotShowIn");
+        (only_show_in.is_empty() || only_show_in.iter().any(|d| desktops.contains(d)))
+            && !not_show_in.iter().any(|d| desktops.contains(d))
+    }
+
+    /// Sets the unlocalized `key`, appending it to the end of `group` (and
+    /// creating the group) when it does not exist yet.
+    pub fn set(&mut self, group: &str, key: &str, value: &str) {
+        let escaped = escape_value(value);
+        let Some((start, end)) = self.group_range(group) else {
+            let entry = Line::Entry {
+                key: key.to_string(),
+                locale: None,
+                value: escaped,
+                raw: None,
+            };
+            if group == DESKTOP_ENTRY {
+                // The main group has to come first.
+                let header = Line::Group {
+                    name: group.to_string(),
+                    raw: None,
+                };
+                let mut head = vec![header, entry];
+                if !self.lines.is_empty() {
+                    head.push(Line::Other(String::new()));
+                }
+                head.append(&mut self.lines);
+                self.lines = head;
+            } else {
+                self.push_group(group);
+                self.lines.push(entry);
+            }
+            return;
+        };
+
+        let existing = self.lines[start..end].iter_mut().find_map(|line| match line {
+            Line::Entry {
+                key: k,
+                locale: None,
+                value,
+                raw,
+            } if k == key => Some((value, raw)),
+            _ => None,
+        });
+        if let Some((value, raw)) = existing {
+            if *value != escaped {
+                *value = escaped;
+                *raw = None;
+            }
+            return;
+        }
+
+        // Insert after the last entry so trailing blank lines stay separators.
+        let insert_at = self.lines[start..end]
+            .iter()
+            .rposition(|line| !matches!(line, Line::Other(raw) if raw.trim().is_empty()))
+            .map_or(end, |pos| start + pos + 1);
+        self.lines.insert(
+            insert_at,
+            Line::Entry {
+                key: key.to_string(),
+                locale: None,
+                value: escaped,
+                raw: None,
+            },
+        );
+    }
+
+    /// Removes `key` from `group`, including its localized variants.
+    pub fn remove(&mut self, group: &str, key: &str) {
+        let Some((start, end)) = self.group_range(group) else {
+            return;
+        };
+        let mut index = start;
+        let mut end = end;
+        while index < end {
+            if matches!(&self.lines[index], Line::Entry { key: k, .. } if k == key) {
+                self.lines.remove(index);
+                end -= 1;
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    fn raw(&self, group: &str, key: &str, locale: Option<&str>) -> Option<&str> {
+        let (start, end) = self.group_range(group)?;
+        self.lines[start..end].iter().find_map(|line| match line {
+            Line::Entry {
+                key: k,
+                locale: l,
+                value,
+                ..
+            } if k == key && l.as_deref() == locale => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Line range of the entries belonging to `group`, excluding its header.
+    fn group_range(&self, group: &str) -> Option<(usize, usize)> {
+        let header = self
+            .lines
+            .iter()
+            .position(|line| matches!(line, Line::Group { name, .. } if name == group))?;
+        let start = header + 1;
+        let end = self.lines[start..]
+            .iter()
+            .position(|line| matches!(line, Line::Group { .. }))
+            .map_or(self.lines.len(), |pos| start + pos);
+        Some((start, end))
+    }
+
+    fn push_group(&mut self, group: &str) {
+        if self
+            .lines
+            .last()
+            .is_some_and(|line| !matches!(line, Line::Other(raw) if raw.trim().is_empty()))
+        {
+            self.lines.push(Line::Other(String::new()));
+        }
+        self.lines.push(Line::Group {
+            name: group.to_string(),
+            raw: None,
+        });
+    }
+}
+
+impl fmt::Display for DesktopEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Other(raw)
+                | Line::Group { raw: Some(raw), .. }
+                | Line::Entry { raw: Some(raw), .. } => writeln!(f, "{raw}")?,
+                Line::Group { name, raw: None } => writeln!(f, "[{name}]")?,
+                Line::Entry {
+                    key,
+                    locale: Some(locale),
+                    value,
+                    raw: None,
+                } => writeln!(f, "{key}[{locale}]={value}")?,
+                Line::Entry {
+                    key,
+                    locale: None,
+                    value,
+                    raw: None,
+                } => writeln!(f, "{key}={value}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_line(raw: &str) -> Line {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return Line::Other(raw.to_string());
+    }
+
+    if let Some(name) = trimmed.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        return Line::Group {
+            name: name.to_string(),
+            raw: Some(raw.to_string()),
+        };
+    }
+
+    let Some((key, value)) = raw.split_once('=') else {
+        return Line::Other(raw.to_string());
+    };
+    let key = key.trim();
+    let (key, locale) = match key.strip_suffix(']').and_then(|k| k.split_once('[')) {
+        Some((key, locale)) => (key, Some(locale.to_string())),
+        None => (key, None),
+    };
+
+    Line::Entry {
+        key: key.to_string(),
+        locale,
+        value: value.trim_start().to_string(),
+        raw: Some(raw.to_string()),
+    }
+}
+
+fn unescape_value(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('\\') => out.push('\\'),
+            // Unknown escapes (e.g. `\;` outside lists) are kept as written.
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn escape_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            ' ' if i == 0 => out.push_str("\\s"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Splits an (already unescaped) `Exec` value into arguments, resolving the
+/// double-quote rules. Field codes are returned as their own arguments.
+/// Returns `None` for an unterminated quote.
+pub fn parse_exec(value: &str) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quoted = false;
+
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' => quoted = false,
+                '\\' => match chars.next() {
+                    Some(next @ ('"' | '`' | '$' | '\\')) => current.push(next),
+                    Some(next) => {
+                        current.push('\\');
+                        current.push(next);
+                    }
+                    None => return None,
+                },
+                _ => current.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            ' ' | '\t' | '\n' => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            '"' => {
+                quoted = true;
+                in_arg = true;
+            }
+            _ => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+
+    if quoted {
+        return None;
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Some(args)
+}
+
+/// Lenient variant of [`parse_exec`] for entries with broken quoting.
+pub fn parse_exec_lossy(value: &str) -> Vec<String> {
+    parse_exec(value).unwrap_or_else(|| {
+        value
+            .split_whitespace()
+            .map(std::string::ToString::to_string)
+            .collect()
+    })
+}
+
+/// Inverse of [`parse_exec`]: quotes arguments that contain reserved characters.
+pub fn serialize_exec(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            if !arg.is_empty() && !arg.contains(RESERVED_EXEC_CHARS) {
+                return arg.clone();
+            }
+            let mut quoted = String::with_capacity(arg.len() + 2);
+            quoted.push('"');
+            for c in arg.chars() {
+                if matches!(c, '"' | '`' | '$' | '\\') {
+                    quoted.push('\\');
+                }
+                quoted.push(c);
+            }
+            quoted.push('"');
+            quoted
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Drops field-code arguments and turns `%%` into `%`, giving the command as it
+/// would run without files or URLs.
+pub fn strip_field_codes(args: &[String]) -> Vec<String> {
+    args.iter()
+        .filter(|arg| !FIELD_CODES.contains(&arg.as_str()))
+        .map(|arg| arg.replace("%%", "%"))
+        .collect()
+}
+
+/// Locale keys to try for `Name[...]`, most specific first, derived from
+/// `LC_ALL`, `LC_MESSAGES` or `LANG` (`lang_COUNTRY.ENCODING@MODIFIER`).
+pub fn current_locales() -> Vec<String> {
+    let value = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|v| !v.is_empty())
+        .unwrap_or_default();
+    locale_keys(&value)
+}
+
+fn locale_keys(value: &str) -> Vec<String> {
+    if value.is_empty() || value == "C" || value == "POSIX" {
+        return Vec::new();
+    }
+
+    let (base, modifier) = match value.split_once('@') {
+        Some((base, modifier)) => (base, Some(modifier)),
+        None => (value, None),
+    };
+    let base = base.split('.').next().unwrap_or(base);
+    let (lang, country) = match base.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (base, None),
+    };
+
+    let mut locales = Vec::new();
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        locales.push(format!("{lang}_{country}@{modifier}"));
+    }
+    if let Some(country) = country {
+        locales.push(format!("{lang}_{country}"));
+    }
+    if let Some(modifier) = modifier {
+        locales.push(format!("{lang}@{modifier}"));
+    }
+    locales.push(lang.to_string());
+    locales
+}
+
+/// Desktop names from `XDG_CURRENT_DESKTOP`, used for `OnlyShowIn`/`NotShowIn`.
+pub fn current_desktops() -> Vec<String> {
+    std::env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|name| !name.is_empty())
+        .map(std::string::ToString::to_string)
+        .collect()
+}
+
+/// `TryExec` check: an absolute path or a program found in `PATH` that is executable.
+pub fn program_available(program: &str) -> bool {
+    if program.contains('/') {
+        return is_executable(Path::new(program));
+    }
+
+    std::env::var_os("PATH").is_some_and(|path| {
+        std::env::split_paths(&path).any(|dir| is_executable(&dir.join(program)))
+    })
+}
+
+fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIREFOX: &str = "\
+# Installed by the distribution
+[Desktop Entry]
+Version=1.0
+Type = Application
+Name=Firefox
+Name[de]=Firefox Webbrowser
+Name[pt_BR]=Navegador Firefox
+Comment=Browse the Web  
+Exec=firefox %u
+Keywords=web;browser\\;internet;
+
+[Desktop Action new-window]
+Name=New Window
+Exec=firefox --new-window %u
+";
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn round_trip_is_byte_identical() {
+        assert_eq!(DesktopEntry::parse(FIREFOX).to_string(), FIREFOX);
+    }
+
+    #[test]
+    fn set_only_rewrites_the_changed_line() {
+        let mut entry = DesktopEntry::parse(FIREFOX);
+        entry.set(DESKTOP_ENTRY, "Type", "Application");
+        entry.set(DESKTOP_ENTRY, "Exec", "env DRI_PRIME=1 firefox %u");
+        let expected = FIREFOX.replace(
+            "Exec=firefox %u\n",
+            "Exec=env DRI_PRIME=1 firefox %u\n",
+        );
+        assert_eq!(entry.to_string(), expected);
+    }
+
+    #[test]
+    fn spacing_around_the_separator_is_not_part_of_the_value() {
+        let entry = DesktopEntry::parse(FIREFOX);
+        assert_eq!(entry.get(DESKTOP_ENTRY, "Type").as_deref(), Some("Application"));
+        assert_eq!(entry.get(DESKTOP_ENTRY, "Comment").as_deref(), Some("Browse the Web  "));
+    }
+
+    #[test]
+    fn new_keys_go_after_the_last_entry_of_their_group() {
+        let mut entry = DesktopEntry::parse(FIREFOX);
+        entry.set(DESKTOP_ENTRY, "X-Kaede-Managed", "true");
+        let expected = FIREFOX.replace(
+            "internet;\n\n",
+            "internet;\nX-Kaede-Managed=true\n\n",
+        );
+        assert_eq!(entry.to_string(), expected);
+    }
+
+    #[test]
+    fn remove_drops_localized_variants() {
+        let mut entry = DesktopEntry::parse(FIREFOX);
+        entry.remove(DESKTOP_ENTRY, "Name");
+        let output = entry.to_string();
+        assert!(!output.contains("Name[de]"));
+        assert!(!output.contains("Name=Firefox"));
+        assert!(output.contains("Name=New Window"));
+    }
+
+    #[test]
+    fn escapes_are_resolved() {
+        let entry = DesktopEntry::parse(
+            "[Desktop Entry]\nComment=\\sleading\\nnext line\\\\back\\;slash\n",
+        );
+        assert_eq!(
+            entry.get(DESKTOP_ENTRY, "Comment").as_deref(),
+            Some(" leading\nnext line\\back\\;slash")
+        );
+    }
+
+    #[test]
+    fn set_escapes_values() {
+        let mut entry = DesktopEntry::default();
+        entry.set(DESKTOP_ENTRY, "Comment", " two\nlines \\ here");
+        assert_eq!(
+            entry.to_string(),
+            "[Desktop Entry]\nComment=\\stwo\\nlines \\\\ here\n"
+        );
+        assert_eq!(
+            entry.get(DESKTOP_ENTRY, "Comment").as_deref(),
+            Some(" two\nlines \\ here")
+        );
+    }
+
+    #[test]
+    fn lists_honour_escaped_separators() {
+        let entry = DesktopEntry::parse(FIREFOX);
+        assert_eq!(
+            entry.get_list(DESKTOP_ENTRY, "Keywords"),
+            strings(&["web", "browser;internet"])
+        );
+    }
+
+    #[test]
+    fn quoted_exec_arguments_keep_their_spaces() {
+        let args = parse_exec(r#""/opt/My Game/run.sh" --name "a \"b\" \$c" %U"#).unwrap();
+        assert_eq!(
+            args,
+            strings(&["/opt/My Game/run.sh", "--name", r#"a "b" $c"#, "%U"])
+        );
+        assert_eq!(parse_exec(&serialize_exec(&args)).unwrap(), args);
+    }
+
+    #[test]
+    fn unterminated_quotes_fall_back_to_whitespace_splitting() {
+        assert_eq!(parse_exec(r#"game "--broken"#), None);
+        assert_eq!(parse_exec_lossy(r#"game "--broken"#), strings(&["game", "\"--broken"]));
+    }
+
+    #[test]
+    fn field_codes_are_dropped_and_percent_signs_unescaped() {
+        let args = parse_exec("app --progress=50%% %f --icon %i %U").unwrap();
+        assert_eq!(
+            strip_field_codes(&args),
+            strings(&["app", "--progress=50%", "--icon"])
+        );
+    }
+
+    #[test]
+    fn localized_names_fall_back_to_less_specific_locales() {
+        let entry = DesktopEntry::parse(FIREFOX);
+        let name = |value: &str| entry.get_localized(DESKTOP_ENTRY, "Name", &locale_keys(value));
+        assert_eq!(name("de_AT.UTF-8").as_deref(), Some("Firefox Webbrowser"));
+        assert_eq!(name("pt_BR.UTF-8").as_deref(), Some("Navegador Firefox"));
+        assert_eq!(name("pt_PT.UTF-8").as_deref(), Some("Firefox"));
+        assert_eq!(name("C").as_deref(), Some("Firefox"));
+    }
+
+    #[test]
+    fn locale_keys_are_most_specific_first() {
+        assert_eq!(
+            locale_keys("sr_RS.UTF-8@latin"),
+            strings(&["sr_RS@latin", "sr_RS", "sr@latin", "sr"])
+        );
+        assert!(locale_keys("POSIX").is_empty());
+    }
+
+    #[test]
+    fn show_in_lists_match_the_current_desktop() {
+        let entry = DesktopEntry::parse("[Desktop Entry]\nOnlyShowIn=GNOME;Unity;\n");
+        assert!(entry.shown_in(&strings(&["ubuntu", "GNOME"])));
+        assert!(!entry.shown_in(&strings(&["KDE"])));
+        // OnlyShowIn cannot be checked without a desktop name.
+        assert!(entry.shown_in(&[]));
+
+        let entry = DesktopEntry::parse("[Desktop Entry]\nNotShowIn=KDE;\n");
+        assert!(!entry.shown_in(&strings(&["KDE"])));
+        assert!(entry.shown_in(&strings(&["GNOME"])));
+    }
+
+    #[test]
+    fn try_exec_looks_up_programs() {
+        assert!(program_available("/bin/sh"));
+        assert!(!program_available("/nonexistent/kaede-test"));
+        assert!(!program_available("kaede-test-no-such-program"));
+        // Directories are not programs.
+        assert!(!program_available("/"));
+    }
+}
@@ -1,35 +1,81 @@
-use crate::models::DesktopApp;
+mod entry;
+
+pub use entry::{parse_exec_lossy, serialize_exec, DesktopEntry, DESKTOP_ENTRY};
+
+use crate::{bottles, lutris, steam};
+use crate::models::{DesktopAction, DesktopApp};
+use entry::{current_desktops, current_locales, program_available, strip_field_codes};
 use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-pub fn scan_desktop_entries() -> Vec<DesktopApp> {
-    let mut map: BTreeMap<String, DesktopApp> = BTreeMap::new();
+struct ScanContext {
+    /// Desktop ID to file for every directory but the user's own, where the
+    /// overrides live.
+    system_entries: BTreeMap<String, PathBuf>,
+    locales: Vec<String>,
+    desktops: Vec<String>,
+}
 
-    for dir in application_dirs() {
-        if !dir.exists() {
-            continue;
-        }
+/// `extra_dirs` are additional `applications` directories from the config.
+pub fn scan_desktop_entries(extra_dirs: &[PathBuf]) -> Vec<DesktopApp> {
+    // Later directories override earlier ones (user data home last), so a
+    // hidden entry in a more important directory masks the others.
+    let user_dir = user_applications_dir();
+    let mut system_entries: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for dir in application_dirs(extra_dirs).iter().filter(|dir| **dir != user_dir) {
+        system_entries.extend(desktop_files(dir));
+    }
+    let mut files = system_entries.clone();
+    files.extend(desktop_files(&user_dir));
+
+    let ctx = ScanContext {
+        system_entries,
+        locales: current_locales(),
+        desktops: current_desktops(),
+    };
+    let mut apps: Vec<DesktopApp> = files
+        .into_iter()
+        .filter_map(|(desktop_id, path)| parse_desktop_file(&path, desktop_id, &ctx))
+        .collect();
+    let steam_games = steam::scan_games(&apps);
+    apps.extend(steam_games);
+    let steam_shortcuts = steam::scan_shortcuts(&apps);
+    apps.extend(steam_shortcuts);
+    let lutris_games = lutris::scan_games(&apps);
+    apps.extend(lutris_games);
+    // Bottles shortcuts all run the Bottles Flatpak; list their programs instead.
+    let bottles_apps = bottles::scan_bottles(&apps);
+    apps.retain(|app| !bottles::is_program_launcher(app, &bottles_apps));
+    apps.extend(bottles_apps);
+    shuffle_in_place(&mut apps);
+    apps
+}
 
-        if let Ok(read_dir) = fs::read_dir(&dir) {
-            for entry in read_dir.flatten() {
-                let path = entry.path();
-                if path.extension().and_then(|e| e.to_str()) != Some("desktop") {
-                    continue;
-                }
-
-                if let Some(app) = parse_desktop_file(&path) {
-                    // Later directories override earlier ones (user local last).
-                    map.insert(app.desktop_id.clone(), app);
-                }
+/// All `.desktop` files below `dir` with their desktop IDs; files in
+/// subdirectories get the path as a prefix (`kde/foo.desktop` -> `kde-foo.desktop`).
+fn desktop_files(dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut found = Vec::new();
+    let mut pending = vec![(dir.to_path_buf(), String::new())];
+
+    while let Some((current, prefix)) = pending.pop() {
+        let Ok(read_dir) = fs::read_dir(&current) else {
+            continue;
+        };
+        for entry in read_dir.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Symlinked directories are not followed to avoid loops.
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                pending.push((path, format!("{prefix}{name}-")));
+            } else if name.ends_with(".desktop") && path.is_file() {
+                found.push((format!("{prefix}{name}"), path));
             }
         }
     }
 
-    let mut apps: Vec<DesktopApp> = map.into_values().collect();
-    shuffle_in_place(&mut apps);
-    apps
+    found
 }
 
 fn shuffle_in_place<T>(items: &mut [T]) {
@@ -64,74 +110,142 @@ fn next_u64(state: &mut u64) -> u64 {
     x.wrapping_mul(0x2545F4914F6CDD1D)
 }
 
-fn application_dirs() -> Vec<PathBuf> {
-    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-    vec![
-        PathBuf::from("/usr/share/applications"),
-        PathBuf::from("/usr/local/share/applications"),
-        PathBuf::from(home.clone()).join(".local/share/applications"),
-        PathBuf::from("/var/lib/flatpak/exports/share/applications"),
-        PathBuf::from(home).join(".local/share/flatpak/exports/share/applications"),
-    ]
+/// `$XDG_DATA_HOME`, defaulting to `~/.local/share`.
+pub fn data_home() -> PathBuf {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| {
+            let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+            PathBuf::from(home).join(".local/share")
+        })
 }
 
-fn parse_desktop_file(path: &Path) -> Option<DesktopApp> {
-    let content = fs::read_to_string(path).ok()?;
-    let mut in_desktop_entry = false;
-    let mut name: Option<String> = None;
-    let mut icon: Option<String> = None;
-    let mut exec: Option<String> = None;
-    let mut no_display = false;
-    let mut hidden = false;
-    let mut typ = String::new();
-    let mut flatpak_app_id: Option<String> = None;
-
-    for raw_line in content.lines() {
-        let line = raw_line.trim();
-        if line.is_empty() || line.starts_with('#') {
-            continue;
-        }
+/// Where Kaede writes desktop overrides; entries here shadow the system ones.
+pub fn user_applications_dir() -> PathBuf {
+    data_home().join("applications")
+}
 
-        if line.starts_with('[') && line.ends_with(']') {
-            in_desktop_entry = line == "[Desktop Entry]";
-            continue;
-        }
+/// `$XDG_DATA_DIRS` in order of importance, defaulting to `/usr/local/share:/usr/share`.
+fn data_dirs() -> Vec<PathBuf> {
+    let dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs.split(':')
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .collect()
+}
 
-        if !in_desktop_entry {
-            continue;
-        }
+/// Application directories from least to most important.
+fn application_dirs(extra_dirs: &[PathBuf]) -> Vec<PathBuf> {
+    // Sessions started outside a login shell often miss these in XDG_DATA_DIRS.
+    let fallbacks = [
+        PathBuf::from("/var/lib/snapd/desktop"),
+        PathBuf::from("/var/lib/flatpak/exports/share"),
+        data_home().join("flatpak/exports/share"),
+    ];
+
+    let data_dirs = data_dirs();
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    let candidates = fallbacks
+        .iter()
+        .map(|base| base.join("applications"))
+        .chain(extra_dirs.iter().cloned())
+        .chain(data_dirs.iter().rev().map(|base| base.join("applications")))
+        .chain(std::iter::once(user_applications_dir()));
+    for dir in candidates {
+        // Keep the most important position of a directory listed twice.
+        dirs.retain(|existing| *existing != dir);
+        dirs.push(dir);
+    }
+    dirs
+}
 
-        let Some((key, value)) = line.split_once('=') else {
-            continue;
-        };
-        let key = key.trim();
-        let value = value.trim().to_string();
-
-        match key {
-            "Name" => name = Some(value),
-            "Icon" => icon = Some(value),
-            "Exec" => exec = Some(strip_desktop_exec_placeholders(&value)),
-            "NoDisplay" => no_display = value.eq_ignore_ascii_case("true"),
-            "Hidden" => hidden = value.eq_ignore_ascii_case("true"),
-            "Type" => typ = value,
-            "X-Flatpak" => flatpak_app_id = Some(value),
-            _ => {}
-        }
+fn parse_desktop_file(path: &Path, desktop_id: String, ctx: &ScanContext) -> Option<DesktopApp> {
+    let locales = ctx.locales.as_slice();
+    let desktops = ctx.desktops.as_slice();
+    let content = fs::read_to_string(path).ok()?;
+    let entry = DesktopEntry::parse(&content);
+
+    if entry.get(DESKTOP_ENTRY, "Type").as_deref() != Some("Application")
+        || entry.get_bool(DESKTOP_ENTRY, "NoDisplay")
+        || entry.get_bool(DESKTOP_ENTRY, "Hidden")
+    {
+        return None;
+    }
+
+    if entry
+        .get(DESKTOP_ENTRY, "TryExec")
+        .is_some_and(|program| !program_available(&program))
+    {
+        return None;
     }
 
-    if no_display || hidden || typ != "Application" {
+    if !entry.shown_in(desktops) {
         return None;
     }
 
-    let desktop_id = path.file_name()?.to_string_lossy().to_string();
+    let name = entry.get_localized(DESKTOP_ENTRY, "Name", locales);
+    let icon = entry.get(DESKTOP_ENTRY, "Icon");
+    let exec = entry
+        .get(DESKTOP_ENTRY, "Exec")
+        .map(|value| serialize_exec(&strip_field_codes(&parse_exec_lossy(&value))));
+    let flatpak_app_id = entry.get(DESKTOP_ENTRY, "X-Flatpak");
+    let kaede_managed = entry.get_bool(DESKTOP_ENTRY, "X-Kaede-Managed");
+    let kaede_source = entry.get(DESKTOP_ENTRY, "X-Kaede-Source");
+    let kaede_source_hash = entry.get(DESKTOP_ENTRY, "X-Kaede-Source-Hash");
+
+    let actions = entry
+        .groups()
+        .filter_map(|group| group.strip_prefix("Desktop Action "))
+        .map(|id| {
+            let group = format!("Desktop Action {id}");
+            DesktopAction {
+                id: id.to_string(),
+                name: entry
+                    .get_localized(&group, "Name", locales)
+                    .unwrap_or_else(|| id.to_string()),
+            }
+        })
+        .collect::<Vec<_>>();
+
     let id_from_filename = desktop_id.strip_suffix(".desktop").map(|s| s.to_string());
     let id_from_exec = flatpak_app_id_from_exec(exec.as_deref().unwrap_or_default());
     let flatpak_id = flatpak_app_id.or(id_from_exec).or(id_from_filename);
     let is_flatpak = is_flatpak_entry(path, exec.as_deref().unwrap_or_default());
-    let steam_app_id = steam_app_id_from_exec(exec.as_deref().unwrap_or_default());
+    // Steam-created shortcuts for non-Steam games use a 64-bit game ID.
+    let steam_game_id = steam_app_id_from_exec(exec.as_deref().unwrap_or_default());
+    let steam_shortcut_id = steam_game_id.as_deref().and_then(steam::shortcut_id_from_game_id);
+    let steam_app_id = steam_game_id.filter(|_| steam_shortcut_id.is_none());
     let (heroic_platform, heroic_app_name) =
         heroic_game_from_exec(exec.as_deref().unwrap_or_default()).unwrap_or_else(|| (None, None));
     let is_heroic_game = heroic_platform.is_some() && heroic_app_name.is_some();
+    let lutris_game = lutris::game_from_exec(&desktop_id, exec.as_deref().unwrap_or_default());
+    let snap_name = entry
+        .get(DESKTOP_ENTRY, "X-SnapInstanceName")
+        .or_else(|| snap_name_from_exec(exec.as_deref().unwrap_or_default()))
+        .or_else(|| snap_name_from_path(path, &desktop_id));
+
+    // Overrides from before the source was recorded are looked up by desktop
+    // ID. Without a recorded hash we cannot tell whether the source changed,
+    // so only a missing source makes such an override stale.
+    let override_source = if kaede_managed {
+        kaede_source
+            .map(PathBuf::from)
+            .or_else(|| ctx.system_entries.get(&desktop_id).cloned())
+    } else {
+        None
+    };
+    let override_stale = kaede_managed
+        && match (&override_source, &kaede_source_hash) {
+            (Some(source), Some(hash)) => fs::read_to_string(source)
+                .map(|content| source_hash(&content) != *hash)
+                .unwrap_or(true),
+            (Some(source), None) => !source.is_file(),
+            (None, _) => true,
+        };
 
     Some(DesktopApp {
         desktop_id,
@@ -139,23 +253,33 @@ fn parse_desktop_file(path: &Path) -> Option<DesktopApp> {
         name: name.unwrap_or_else(|| "Unnamed Application".to_string()),
         icon,
         exec: exec.unwrap_or_default(),
-        is_steam_game: steam_app_id.is_some(),
+        is_steam_game: steam_app_id.is_some() || steam_shortcut_id.is_some(),
         steam_app_id,
+        steam_shortcut_id,
         is_heroic_game,
         heroic_platform,
         heroic_app_name,
+        is_lutris_game: lutris_game.is_some(),
+        lutris_slug: lutris_game.flatten(),
+        is_bottles_app: false,
+        bottle_name: None,
+        bottle_program: None,
         is_flatpak,
         flatpak_app_id: if is_flatpak { flatpak_id } else { None },
+        is_snap: snap_name.is_some(),
+        snap_name,
+        override_source,
+        override_stale,
+        actions,
     })
 }
 
-fn strip_desktop_exec_placeholders(exec: &str) -> String {
-    ["%f", "%F", "%u", "%U", "%i", "%c", "%k"]
-        .iter()
-        .fold(exec.to_string(), |acc, token| acc.replace(token, ""))
-        .split_whitespace()
-        .collect::<Vec<_>>()
-        .join(" ")
+/// FNV-1a over the file content; stable across runs and Rust versions.
+pub fn source_hash(content: &str) -> String {
+    let hash = content.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{hash:016x}")
 }
 
 fn is_flatpak_entry(path: &Path, exec: &str) -> bool {
@@ -165,6 +289,33 @@ fn is_flatpak_entry(path: &Path, exec: &str) -> bool {
         || exec.contains("/flatpak")
 }
 
+fn snap_name_from_exec(exec: &str) -> Option<String> {
+    let parts = exec.split_whitespace().collect::<Vec<_>>();
+    let from_bin = parts
+        .iter()
+        .find_map(|part| part.strip_prefix("/snap/bin/"))
+        .filter(|name| !name.is_empty());
+    let from_run = parts
+        .windows(3)
+        .find(|w| (w[0] == "snap" || w[0].ends_with("/snap")) && w[1] == "run")
+        .map(|w| w[2]);
+    // `/snap/bin/<snap>.<app>` launches a non-default app of the snap.
+    from_bin
+        .or(from_run)
+        .and_then(|command| command.split('.').next())
+        .map(|name| name.to_string())
+}
+
+/// snapd exports launchers as `<snap>_<app>.desktop`.
+fn snap_name_from_path(path: &Path, desktop_id: &str) -> Option<String> {
+    if !path.starts_with("/var/lib/snapd/desktop/applications") {
+        return None;
+    }
+    desktop_id
+        .split_once('_')
+        .map(|(snap, _)| snap.to_string())
+}
+
 fn flatpak_app_id_from_exec(exec: &str) -> Option<String> {
     if !exec.contains("flatpak") || !exec.contains("run") {
         return None;
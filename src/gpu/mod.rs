@@ -1,4 +1,4 @@
-use crate::models::GpuInfo;
+use crate::models::{GpuChoice, GpuId, GpuInfo};
 use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
@@ -28,6 +28,9 @@ pub fn detect_gpus() -> Vec<GpuInfo> {
                 })
             });
 
+            let vendor_id = read_pci_id(&device_path.join("vendor"));
+            let device_id = read_pci_id(&device_path.join("device"));
+
             let render_node = render_map.get(&file_name).cloned();
             let card_name = pci_slot
                 .as_ref()
@@ -39,6 +42,8 @@ pub fn detect_gpus() -> Vec<GpuInfo> {
                 name: card_name,
                 driver,
                 pci_slot,
+                vendor_id,
+                device_id,
                 render_node,
                 dri_prime_index: None,
                 renderer: None,
@@ -63,6 +68,83 @@ pub fn detect_gpus() -> Vec<GpuInfo> {
     cards
 }
 
+/// Resolves an assignment to a currently present GPU. Returns `None` for the
+/// default choice and for assignments whose GPU is no longer present.
+pub fn gpu_for_choice<'a>(gpus: &'a [GpuInfo], choice: &GpuChoice) -> Option<&'a GpuInfo> {
+    match choice {
+        GpuChoice::Gpu(id) => find_gpu(gpus, id),
+        GpuChoice::Default | GpuChoice::LegacyIndex(_) => None,
+    }
+}
+
+pub fn find_gpu<'a>(gpus: &'a [GpuInfo], id: &GpuId) -> Option<&'a GpuInfo> {
+    let same_device = |g: &GpuInfo| {
+        id.vendor_id.is_some()
+            && id.device_id.is_some()
+            && g.vendor_id == id.vendor_id
+            && g.device_id == id.device_id
+    };
+
+    let exact = gpus.iter().find(|g| {
+        id.pci_slot.is_some()
+            && g.pci_slot == id.pci_slot
+            && (same_device(g) || id.vendor_id.is_none())
+    });
+    if exact.is_some() {
+        return exact;
+    }
+
+    // The card moved to another slot (eGPU replug, BIOS change): accept it only
+    // when the match is unambiguous.
+    unique(gpus.iter().filter(|g| same_device(g)))
+        .or_else(|| unique(gpus.iter().filter(|g| g.stable_id().name == id.name)))
+}
+
+/// Converts index-based assignments from older configs and re-anchors ids that
+/// only matched through a fallback. Returns whether anything changed.
+pub fn migrate_assignments<'a>(
+    assignments: impl Iterator<Item = &'a mut GpuChoice>,
+    gpus: &[GpuInfo],
+) -> bool {
+    let mut changed = false;
+    for choice in assignments {
+        let current = match choice {
+            GpuChoice::Default => continue,
+            GpuChoice::LegacyIndex(idx) => {
+                gpus.iter().find(|g| g.dri_prime_index == Some(*idx))
+            }
+            GpuChoice::Gpu(id) => find_gpu(gpus, id),
+        };
+
+        let Some(gpu) = current else {
+            continue;
+        };
+        let migrated = GpuChoice::Gpu(gpu.stable_id());
+        if *choice != migrated {
+            *choice = migrated;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Extracts the `DRI_PRIME` value from an env string such as a LaunchOptions
+/// prefix, an `Exec=` line or `--env=DRI_PRIME=1` arguments.
+pub fn parse_dri_prime(text: &str) -> Option<usize> {
+    text.split_whitespace().find_map(|token| {
+        let token = token.trim_start_matches("--env=").trim_matches('"');
+        token.strip_prefix("DRI_PRIME=")?.parse().ok()
+    })
+}
+
+fn unique<'a>(mut iter: impl Iterator<Item = &'a GpuInfo>) -> Option<&'a GpuInfo> {
+    let first = iter.next()?;
+    if iter.next().is_some() {
+        return None;
+    }
+    Some(first)
+}
+
 fn read_lspci_gpu_names() -> BTreeMap<String, String> {
     let mut map = BTreeMap::new();
     let Ok(output) = Command::new("lspci").arg("-nn").output() else {
@@ -150,6 +232,12 @@ fn read_driver_name(device_path: &Path) -> Option<String> {
     link.file_name().map(|v| v.to_string_lossy().to_string())
 }
 
+fn read_pci_id(path: &Path) -> Option<String> {
+    let raw = read_file_trimmed(path)?;
+    let id = raw.trim_start_matches("0x").to_ascii_lowercase();
+    (!id.is_empty()).then_some(id)
+}
+
 fn read_file_trimmed(path: impl AsRef<Path>) -> Option<String> {
     fs::read_to_string(path).ok().map(|s| s.trim().to_string())
 }
@@ -1,3 +1,5 @@
+use crate::backup;
+use crate::models::OverrideState;
 use anyhow::{Context, Result};
 use serde_json::{Map, Value};
 use std::fs;
@@ -29,7 +31,7 @@ pub fn apply_heroic_launch_env(platform: &str, app_name: &str, env_vars: &[Strin
         matched = true;
         let changed = apply_env_to_heroic_json(&mut json, app_name, env_vars)?;
         if changed {
-            write_backup_if_missing(&path, &raw)?;
+            backup::snapshot(&path)?;
             let body = serde_json::to_string_pretty(&json)
                 .with_context(|| format!("failed to serialize Heroic config {}", path.display()))?;
             fs::write(&path, body)
@@ -67,6 +69,50 @@ pub fn apply_heroic_launch_env(platform: &str, app_name: &str, env_vars: &[Strin
     Ok(())
 }
 
+pub fn read_override_state(app_name: &str, env_vars: &[String]) -> Result<OverrideState> {
+    for path in find_heroic_game_config_candidates(app_name) {
+        let raw = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read Heroic config {}", path.display()))?;
+        let json: Value = serde_json::from_str(&raw)
+            .with_context(|| format!("failed to parse Heroic config {}", path.display()))?;
+        if !heroic_config_matches_game(&json, &raw, &path, app_name) {
+            continue;
+        }
+
+        let managed = managed_env_value(&json, KAEDE_MARKER_KEY).is_some();
+        let in_sync = if env_vars.is_empty() {
+            !managed
+        } else {
+            validate_env_in_heroic_json(&json, app_name, env_vars)
+        };
+        let observed_dri_prime = if managed {
+            managed_env_value(&json, "DRI_PRIME").and_then(|v| v.parse().ok())
+        } else {
+            None
+        };
+
+        return Ok(OverrideState {
+            in_sync,
+            observed_dri_prime,
+        });
+    }
+
+    anyhow::bail!("Heroic game {} not matched in configs", app_name)
+}
+
+fn managed_env_value(json: &Value, key: &str) -> Option<String> {
+    match json.get("envVariables")? {
+        Value::Object(map) => map.get(key).and_then(|v| v.as_str()).map(str::to_string),
+        Value::Array(arr) => arr.iter().find_map(|item| {
+            if item.get("name").and_then(|n| n.as_str()) != Some(key) {
+                return None;
+            }
+            item.get("value").and_then(|v| v.as_str()).map(str::to_string)
+        }),
+        _ => None,
+    }
+}
+
 fn find_heroic_game_config_candidates(app_name: &str) -> Vec<PathBuf> {
     let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
     let bases = [
@@ -389,13 +435,3 @@ fn parse_env_option_entry(item: &Value) -> Option<(String, String)> {
     let v = item.get("value").and_then(|v| v.as_str())?;
     Some((k.to_string(), v.to_string()))
 }
-
-fn write_backup_if_missing(path: &Path, content: &str) -> Result<()> {
-    let backup = path.with_extension("json.kaede.bak");
-    if !backup.exists() {
-        fs::write(&backup, content)
-            .with_context(|| format!("failed to write Heroic backup {}", backup.display()))?;
-        info!(backup = %backup.display(), "Heroic config backup created");
-    }
-    Ok(())
-}
@@ -1,7 +1,16 @@
-use crate::heroic::apply_heroic_launch_env;
-use crate::models::{DesktopApp, GpuChoice, GpuInfo};
-use crate::steam::apply_steam_launch_options;
+use crate::backup;
+use crate::bottles::{self, apply_bottle_env};
+use crate::desktop::{
+    data_home, parse_exec_lossy, serialize_exec, source_hash, user_applications_dir,
+    DesktopEntry, DESKTOP_ENTRY,
+};
+use crate::gpu::{gpu_for_choice, parse_dri_prime};
+use crate::heroic::{self, apply_heroic_launch_env};
+use crate::lutris::{self, apply_lutris_launch_env};
+use crate::models::{DesktopApp, GpuChoice, GpuInfo, OverrideState, SteamAccountScope};
+use crate::steam::{self, apply_steam_launch_options};
 use anyhow::{Context, Result};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -9,25 +18,91 @@ use tracing::{debug, info, warn};
 
 const KAEDE_MARKER: &str = "X-Kaede-Managed=true";
 
+/// Rewrites one Exec command line for the given `DRI_PRIME` index and GPU.
+type ExecWrapper<'a> = dyn Fn(&[String], usize, Option<&GpuInfo>) -> Vec<String> + 'a;
+
+/// Extra key on overrides written for Snap apps, holding the snap instance name.
+const SNAP_MARKER_KEY: &str = "X-Kaede-Snap";
+
+/// Bottles' own launchers are edited in place; this keeps their original `Exec`.
+const BOTTLES_EXEC_KEY: &str = "X-Kaede-Bottles-Exec";
+
+/// Marks an environment block written by Kaede.
+pub const MANAGED_ENV_MARKER: &str = "KAEDE_GPU_MANAGED";
+
+/// Every variable Kaede may set in a launcher's environment.
+pub const MANAGED_ENV: [&str; 9] = [
+    "DRI_PRIME",
+    "PRESSURE_VESSEL_IMPORT_VARS",
+    "__NV_PRIME_RENDER_OFFLOAD",
+    "__GLX_VENDOR_LIBRARY_NAME",
+    "__VK_LAYER_NV_optimus",
+    "MESA_VK_DEVICE_SELECT",
+    "MESA_VK_DEVICE_SELECT_FORCE_DEFAULT_DEVICE",
+    "DXVK_FILTER_DEVICE_NAME",
+    MANAGED_ENV_MARKER,
+];
+
+/// The effective GPU of one desktop action after falling back to the app's choice.
+struct ActionTarget<'a> {
+    id: String,
+    dri_prime: Option<usize>,
+    gpu: Option<&'a GpuInfo>,
+}
+
+/// `action_choices` only affects apps launched through a desktop override;
+/// Steam, Heroic and Flatpak apply one environment to the whole app.
+/// `steam_accounts` only matters for Steam games.
 pub fn apply_launcher_override(
     app: &DesktopApp,
     choice: &GpuChoice,
+    action_choices: &BTreeMap<String, GpuChoice>,
+    steam_accounts: &SteamAccountScope,
     selected_gpu: Option<&GpuInfo>,
     all_gpus: &[GpuInfo],
     use_env_wrapper: bool,
 ) -> Result<()> {
+    let _backup = backup::begin(&format!("Assign {} to {}", choice.label(), app.name));
+    let dri_prime = assigned_dri_prime(choice, selected_gpu)?;
+
+    if let (true, Some(shortcut)) = (app.is_steam_game, app.steam_shortcut_id) {
+        let _ = remove_kaede_override_if_present(&user_launcher_path(&app.desktop_id));
+        let steam_env = steam_env_vars(dri_prime, selected_gpu, all_gpus, use_env_wrapper);
+        info!(
+            shortcut = shortcut,
+            gpu_choice = %choice.label(),
+            accounts = ?steam_accounts,
+            env = ?steam_env,
+            "applying Steam shortcut LaunchOptions override"
+        );
+        return steam::apply_shortcut_launch_options(
+            shortcut,
+            dri_prime,
+            &steam_env,
+            use_env_wrapper,
+            steam_accounts,
+        );
+    }
+
     if app.is_steam_game {
         if let Some(app_id) = app.steam_app_id.as_deref() {
             // Steam games should be configured through Steam LaunchOptions.
             let _ = remove_kaede_override_if_present(&user_launcher_path(&app.desktop_id));
-            let steam_env = steam_env_vars(choice, selected_gpu, all_gpus, use_env_wrapper);
+            let steam_env = steam_env_vars(dri_prime, selected_gpu, all_gpus, use_env_wrapper);
             info!(
                 app_id = app_id,
                 gpu_choice = %choice.label(),
+                accounts = ?steam_accounts,
                 env = ?steam_env,
                 "applying Steam LaunchOptions override"
             );
-            return apply_steam_launch_options(app_id, choice, &steam_env, use_env_wrapper);
+            return apply_steam_launch_options(
+                app_id,
+                dri_prime,
+                &steam_env,
+                use_env_wrapper,
+                steam_accounts,
+            );
         }
         warn!(
             desktop_id = %app.desktop_id,
@@ -40,9 +115,9 @@ pub fn apply_launcher_override(
             app.heroic_platform.as_deref(),
             app.heroic_app_name.as_deref(),
         ) {
-            let heroic_env = match choice {
-                GpuChoice::Default => Vec::new(),
-                GpuChoice::Gpu(index) => build_env_pairs(*index, false, selected_gpu, all_gpus, use_env_wrapper),
+            let heroic_env = match dri_prime {
+                None => Vec::new(),
+                Some(index) => build_env_pairs(index, false, selected_gpu, all_gpus, use_env_wrapper),
             };
             info!(
                 platform = platform,
@@ -59,6 +134,63 @@ pub fn apply_launcher_override(
         );
     }
 
+    if let (true, Some(bottle)) = (app.is_bottles_app, app.bottle_name.as_deref()) {
+        let bottle_env = match dri_prime {
+            None => Vec::new(),
+            Some(index) => build_env_pairs(index, false, selected_gpu, all_gpus, use_env_wrapper),
+        };
+        info!(
+            bottle = bottle,
+            program = ?app.bottle_program,
+            gpu_choice = %choice.label(),
+            env = ?bottle_env,
+            "applying Bottles env override"
+        );
+        if app.bottle_program.is_none() {
+            return apply_bottle_env(bottle, &bottle_env);
+        }
+        // Programs share their bottle's environment, so the variables go through
+        // the program's launcher and `bottles-cli` instead. Launchers outside
+        // the user's directory get a regular override below.
+        let wrap = |args: &[String], index| {
+            wrap_exec_for_gpu(args, index, selected_gpu, all_gpus, use_env_wrapper)
+        };
+        match bottles_program_launcher(app) {
+            Some((path, true)) => return edit_bottles_launcher(&path, dri_prime, &wrap),
+            Some((target, false)) => {
+                return match dri_prime {
+                    None => remove_kaede_override_if_present(&target),
+                    Some(index) => {
+                        let args = wrap(&parse_exec_lossy(&app.exec), index);
+                        write_generated_launcher(app, &target, &args)
+                    }
+                };
+            }
+            None => {}
+        }
+    }
+
+    // Flatpak Lutris shortcuts also look like Flatpak apps, so this goes first.
+    if app.is_lutris_game {
+        if let Some(slug) = app.lutris_slug.as_deref() {
+            let lutris_env = match dri_prime {
+                None => Vec::new(),
+                Some(index) => build_env_pairs(index, false, selected_gpu, all_gpus, use_env_wrapper),
+            };
+            info!(
+                game = slug,
+                gpu_choice = %choice.label(),
+                env = ?lutris_env,
+                "applying Lutris game env override"
+            );
+            return apply_lutris_launch_env(slug, &lutris_env);
+        }
+        warn!(
+            desktop_id = %app.desktop_id,
+            "lutris game detected without slug, falling back to desktop override"
+        );
+    }
+
     if app.is_flatpak {
         if let Some(app_id) = app.flatpak_app_id.as_deref() {
             let profile = gpu_profile(selected_gpu);
@@ -69,7 +201,7 @@ pub fn apply_launcher_override(
                 mesa = profile.is_mesa,
                 "applying Flatpak override"
             );
-            return apply_flatpak_override(app_id, choice, selected_gpu, all_gpus, use_env_wrapper);
+            return apply_flatpak_override(app_id, dri_prime, selected_gpu, all_gpus, use_env_wrapper);
         }
         warn!(
             desktop_id = %app.desktop_id,
@@ -78,40 +210,320 @@ pub fn apply_launcher_override(
     }
 
     let target = user_launcher_path(&app.desktop_id);
+    let actions = action_targets(app, choice, action_choices, all_gpus)?;
+    let unassigned = dri_prime.is_none() && actions.iter().all(|a| a.dri_prime.is_none());
+
+    if app.is_snap {
+        if let Some(snap_name) = app.snap_name.as_deref() {
+            info!(
+                snap = snap_name,
+                gpu_choice = %choice.label(),
+                "applying Snap launcher override"
+            );
+            if unassigned {
+                return remove_snap_override(&target, snap_name);
+            }
+            return write_override(
+                app,
+                dri_prime,
+                selected_gpu,
+                &actions,
+                &target,
+                &|args, index, gpu| wrap_snap_exec(args, index, gpu, all_gpus),
+                Some(snap_name),
+            );
+        }
+        warn!(
+            desktop_id = %app.desktop_id,
+            "snap app detected without snap name, falling back to desktop override"
+        );
+    }
+
+    if unassigned {
+        return remove_kaede_override_if_present(&target);
+    }
+    write_override(
+        app,
+        dri_prime,
+        selected_gpu,
+        &actions,
+        &target,
+        &|args, index, gpu| wrap_exec_for_gpu(args, index, gpu, all_gpus, use_env_wrapper),
+        None,
+    )
+}
+
+fn action_targets<'a>(
+    app: &DesktopApp,
+    choice: &GpuChoice,
+    action_choices: &BTreeMap<String, GpuChoice>,
+    all_gpus: &'a [GpuInfo],
+) -> Result<Vec<ActionTarget<'a>>> {
+    app.actions
+        .iter()
+        .map(|action| {
+            let effective = action_choices.get(&action.id).unwrap_or(choice);
+            let gpu = gpu_for_choice(all_gpus, effective);
+            Ok(ActionTarget {
+                id: action.id.clone(),
+                dri_prime: assigned_dri_prime(effective, gpu)
+                    .with_context(|| format!("desktop action '{}'", action.name))?,
+                gpu,
+            })
+        })
+        .collect()
+}
+
+/// Reads back what is currently on disk for `app` and compares it with the
+/// override `apply_launcher_override` would write for `choice`.
+pub fn read_launcher_state(
+    app: &DesktopApp,
+    choice: &GpuChoice,
+    action_choices: &BTreeMap<String, GpuChoice>,
+    steam_accounts: &SteamAccountScope,
+    selected_gpu: Option<&GpuInfo>,
+    all_gpus: &[GpuInfo],
+    use_env_wrapper: bool,
+) -> Result<OverrideState> {
+    let dri_prime = assigned_dri_prime(choice, selected_gpu)?;
+
+    if let (true, Some(app_id)) = (app.is_steam_game, app.steam_app_id.as_deref()) {
+        return steam::read_override_state(app_id, dri_prime, steam_accounts);
+    }
+    if let (true, Some(shortcut)) = (app.is_steam_game, app.steam_shortcut_id) {
+        return steam::read_shortcut_state(shortcut, dri_prime, steam_accounts);
+    }
+
+    if let (true, Some(_), Some(app_name)) = (
+        app.is_heroic_game,
+        app.heroic_platform.as_deref(),
+        app.heroic_app_name.as_deref(),
+    ) {
+        let heroic_env = match dri_prime {
+            None => Vec::new(),
+            Some(index) => build_env_pairs(index, false, selected_gpu, all_gpus, use_env_wrapper),
+        };
+        return heroic::read_override_state(app_name, &heroic_env);
+    }
+
+    if let (true, Some(_), Some((path, in_place))) = (
+        app.is_bottles_app,
+        app.bottle_program.as_deref(),
+        bottles_program_launcher(app),
+    ) {
+        let entry = fs::read_to_string(&path)
+            .ok()
+            .map(|content| DesktopEntry::parse(&content))
+            .filter(|entry| {
+                if in_place {
+                    entry.get(DESKTOP_ENTRY, BOTTLES_EXEC_KEY).is_some()
+                } else {
+                    entry.get_bool(DESKTOP_ENTRY, "X-Kaede-Managed")
+                }
+            });
+        let managed_exec = entry.and_then(|entry| entry.get(DESKTOP_ENTRY, "Exec"));
+        return Ok(OverrideState {
+            in_sync: exec_matches(
+                managed_exec.as_deref(),
+                dri_prime,
+                selected_gpu,
+                all_gpus,
+                use_env_wrapper,
+            ),
+            observed_dri_prime: managed_exec.as_deref().and_then(parse_dri_prime),
+        });
+    }
+
+    if let (true, Some(bottle), None) = (
+        app.is_bottles_app,
+        app.bottle_name.as_deref(),
+        app.bottle_program.as_deref(),
+    ) {
+        let bottle_env = match dri_prime {
+            None => Vec::new(),
+            Some(index) => build_env_pairs(index, false, selected_gpu, all_gpus, use_env_wrapper),
+        };
+        return bottles::read_override_state(bottle, &bottle_env);
+    }
+
+    if let (true, Some(slug)) = (app.is_lutris_game, app.lutris_slug.as_deref()) {
+        let lutris_env = match dri_prime {
+            None => Vec::new(),
+            Some(index) => build_env_pairs(index, false, selected_gpu, all_gpus, use_env_wrapper),
+        };
+        return lutris::read_override_state(slug, &lutris_env);
+    }
+
+    if let (true, Some(app_id)) = (app.is_flatpak, app.flatpak_app_id.as_deref()) {
+        return read_flatpak_state(app_id, dri_prime, selected_gpu, all_gpus, use_env_wrapper);
+    }
+
+    let target = user_launcher_path(&app.desktop_id);
+    let entry = if file_contains_marker(&target) {
+        fs::read_to_string(&target)
+            .ok()
+            .map(|content| DesktopEntry::parse(&content))
+    } else {
+        None
+    };
+    let managed_exec = entry
+        .as_ref()
+        .and_then(|e| e.get(DESKTOP_ENTRY, "Exec"));
+
+    let actions = action_targets(app, choice, action_choices, all_gpus)?;
+    let in_sync = exec_matches(
+        managed_exec.as_deref(),
+        dri_prime,
+        selected_gpu,
+        all_gpus,
+        use_env_wrapper,
+    ) && actions.iter().all(|action| {
+        let action_exec = entry
+            .as_ref()
+            .and_then(|e| e.get(&action_group(&action.id), "Exec"));
+        exec_matches(
+            action_exec.as_deref(),
+            action.dri_prime,
+            action.gpu,
+            all_gpus,
+            use_env_wrapper,
+        )
+    });
+
+    Ok(OverrideState {
+        in_sync: in_sync && !app.override_stale,
+        observed_dri_prime: managed_exec.as_deref().and_then(parse_dri_prime),
+    })
+}
+
+fn exec_matches(
+    exec: Option<&str>,
+    dri_prime: Option<usize>,
+    gpu: Option<&GpuInfo>,
+    all_gpus: &[GpuInfo],
+    use_env_wrapper: bool,
+) -> bool {
+    match (dri_prime, exec) {
+        (None, exec) => exec.and_then(parse_dri_prime).is_none(),
+        (Some(_), None) => false,
+        (Some(index), Some(exec)) => {
+            build_env_pairs(index, is_steam_exec(exec), gpu, all_gpus, use_env_wrapper)
+                .iter()
+                .all(|kv| exec.contains(kv.as_str()))
+        }
+    }
+}
 
+/// Never falls back to a positional index: if the assigned GPU is gone we
+/// would silently target whatever card now has that number.
+fn assigned_dri_prime(choice: &GpuChoice, selected_gpu: Option<&GpuInfo>) -> Result<Option<usize>> {
     match choice {
-        GpuChoice::Default => remove_kaede_override_if_present(&target),
-        GpuChoice::Gpu(index) => write_override(app, *index, selected_gpu, all_gpus, use_env_wrapper, &target),
+        GpuChoice::Default => Ok(None),
+        _ => selected_gpu
+            .and_then(|gpu| gpu.dri_prime_index)
+            .map(Some)
+            .with_context(|| format!("assigned GPU {} is missing; not applying", choice.label())),
+    }
+}
+
+fn read_flatpak_state(
+    app_id: &str,
+    dri_prime: Option<usize>,
+    selected_gpu: Option<&GpuInfo>,
+    all_gpus: &[GpuInfo],
+    use_env_wrapper: bool,
+) -> Result<OverrideState> {
+    let output = Command::new("flatpak")
+        .args(["override", "--user", "--show", app_id])
+        .output()
+        .with_context(|| format!("failed to execute flatpak override --show for {app_id}"))?;
+    if !output.status.success() {
+        anyhow::bail!("flatpak override --show failed for {app_id}");
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut in_environment = false;
+    let mut env = Vec::new();
+    for line in stdout.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_environment = line == "[Environment]";
+            continue;
+        }
+        if in_environment && line.contains('=') {
+            env.push(line.to_string());
+        }
+    }
+
+    let in_sync = match dri_prime {
+        None => !env.iter().any(|kv| {
+            let key = kv.split_once('=').map(|(k, _)| k).unwrap_or_default();
+            MANAGED_ENV.contains(&key)
+        }),
+        Some(index) => build_env_pairs(index, false, selected_gpu, all_gpus, use_env_wrapper)
+            .iter()
+            .all(|kv| env.contains(kv)),
+    };
+
+    Ok(OverrideState {
+        in_sync,
+        observed_dri_prime: parse_dri_prime(&env.join(" ")),
+    })
+}
+
+/// `KEY=value` pairs as environment entries, plus the marker unless empty.
+pub fn managed_env_entries(env_vars: &[String]) -> Vec<(String, String)> {
+    let mut desired = env_vars
+        .iter()
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect::<Vec<_>>();
+    if !desired.is_empty() {
+        desired.push((MANAGED_ENV_MARKER.to_string(), "1".to_string()));
+    }
+    desired
+}
+
+/// Compares an environment block read from a launcher config with `env_vars`.
+pub fn env_override_state(env: &[(String, String)], env_vars: &[String]) -> OverrideState {
+    let value = |key: &str| env.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+    let managed = value(MANAGED_ENV_MARKER).is_some();
+    let in_sync = if env_vars.is_empty() {
+        !managed
+    } else {
+        managed_env_entries(env_vars)
+            .iter()
+            .all(|(k, v)| value(k) == Some(v.as_str()))
+    };
+
+    OverrideState {
+        in_sync,
+        observed_dri_prime: value("DRI_PRIME")
+            .filter(|_| managed)
+            .and_then(|v| v.parse().ok()),
     }
 }
 
 fn apply_flatpak_override(
     app_id: &str,
-    choice: &GpuChoice,
+    dri_prime: Option<usize>,
     selected_gpu: Option<&GpuInfo>,
     all_gpus: &[GpuInfo],
     use_env_wrapper: bool,
 ) -> Result<()> {
+    // Where `flatpak override --user` keeps its keyfile.
+    backup::snapshot(&data_home().join("flatpak/overrides").join(app_id))?;
     let mut cmd = Command::new("flatpak");
     cmd.args(["override", "--user"]);
 
-    match choice {
-        GpuChoice::Default => {
-            cmd.args([
-                "--unset-env=DRI_PRIME",
-                "--unset-env=PRESSURE_VESSEL_IMPORT_VARS",
-                "--unset-env=__NV_PRIME_RENDER_OFFLOAD",
-                "--unset-env=__GLX_VENDOR_LIBRARY_NAME",
-                "--unset-env=__VK_LAYER_NV_optimus",
-                "--unset-env=MESA_VK_DEVICE_SELECT",
-                "--unset-env=MESA_VK_DEVICE_SELECT_FORCE_DEFAULT_DEVICE",
-                "--unset-env=KAEDE_GPU_MANAGED",
-                "--unset-env=DXVK_FILTER_DEVICE_NAME",
-                app_id,
-            ]);
+    match dri_prime {
+        None => {
+            for key in MANAGED_ENV {
+                cmd.arg(format!("--unset-env={key}"));
+            }
+            cmd.arg(app_id);
         }
-        GpuChoice::Gpu(index) => {
-            for env in build_env_pairs(*index, false, selected_gpu, all_gpus, use_env_wrapper) {
+        Some(index) => {
+            for env in build_env_pairs(index, false, selected_gpu, all_gpus, use_env_wrapper) {
                 cmd.arg(format!("--env={env}"));
             }
             cmd.arg(app_id);
@@ -132,11 +544,12 @@ fn apply_flatpak_override(
 
 fn write_override(
     app: &DesktopApp,
-    index: usize,
+    dri_prime: Option<usize>,
     selected_gpu: Option<&GpuInfo>,
-    all_gpus: &[GpuInfo],
-    use_env_wrapper: bool,
+    actions: &[ActionTarget],
     target: &Path,
+    wrap: &ExecWrapper,
+    snap_name: Option<&str>,
 ) -> Result<()> {
     if app.path == target && !file_contains_marker(target) {
         anyhow::bail!(
@@ -150,35 +563,206 @@ fn write_override(
             .with_context(|| format!("failed to create {}", parent.display()))?;
     }
 
-    let source_content = fs::read_to_string(&app.path).unwrap_or_default();
-    let original_exec = desktop_exec_value(&source_content)
-        .filter(|v| !v.trim().is_empty())
-        .unwrap_or_else(|| app.exec.clone());
-    let wrapped_exec = wrap_exec_for_gpu(&original_exec, index, selected_gpu, all_gpus, use_env_wrapper);
-    let content = rewrite_desktop_override_content(&source_content, &wrapped_exec, app);
+    // When our own override shadows the system entry, rebuild from the
+    // recorded source instead of wrapping the already wrapped Exec again.
+    let source_path = if app.path == target {
+        app.override_source
+            .clone()
+            .with_context(|| format!("no source desktop file recorded for {}", target.display()))?
+    } else {
+        app.path.clone()
+    };
+
+    let source_content = fs::read_to_string(&source_path).unwrap_or_default();
+    let mut entry = DesktopEntry::parse(&source_content);
+    if !entry.has_group(DESKTOP_ENTRY) {
+        entry.set(DESKTOP_ENTRY, "Type", "Application");
+        entry.set(DESKTOP_ENTRY, "Name", &app.name);
+        entry.set(
+            DESKTOP_ENTRY,
+            "Icon",
+            app.icon.as_deref().unwrap_or("application-x-executable"),
+        );
+        entry.set(DESKTOP_ENTRY, "Terminal", "false");
+    }
+
+    let original_args = entry
+        .get(DESKTOP_ENTRY, "Exec")
+        .map(|value| parse_exec_lossy(&value))
+        .filter(|args| !args.is_empty())
+        .unwrap_or_else(|| parse_exec_lossy(&app.exec));
+    let main_args = match dri_prime {
+        Some(index) => wrap(&original_args, index, selected_gpu),
+        None => original_args,
+    };
+    entry.set(DESKTOP_ENTRY, "Exec", &serialize_exec(&main_args));
 
-    fs::write(target, content)
+    for action in actions {
+        let group = action_group(&action.id);
+        let (Some(index), Some(exec)) = (action.dri_prime, entry.get(&group, "Exec")) else {
+            continue;
+        };
+        let wrapped = wrap(&parse_exec_lossy(&exec), index, action.gpu);
+        entry.set(&group, "Exec", &serialize_exec(&wrapped));
+    }
+
+    for key in ["X-Kaede-Managed", "X-Kaede-Source", "X-Kaede-Source-Hash", SNAP_MARKER_KEY] {
+        entry.remove(DESKTOP_ENTRY, key);
+    }
+    entry.set(DESKTOP_ENTRY, "X-Kaede-Managed", "true");
+    if let Some(snap_name) = snap_name {
+        entry.set(DESKTOP_ENTRY, SNAP_MARKER_KEY, snap_name);
+    }
+    entry.set(
+        DESKTOP_ENTRY,
+        "X-Kaede-Source",
+        &source_path.to_string_lossy(),
+    );
+    entry.set(DESKTOP_ENTRY, "X-Kaede-Source-Hash", &source_hash(&source_content));
+
+    backup::snapshot(target)?;
+    fs::write(target, entry.to_string())
         .with_context(|| format!("failed to write launcher {}", target.display()))?;
     debug!(target = %target.display(), "desktop override launcher written");
     Ok(())
 }
 
+/// Writes a launcher that has no system `.desktop` file behind it.
+fn write_generated_launcher(app: &DesktopApp, target: &Path, args: &[String]) -> Result<()> {
+    if target.exists() && !file_contains_marker(target) {
+        anyhow::bail!(
+            "refusing to overwrite unmanaged local desktop file: {}",
+            target.display()
+        );
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+
+    let mut entry = DesktopEntry::default();
+    entry.set(DESKTOP_ENTRY, "Type", "Application");
+    entry.set(DESKTOP_ENTRY, "Name", &app.name);
+    entry.set(
+        DESKTOP_ENTRY,
+        "Icon",
+        app.icon.as_deref().unwrap_or("application-x-executable"),
+    );
+    entry.set(DESKTOP_ENTRY, "Exec", &serialize_exec(args));
+    entry.set(DESKTOP_ENTRY, "Terminal", "false");
+    entry.set(DESKTOP_ENTRY, "X-Kaede-Managed", "true");
+
+    backup::snapshot(target)?;
+    fs::write(target, entry.to_string())
+        .with_context(|| format!("failed to write launcher {}", target.display()))?;
+    debug!(target = %target.display(), "generated launcher written");
+    Ok(())
+}
+
+/// The launcher that carries a Bottles program's assignment, and whether it is
+/// Bottles' own launcher, which is edited in place. Programs without a launcher
+/// get one from Kaede; `None` for a launcher outside the user's directory.
+fn bottles_program_launcher(app: &DesktopApp) -> Option<(PathBuf, bool)> {
+    let target = user_launcher_path(&app.desktop_id);
+    if app.path == target {
+        Some((target, true))
+    } else if bottles::has_launcher(app) {
+        None
+    } else {
+        Some((user_launcher_path(&bottles::generated_launcher_id(&app.desktop_id)), false))
+    }
+}
+
+/// Wraps the `Exec` of a launcher Bottles created, keeping the original so that
+/// clearing the assignment puts it back instead of removing the menu entry.
+fn edit_bottles_launcher(
+    path: &Path,
+    dri_prime: Option<usize>,
+    wrap: &dyn Fn(&[String], usize) -> Vec<String>,
+) -> Result<()> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("failed to read launcher {}", path.display()))?;
+    let mut entry = DesktopEntry::parse(&content);
+    let original = entry
+        .get(DESKTOP_ENTRY, BOTTLES_EXEC_KEY)
+        .or_else(|| entry.get(DESKTOP_ENTRY, "Exec"))
+        .with_context(|| format!("launcher {} has no Exec", path.display()))?;
+
+    match dri_prime {
+        Some(index) => {
+            let wrapped = wrap(&parse_exec_lossy(&original), index);
+            entry.set(DESKTOP_ENTRY, "Exec", &serialize_exec(&wrapped));
+            entry.set(DESKTOP_ENTRY, BOTTLES_EXEC_KEY, &original);
+        }
+        None => {
+            entry.set(DESKTOP_ENTRY, "Exec", &original);
+            entry.remove(DESKTOP_ENTRY, BOTTLES_EXEC_KEY);
+        }
+    }
+    let updated = entry.to_string();
+    if updated == content {
+        return Ok(());
+    }
+
+    backup::snapshot(path)?;
+    fs::write(path, updated)
+        .with_context(|| format!("failed to write launcher {}", path.display()))?;
+    debug!(path = %path.display(), "Bottles launcher updated");
+    Ok(())
+}
+
+fn action_group(action_id: &str) -> String {
+    format!("Desktop Action {action_id}")
+}
+
 fn wrap_exec_for_gpu(
-    exec: &str,
+    args: &[String],
     index: usize,
     selected_gpu: Option<&GpuInfo>,
     all_gpus: &[GpuInfo],
     use_env_wrapper: bool,
-) -> String {
-    let is_steam = is_steam_exec(exec);
+) -> Vec<String> {
+    let is_steam = is_steam_exec(&args.join(" "));
     let env_pairs = build_env_pairs(index, is_steam, selected_gpu, all_gpus, use_env_wrapper);
 
-    if looks_like_flatpak_run(exec) {
-        return wrap_flatpak_run_with_env(exec, &env_pairs);
+    if let Some(run_at) = flatpak_run_position(args) {
+        let mut wrapped = args.to_vec();
+        wrapped.splice(
+            run_at + 1..run_at + 1,
+            env_pairs.iter().map(|kv| format!("--env={kv}")),
+        );
+        return wrapped;
+    }
+
+    let mut wrapped = Vec::with_capacity(env_pairs.len() + args.len() + 1);
+    if use_env_wrapper {
+        wrapped.push("env".to_string());
     }
+    wrapped.extend(env_pairs);
+    wrapped.extend(args.iter().cloned());
+    wrapped
+}
 
-    let prefix = if use_env_wrapper { "env " } else { "" };
-    format!("{}{} {}", prefix, env_pairs.join(" "), exec)
+/// Snap launchers usually start with `env BAMF_DESKTOP_FILE_HINT=... /snap/bin/app`.
+/// The GPU variables join that `env` call instead of nesting a second one; `snap run`
+/// passes them through to the confined app.
+fn wrap_snap_exec(
+    args: &[String],
+    index: usize,
+    selected_gpu: Option<&GpuInfo>,
+    all_gpus: &[GpuInfo],
+) -> Vec<String> {
+    let env_pairs = build_env_pairs(index, false, selected_gpu, all_gpus, true);
+    let rest = match args.first() {
+        Some(first) if first == "env" => &args[1..],
+        _ => args,
+    };
+
+    let mut wrapped = Vec::with_capacity(env_pairs.len() + rest.len() + 1);
+    wrapped.push("env".to_string());
+    wrapped.extend(env_pairs);
+    wrapped.extend(rest.iter().cloned());
+    wrapped
 }
 
 fn build_env_pairs(
@@ -248,14 +832,14 @@ fn build_env_pairs(
 }
 
 fn steam_env_vars(
-    choice: &GpuChoice,
+    dri_prime: Option<usize>,
     selected_gpu: Option<&GpuInfo>,
     all_gpus: &[GpuInfo],
     use_env_wrapper: bool,
 ) -> Vec<String> {
-    match choice {
-        GpuChoice::Default => Vec::new(),
-        GpuChoice::Gpu(index) => build_env_pairs(*index, true, selected_gpu, all_gpus, use_env_wrapper),
+    match dri_prime {
+        None => Vec::new(),
+        Some(index) => build_env_pairs(index, true, selected_gpu, all_gpus, use_env_wrapper),
     }
 }
 
@@ -321,38 +905,11 @@ fn mesa_vk_device_select_from_pci(pci: Option<&str>) -> Option<String> {
     Some(format!("pci-{normalized}!"))
 }
 
-fn looks_like_flatpak_run(exec: &str) -> bool {
-    let parts = exec.split_whitespace().collect::<Vec<_>>();
-    parts.windows(2).any(|w| {
-        let a = w[0];
-        let b = w[1];
-        (a == "flatpak" || a.ends_with("/flatpak")) && b == "run"
-    })
-}
-
-fn wrap_flatpak_run_with_env(exec: &str, env_pairs: &[String]) -> String {
-    let mut parts = exec
-        .split_whitespace()
-        .map(std::string::ToString::to_string)
-        .collect::<Vec<_>>();
-
-    if let Some(i) = parts.windows(2).position(|w| {
-        let a = w[0].as_str();
-        let b = w[1].as_str();
-        (a == "flatpak" || a.ends_with("/flatpak")) && b == "run"
-    }) {
-        let insert_at = i + 2;
-        let mut env_args = env_pairs
-            .iter()
-            .map(|kv| format!("--env={kv}"))
-            .collect::<Vec<_>>();
-        for (offset, arg) in env_args.drain(..).enumerate() {
-            parts.insert(insert_at + offset, arg);
-        }
-        return parts.join(" ");
-    }
-
-    format!("env {} {}", env_pairs.join(" "), exec)
+/// Index of the `run` argument in a `flatpak run ...` command.
+fn flatpak_run_position(args: &[String]) -> Option<usize> {
+    args.windows(2)
+        .position(|w| (w[0] == "flatpak" || w[0].ends_with("/flatpak")) && w[1] == "run")
+        .map(|i| i + 1)
 }
 
 fn is_steam_exec(exec: &str) -> bool {
@@ -362,95 +919,78 @@ fn is_steam_exec(exec: &str) -> bool {
         || (lower.contains("steam") && lower.contains("steam://run"))
 }
 
-fn desktop_exec_value(content: &str) -> Option<String> {
-    content.lines().find_map(|line| {
-        line.strip_prefix("Exec=")
-            .map(str::trim)
-            .filter(|v| !v.is_empty())
-            .map(std::string::ToString::to_string)
-    })
-}
-
-fn rewrite_desktop_override_content(source: &str, wrapped_exec: &str, app: &DesktopApp) -> String {
-    if source.trim().is_empty() {
-        let icon = app.icon.as_deref().unwrap_or("application-x-executable");
-        return format!(
-            "[Desktop Entry]\nType=Application\nName={}\nIcon={}\nExec={}\nTerminal=false\n{}\n",
-            app.name, icon, wrapped_exec, KAEDE_MARKER
-        );
-    }
-
-    let mut lines = Vec::new();
-    let mut replaced_exec = false;
-    let mut has_marker = false;
-    let mut in_desktop_entry = false;
-    let mut inserted_exec_in_section = false;
-
-    for line in source.lines() {
-        if line.trim_start().starts_with('[') {
-            if in_desktop_entry && !replaced_exec && !inserted_exec_in_section {
-                lines.push(format!("Exec={wrapped_exec}"));
-                replaced_exec = true;
-                inserted_exec_in_section = true;
-            }
-            in_desktop_entry = line.trim() == "[Desktop Entry]";
-            lines.push(line.to_string());
-            continue;
-        }
-
-        if line.starts_with("X-Kaede-Managed=") {
-            has_marker = true;
-            lines.push(KAEDE_MARKER.to_string());
-            continue;
-        }
-
-        if in_desktop_entry && line.starts_with("Exec=") && !replaced_exec {
-            lines.push(format!("Exec={wrapped_exec}"));
-            replaced_exec = true;
-            inserted_exec_in_section = true;
-            continue;
-        }
-
-        lines.push(line.to_string());
-    }
-
-    if !replaced_exec {
-        let mut insert_at = 0usize;
-        for (idx, line) in lines.iter().enumerate() {
-            if line.trim() == "[Desktop Entry]" {
-                insert_at = idx + 1;
-                break;
-            }
-        }
-        lines.insert(insert_at, format!("Exec={wrapped_exec}"));
-    }
-
-    if !has_marker {
-        lines.push(KAEDE_MARKER.to_string());
+/// Records the source of an override written before Kaede tracked it, so later
+/// changes to the source can be detected. Returns whether the file was updated.
+pub fn record_override_source(app: &DesktopApp) -> Result<bool> {
+    let Some(source_path) = app.override_source.as_deref() else {
+        return Ok(false);
+    };
+    let content = fs::read_to_string(&app.path)
+        .with_context(|| format!("failed to read launcher {}", app.path.display()))?;
+    let mut entry = DesktopEntry::parse(&content);
+    if !entry.get_bool(DESKTOP_ENTRY, "X-Kaede-Managed")
+        || entry.get(DESKTOP_ENTRY, "X-Kaede-Source-Hash").is_some()
+    {
+        return Ok(false);
     }
+    let source_content = fs::read_to_string(source_path)
+        .with_context(|| format!("failed to read {}", source_path.display()))?;
+
+    entry.set(DESKTOP_ENTRY, "X-Kaede-Source", &source_path.to_string_lossy());
+    entry.set(DESKTOP_ENTRY, "X-Kaede-Source-Hash", &source_hash(&source_content));
+    let _backup = backup::begin(&format!("Record the source of {}", app.name));
+    backup::snapshot(&app.path)?;
+    fs::write(&app.path, entry.to_string())
+        .with_context(|| format!("failed to write launcher {}", app.path.display()))?;
+    debug!(path = %app.path.display(), "override source recorded");
+    Ok(true)
+}
 
-    let mut out = lines.join("\n");
-    out.push('\n');
-    out
+/// Removes the Kaede-managed user launcher for `desktop_id`, leaving unmanaged files alone.
+pub fn remove_desktop_override(desktop_id: &str) -> Result<()> {
+    remove_kaede_override_if_present(&user_launcher_path(desktop_id))
 }
 
 fn remove_kaede_override_if_present(path: &Path) -> Result<()> {
     if path.exists() && file_contains_marker(path) {
+        backup::snapshot(path)?;
         fs::remove_file(path)
             .with_context(|| format!("failed to remove launcher {}", path.display()))?;
     }
     Ok(())
 }
 
+/// Removes a Snap override, leaving alone one that was written for a different
+/// snap instance under the same desktop ID.
+fn remove_snap_override(path: &Path, snap_name: &str) -> Result<()> {
+    if !file_contains_marker(path) {
+        return Ok(());
+    }
+    let owner = fs::read_to_string(path)
+        .ok()
+        .and_then(|content| DesktopEntry::parse(&content).get(DESKTOP_ENTRY, SNAP_MARKER_KEY));
+    if owner.as_deref().is_some_and(|owner| owner != snap_name) {
+        warn!(
+            path = %path.display(),
+            snap = snap_name,
+            owner = ?owner,
+            "launcher override belongs to another snap; not removing"
+        );
+        return Ok(());
+    }
+    backup::snapshot(path)?;
+    fs::remove_file(path)
+        .with_context(|| format!("failed to remove launcher {}", path.display()))?;
+    debug!(snap = snap_name, "snap launcher override removed");
+    Ok(())
+}
+
 fn file_contains_marker(path: &Path) -> bool {
     fs::read_to_string(path)
         .map(|body| body.contains(KAEDE_MARKER))
         .unwrap_or(false)
 }
 
-fn user_launcher_path(desktop_id: &str) -> PathBuf {
-    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-    PathBuf::from(home)
-        .join(".local/share/applications")
-        .join(desktop_id)
+pub fn user_launcher_path(desktop_id: &str) -> PathBuf {
+    user_applications_dir().join(desktop_id)
 }
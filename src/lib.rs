@@ -0,0 +1,5 @@
+//! Code shared by the `kaede` GUI and the privileged `kaede-helper`.
+
+pub mod backup;
+pub mod nvidia;
+pub mod transaction;
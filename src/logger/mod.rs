@@ -5,6 +5,7 @@ pub fn init() {
 
     let _ = tracing_subscriber::fmt()
         .with_env_filter(filter)
+        .with_writer(std::io::stderr)
         .with_target(false)
         .with_thread_ids(false)
         .with_thread_names(false)
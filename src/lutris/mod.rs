@@ -0,0 +1,215 @@
+use crate::backup;
+use crate::desktop::data_home;
+use crate::launcher::{self, MANAGED_ENV};
+use crate::models::{DesktopApp, OverrideState};
+use crate::yaml;
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use tracing::{debug, info};
+
+/// Per-game environment lives under `system: env:` in the game's YAML config.
+const SYSTEM_ENV: [&str; 2] = ["system", "env"];
+
+/// Returns `Some(slug)` for a Lutris launcher (`lutris:rungame/<slug>` or
+/// `lutris:rungameid/<id>`), with `None` inside when the slug cannot be resolved.
+pub fn game_from_exec(desktop_id: &str, exec: &str) -> Option<Option<String>> {
+    let uri = exec
+        .split_whitespace()
+        .map(|part| part.trim_matches(|c| c == '"' || c == '\''))
+        .find(|part| part.starts_with("lutris:rungame"))?;
+
+    if let Some(slug) = uri.strip_prefix("lutris:rungame/") {
+        return Some(Some(slug.to_string()).filter(|s| !s.is_empty()));
+    }
+    let game_id = uri.strip_prefix("lutris:rungameid/").unwrap_or_default();
+    Some(slug_from_desktop_id(desktop_id, game_id))
+}
+
+/// Lutris names its shortcuts `net.lutris.<slug>-<id>.desktop` (older
+/// versions used `lutris-<slug>-<id>.desktop`).
+fn slug_from_desktop_id(desktop_id: &str, game_id: &str) -> Option<String> {
+    let stem = desktop_id.strip_suffix(".desktop").unwrap_or(desktop_id);
+    let stem = stem
+        .strip_prefix("net.lutris.")
+        .or_else(|| stem.strip_prefix("lutris-"))?;
+    let (slug, id) = stem.rsplit_once('-')?;
+    if slug.is_empty() || !(game_id.is_empty() || id == game_id) {
+        return None;
+    }
+    Some(slug.to_string())
+}
+
+/// Games configured in Lutris that have no desktop shortcut.
+pub fn scan_games(known: &[DesktopApp]) -> Vec<DesktopApp> {
+    let mut seen = known
+        .iter()
+        .filter_map(|app| app.lutris_slug.clone())
+        .collect::<BTreeSet<_>>();
+
+    let mut games = Vec::new();
+    for path in game_config_files() {
+        let Some(slug) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .map(slug_from_config_stem)
+        else {
+            continue;
+        };
+        if !seen.insert(slug.clone()) {
+            continue;
+        }
+
+        let content = fs::read_to_string(&path).unwrap_or_default();
+        let name = yaml::get(&content, &["name"])
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| title_from_slug(&slug));
+
+        games.push(DesktopApp {
+            desktop_id: format!("lutris:{slug}"),
+            path: path.clone(),
+            name,
+            icon: Some(format!("lutris_{slug}")),
+            exec: format!("lutris lutris:rungame/{slug}"),
+            is_steam_game: false,
+            steam_app_id: None,
+            steam_shortcut_id: None,
+            is_heroic_game: false,
+            heroic_platform: None,
+            heroic_app_name: None,
+            is_lutris_game: true,
+            lutris_slug: Some(slug),
+            is_bottles_app: false,
+            bottle_name: None,
+            bottle_program: None,
+            is_flatpak: false,
+            flatpak_app_id: None,
+            is_snap: false,
+            snap_name: None,
+            override_source: None,
+            override_stale: false,
+            actions: Vec::new(),
+        });
+    }
+    games
+}
+
+pub fn apply_lutris_launch_env(slug: &str, env_vars: &[String]) -> Result<()> {
+    let files = find_game_configs(slug);
+    if files.is_empty() {
+        anyhow::bail!("Lutris config not found for game {}", slug);
+    }
+
+    let desired = launcher::managed_env_entries(env_vars);
+    for path in files {
+        let raw = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read Lutris config {}", path.display()))?;
+        let updated = yaml::set_entries(&raw, &SYSTEM_ENV, &MANAGED_ENV, &desired)
+            .with_context(|| format!("failed to update Lutris config {}", path.display()))?;
+        if updated == raw {
+            info!(path = %path.display(), game = slug, "Lutris env already in desired state");
+            continue;
+        }
+
+        backup::snapshot(&path)?;
+        fs::write(&path, updated)
+            .with_context(|| format!("failed to write Lutris config {}", path.display()))?;
+        info!(path = %path.display(), game = slug, "Lutris env updated");
+    }
+    Ok(())
+}
+
+/// `apply_lutris_launch_env` writes every config of the game, so all of them must match.
+pub fn read_override_state(slug: &str, env_vars: &[String]) -> Result<OverrideState> {
+    let files = find_game_configs(slug);
+    if files.is_empty() {
+        anyhow::bail!("Lutris config not found for game {slug}");
+    }
+
+    let mut state = OverrideState {
+        in_sync: true,
+        observed_dri_prime: None,
+    };
+    for path in files {
+        let raw = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read Lutris config {}", path.display()))?;
+        let file = launcher::env_override_state(&yaml::entries(&raw, &SYSTEM_ENV), env_vars);
+        state.in_sync &= file.in_sync;
+        // Configs are sorted newest first.
+        state.observed_dri_prime = state.observed_dri_prime.or(file.observed_dri_prime);
+    }
+    Ok(state)
+}
+
+fn game_config_dirs() -> Vec<PathBuf> {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    vec![
+        data_home().join("lutris/games"),
+        PathBuf::from(&home).join(".config/lutris/games"),
+        PathBuf::from(&home).join(".var/app/net.lutris.Lutris/data/lutris/games"),
+    ]
+}
+
+fn game_config_files() -> Vec<PathBuf> {
+    let mut out = Vec::new();
+    for dir in game_config_dirs() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("yml") {
+                out.push(path);
+            }
+        }
+    }
+    out.sort();
+    out.dedup();
+    out
+}
+
+/// Game configs are saved as `<slug>-<unix timestamp>.yml`.
+fn slug_from_config_stem(stem: &str) -> String {
+    match stem.rsplit_once('-') {
+        Some((slug, stamp))
+            if !slug.is_empty() && !stamp.is_empty() && stamp.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            slug.to_string()
+        }
+        _ => stem.to_string(),
+    }
+}
+
+fn find_game_configs(slug: &str) -> Vec<PathBuf> {
+    let mut files = game_config_files()
+        .into_iter()
+        .filter(|path| {
+            path.file_stem()
+                .and_then(|s| s.to_str())
+                .is_some_and(|stem| slug_from_config_stem(stem) == slug)
+        })
+        .collect::<Vec<_>>();
+    // A reinstalled game leaves older configs behind; check the newest first.
+    files.sort_by_key(|path| {
+        std::cmp::Reverse(fs::metadata(path).and_then(|m| m.modified()).ok())
+    });
+    if files.len() > 1 {
+        debug!(game = slug, count = files.len(), "multiple Lutris configs match game");
+    }
+    files
+}
+
+fn title_from_slug(slug: &str) -> String {
+    slug.split('-')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -1,19 +1,31 @@
+mod bottles;
+mod cli;
 mod config;
+mod datetime;
 mod desktop;
 mod gpu;
 mod heroic;
 mod launcher;
 mod logger;
+mod lutris;
 mod models;
-mod nvidia;
+mod reconcile;
 mod steam;
 mod ui;
 mod updates;
+mod yaml;
 
 use adw::prelude::*;
+use kaede::{backup, nvidia, transaction};
 
 fn main() {
     logger::init();
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
+    }
+
     let app = adw::Application::builder()
         .application_id("com.kaede.gpu-manager")
         .build();
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
@@ -8,6 +8,8 @@ pub struct GpuInfo {
     pub name: String,
     pub driver: Option<String>,
     pub pci_slot: Option<String>,
+    pub vendor_id: Option<String>,
+    pub device_id: Option<String>,
     pub render_node: Option<String>,
     pub dri_prime_index: Option<usize>,
     pub renderer: Option<String>,
@@ -21,29 +23,59 @@ impl GpuInfo {
             .filter(|v| !v.trim().is_empty())
             .unwrap_or(&self.name);
 
-        let mut cleaned = source.trim().to_string();
+        clean_gpu_name(source)
+    }
 
-        if let Some((_, rhs)) = cleaned.split_once(':') {
-            cleaned = rhs.trim().to_string();
+    /// Identity that survives `cardN` reordering, used to key saved assignments.
+    pub fn stable_id(&self) -> GpuId {
+        let cleaned = clean_gpu_name(&self.name);
+        GpuId {
+            pci_slot: self.pci_slot.clone(),
+            vendor_id: self.vendor_id.clone(),
+            device_id: self.device_id.clone(),
+            name: if cleaned.is_empty() {
+                self.name.clone()
+            } else {
+                cleaned
+            },
         }
+    }
+}
 
-        for suffix in ["(TM)", "(tm)", "(R)", "(r)", "Corporation", "Inc."] {
-            cleaned = cleaned.replace(suffix, "");
-        }
+pub fn clean_gpu_name(source: &str) -> String {
+    let mut cleaned = source.trim().to_string();
 
-        for splitter in [" (", ", ", " [", " / "] {
-            if let Some((left, _)) = cleaned.split_once(splitter) {
-                cleaned = left.trim().to_string();
-            }
-        }
+    if let Some((_, rhs)) = cleaned.split_once(':') {
+        cleaned = rhs.trim().to_string();
+    }
+
+    for suffix in ["(TM)", "(tm)", "(R)", "(r)", "Corporation", "Inc."] {
+        cleaned = cleaned.replace(suffix, "");
+    }
 
-        if let Some(pos) = cleaned.find("Series") {
-            let keep = &cleaned[..pos + "Series".len()];
-            cleaned = keep.trim().to_string();
+    for splitter in [" (", ", ", " [", " / "] {
+        if let Some((left, _)) = cleaned.split_once(splitter) {
+            cleaned = left.trim().to_string();
         }
+    }
 
-        cleaned.split_whitespace().collect::<Vec<_>>().join(" ")
+    if let Some(pos) = cleaned.find("Series") {
+        let keep = &cleaned[..pos + "Series".len()];
+        cleaned = keep.trim().to_string();
     }
+
+    cleaned.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GpuId {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pci_slot: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vendor_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+    pub name: String,
 }
 
 #[derive(Debug, Clone)]
@@ -55,18 +87,68 @@ pub struct DesktopApp {
     pub exec: String,
     pub is_steam_game: bool,
     pub steam_app_id: Option<String>,
+    /// Non-Steam game added to Steam, identified by its `shortcuts.vdf` app ID.
+    pub steam_shortcut_id: Option<u32>,
     pub is_heroic_game: bool,
     pub heroic_platform: Option<String>,
     pub heroic_app_name: Option<String>,
+    pub is_lutris_game: bool,
+    /// Lutris game slug, which also names its `games/<slug>-<timestamp>.yml` config.
+    pub lutris_slug: Option<String>,
+    pub is_bottles_app: bool,
+    /// Directory name of the bottle under `bottles/bottles`.
+    pub bottle_name: Option<String>,
+    /// Program inside the bottle; `None` for the bottle itself.
+    pub bottle_program: Option<String>,
     pub is_flatpak: bool,
     pub flatpak_app_id: Option<String>,
+    pub is_snap: bool,
+    /// Snap instance name, e.g. `firefox` or `firefox_beta`.
+    pub snap_name: Option<String>,
+    /// For a Kaede-managed override, the system `.desktop` file it was generated from.
+    pub override_source: Option<PathBuf>,
+    /// The override's source file changed or disappeared since it was generated.
+    pub override_stale: bool,
+    pub actions: Vec<DesktopAction>,
+}
+
+impl DesktopApp {
+    /// Steam, Heroic, Lutris, Bottles and Flatpak apps get their GPU environment
+    /// from their own launcher config; everything else goes through a desktop override.
+    pub fn uses_desktop_override(&self) -> bool {
+        !(self.is_steam_game
+            || self.is_heroic_game
+            || (self.is_lutris_game && self.lutris_slug.is_some())
+            || (self.is_bottles_app && self.bottle_name.is_some())
+            || (self.is_flatpak && self.flatpak_app_id.is_some()))
+    }
+}
+
+/// A `[Desktop Action <id>]` group, e.g. Firefox's "New Private Window".
+#[derive(Debug, Clone)]
+pub struct DesktopAction {
+    pub id: String,
+    pub name: String,
+}
+
+/// What a launcher backend currently has on disk for one app.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverrideState {
+    /// Whether the on-disk state matches the saved assignment.
+    pub in_sync: bool,
+    /// `DRI_PRIME` found in the Kaede-managed env, or `None` when no override is present.
+    pub observed_dri_prime: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(tag = "type", content = "value")]
 pub enum GpuChoice {
     Default,
-    Gpu(usize),
+    /// Positional `cardN` index saved by older versions; migrated once GPUs are detected.
+    #[serde(rename = "Gpu")]
+    LegacyIndex(usize),
+    #[serde(rename = "Device")]
+    Gpu(GpuId),
 }
 
 impl Default for GpuChoice {
@@ -79,20 +161,46 @@ impl GpuChoice {
     pub fn label(&self) -> String {
         match self {
             GpuChoice::Default => "Default GPU".to_string(),
-            GpuChoice::Gpu(idx) => format!("GPU {}", idx),
+            GpuChoice::LegacyIndex(idx) => format!("GPU {}", idx),
+            GpuChoice::Gpu(id) => id.name.clone(),
         }
     }
 }
 
+/// Which Steam accounts' `localconfig.vdf` a Steam game assignment is written to.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", content = "value")]
+pub enum SteamAccountScope {
+    /// The account that logged in last, per `loginusers.vdf`.
+    #[default]
+    MostRecent,
+    All,
+    /// Account IDs as used for `userdata/<id>`.
+    Accounts(Vec<u32>),
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
     #[serde(default)]
     pub assignments: BTreeMap<String, GpuChoice>,
+    /// Per-action overrides keyed by desktop ID, then action ID. Actions
+    /// without an entry follow the application's assignment.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub action_assignments: BTreeMap<String, BTreeMap<String, GpuChoice>>,
+    /// Steam account scope per desktop ID; missing entries use the most recent login.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub steam_accounts: BTreeMap<String, SteamAccountScope>,
+    /// Steam games whose saved assignment hasn't been written yet because
+    /// Steam was running; applied once Steam exits.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub pending_steam_changes: BTreeSet<String>,
     #[serde(default = "default_true")]
     pub show_steam_apps: bool,
     #[serde(default = "default_true")]
     pub show_heroic_apps: bool,
     #[serde(default = "default_true")]
+    pub show_lutris_apps: bool,
+    #[serde(default = "default_true")]
     pub show_flatpak_apps: bool,
     #[serde(default)]
     pub use_env_wrapper: bool,
@@ -100,6 +208,11 @@ pub struct AppConfig {
     pub skip_nvidia_warning: bool,
     #[serde(default = "default_true")]
     pub check_updates_at_startup: bool,
+    #[serde(default = "default_true")]
+    pub regenerate_stale_overrides: bool,
+    /// Extra `applications` directories scanned besides the XDG data dirs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_scan_dirs: Vec<PathBuf>,
 }
 
 fn default_true() -> bool {
@@ -110,12 +223,18 @@ impl Default for AppConfig {
     fn default() -> Self {
         Self {
             assignments: BTreeMap::new(),
+            action_assignments: BTreeMap::new(),
+            steam_accounts: BTreeMap::new(),
+            pending_steam_changes: BTreeSet::new(),
             show_steam_apps: true,
             show_heroic_apps: true,
+            show_lutris_apps: true,
             show_flatpak_apps: true,
             use_env_wrapper: false,
             skip_nvidia_warning: false,
             check_updates_at_startup: true,
+            regenerate_stale_overrides: true,
+            extra_scan_dirs: Vec::new(),
         }
     }
 }
@@ -0,0 +1,586 @@
+//! Kernel command line parameters for the graphics modes. modprobe.d options
+//! only apply once a module is loaded from the root filesystem; on the command
+//! line they also reach the initramfs and early KMS.
+
+use super::initramfs::find_program;
+use super::run_with_output;
+use crate::transaction::Transaction;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
+
+pub const KERNEL_CMDLINE_PATH: &str = "/etc/kernel/cmdline";
+pub const GRUB_DEFAULT_PATH: &str = "/etc/default/grub";
+/// Debian and Ubuntu source these after `/etc/default/grub`.
+const GRUB_DROP_IN_DIR: &str = "/etc/default/grub.d";
+/// Parameters Kaede added, one per line, so that later switches only ever
+/// remove those and leave the user's own alone.
+const OWNED_PARAMS_PATH: &str = "/var/lib/kaede/kernel-params";
+const BOOT_ENTRY_DIRS: [&str; 3] = [
+    "/boot/loader/entries",
+    "/efi/loader/entries",
+    "/boot/efi/loader/entries",
+];
+/// `GRUB_CMDLINE_LINUX_DEFAULT` is preferred so recovery entries stay untouched.
+const GRUB_VARIABLES: [&str; 2] = ["GRUB_CMDLINE_LINUX_DEFAULT", "GRUB_CMDLINE_LINUX"];
+const BLACKLISTED_MODULES: &str = "nouveau,nova_core,nova_drm,nvidia,nvidia_drm,nvidia_uvm,nvidia_modeset,nvidia_current,nvidia_current_drm,nvidia_current_uvm,nvidia_current_modeset";
+/// Prefixes of the running parameters worth showing next to the mode.
+const RELEVANT_PREFIXES: [&str; 6] = [
+    "nvidia",
+    "nouveau",
+    "module_blacklist=",
+    "modprobe.blacklist=",
+    "rd.driver.blacklist=",
+    "nomodeset",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    RpmOstree,
+    /// `/etc/kernel/cmdline`, read by kernel-install and UKI generators.
+    KernelCmdline,
+    Grub,
+    /// Boot Loader Specification entries, used by systemd-boot and by GRUB
+    /// with `GRUB_ENABLE_BLSCFG`.
+    BootEntries,
+}
+
+impl Source {
+    fn label(self) -> &'static str {
+        match self {
+            Source::RpmOstree => "rpm-ostree kargs",
+            Source::KernelCmdline => KERNEL_CMDLINE_PATH,
+            Source::Grub => GRUB_DEFAULT_PATH,
+            Source::BootEntries => "boot loader entries",
+        }
+    }
+}
+
+/// Commands that apply the staged parameters once the files are written, and
+/// the ones that take them back if a later step fails.
+#[derive(Debug, Default)]
+pub(super) struct KernelParams {
+    commands: Vec<Vec<String>>,
+    undo: Vec<Vec<String>>,
+}
+
+impl KernelParams {
+    pub fn commands(&self) -> Vec<String> {
+        self.commands.iter().map(|command| command.join(" ")).collect()
+    }
+
+    pub fn run(&self, output: &mut dyn FnMut(&str)) -> Result<(), String> {
+        run_all(&self.commands, output)
+    }
+
+    /// Expects the files to be rolled back already.
+    pub fn undo(&self, output: &mut dyn FnMut(&str)) -> Result<(), String> {
+        run_all(&self.undo, output)
+    }
+}
+
+pub(super) fn blacklist_params() -> Vec<String> {
+    vec![
+        format!("module_blacklist={BLACKLISTED_MODULES}"),
+        format!("rd.driver.blacklist={BLACKLISTED_MODULES}"),
+    ]
+}
+
+pub(super) fn drm_params(use_nvidia_current: bool, fbdev: bool) -> Vec<String> {
+    let module = if use_nvidia_current {
+        "nvidia-current-drm"
+    } else {
+        "nvidia-drm"
+    };
+    let mut params = vec![format!("{module}.modeset=1")];
+    if fbdev {
+        params.push(format!("{module}.fbdev=1"));
+    }
+    params
+}
+
+/// NVIDIA-related parameters the running kernel was booted with.
+pub(super) fn running_params() -> Vec<String> {
+    fs::read_to_string("/proc/cmdline")
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter(|param| RELEVANT_PREFIXES.iter().any(|prefix| param.starts_with(prefix)))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Stages `desired` on every kernel command line source found, removing the
+/// parameters an earlier switch added that are no longer wanted. Each source is
+/// compared on its own, so a parameter already in one still goes into the others.
+pub(super) fn stage(
+    tx: &mut Transaction,
+    desired: &[String],
+    notes: &mut Vec<String>,
+) -> KernelParams {
+    let sources = detect_sources();
+    if sources.is_empty() {
+        if !desired.is_empty() {
+            notes.push(
+                "No supported boot configuration was found, so the kernel command line is left alone"
+                    .to_string(),
+            );
+        }
+        return KernelParams::default();
+    }
+
+    let owned = fs::read_to_string(OWNED_PARAMS_PATH)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.trim().rsplit_once(' '))
+        .map(|(key, param)| (key.to_string(), param.to_string()))
+        .collect::<BTreeSet<_>>();
+    let mut edits = Edits {
+        desired,
+        owned: &owned,
+        now_owned: BTreeSet::new(),
+        notes,
+    };
+
+    let mut params = KernelParams::default();
+    for source in sources {
+        match source {
+            Source::RpmOstree => {
+                let edit = edits.plan(source.label(), &rpm_ostree_params());
+                if !edit.is_empty() {
+                    params.commands.push(rpm_ostree_kargs(&edit.add, &edit.remove));
+                    params.undo.push(rpm_ostree_kargs(&edit.remove, &edit.add));
+                }
+            }
+            Source::KernelCmdline => {
+                let Ok(content) = fs::read_to_string(KERNEL_CMDLINE_PATH) else {
+                    continue;
+                };
+                let present = content.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+                let edit = edits.plan(source.label(), &present);
+                if !edit.is_empty() {
+                    let updated = edit_params(content.trim(), &edit.remove, &edit.add);
+                    tx.write(KERNEL_CMDLINE_PATH, format!("{updated}\n"));
+                }
+            }
+            Source::Grub => {
+                let files = grub_files();
+                if let Some((path, _)) = files
+                    .iter()
+                    .find(|(_, content)| content.lines().any(GrubAssignment::is_multiline))
+                {
+                    edits.notes.push(format!(
+                        "{} sets the kernel command line over several lines, so Kaede leaves GRUB alone",
+                        path.display()
+                    ));
+                    continue;
+                }
+                let present = files
+                    .iter()
+                    .flat_map(|(_, content)| content.lines().filter_map(GrubAssignment::parse))
+                    .flat_map(|assignment| assignment.value.split_whitespace())
+                    .map(str::to_string)
+                    .collect::<Vec<_>>();
+                let edit = edits.plan(source.label(), &present);
+                if edit.is_empty() {
+                    continue;
+                }
+                let target = grub_target(&files);
+                for (index, (path, content)) in files.iter().enumerate() {
+                    let updated = match target {
+                        Some((file, line)) if file == index => {
+                            edit_grub(content, &edit.remove, &edit.add, Some(line))
+                        }
+                        None if index == 0 => edit_grub(content, &edit.remove, &edit.add, None),
+                        _ => edit_grub(content, &edit.remove, &[], None),
+                    };
+                    if updated != *content {
+                        tx.write(path, updated);
+                    }
+                }
+                match grub_mkconfig() {
+                    Some(command) => {
+                        params.commands.push(command.clone());
+                        params.undo.push(command);
+                    }
+                    None => edits.notes.push(format!(
+                        "No grub-mkconfig or update-grub was found; regenerate the GRUB configuration after {GRUB_DEFAULT_PATH} changes"
+                    )),
+                }
+            }
+            Source::BootEntries => {
+                for entry in boot_entries() {
+                    let Ok(content) = fs::read_to_string(&entry) else {
+                        continue;
+                    };
+                    let present = content
+                        .lines()
+                        .filter_map(options_line)
+                        .flat_map(str::split_whitespace)
+                        .map(str::to_string)
+                        .collect::<Vec<_>>();
+                    let edit = edits.plan(&entry.display().to_string(), &present);
+                    if !edit.is_empty() {
+                        tx.write(&entry, edit_boot_entry(&content, &edit.remove, &edit.add));
+                    }
+                }
+            }
+        }
+    }
+
+    if edits.now_owned != owned {
+        if edits.now_owned.is_empty() {
+            tx.remove(OWNED_PARAMS_PATH);
+        } else {
+            let lines = edits
+                .now_owned
+                .iter()
+                .map(|(key, param)| format!("{key} {param}\n"))
+                .collect::<String>();
+            tx.write(OWNED_PARAMS_PATH, lines);
+        }
+    }
+    params
+}
+
+/// Works out the changes for each source, and which parameters Kaede owns in
+/// it afterwards. Ownership is recorded per source, as `<source> <param>`.
+struct Edits<'a> {
+    desired: &'a [String],
+    owned: &'a BTreeSet<(String, String)>,
+    now_owned: BTreeSet<(String, String)>,
+    notes: &'a mut Vec<String>,
+}
+
+struct Edit {
+    add: Vec<String>,
+    remove: Vec<String>,
+}
+
+impl Edit {
+    fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty()
+    }
+}
+
+impl Edits<'_> {
+    /// `key` names the source: a path, or `rpm-ostree kargs`.
+    fn plan(&mut self, key: &str, present: &[String]) -> Edit {
+        let owns = |param: &String| self.owned.contains(&(key.to_string(), param.clone()));
+        let remove = self
+            .owned
+            .iter()
+            .filter(|(owner, param)| {
+                owner == key && !self.desired.contains(param) && present.contains(param)
+            })
+            .map(|(_, param)| param.clone())
+            .collect::<Vec<_>>();
+        let add = self
+            .desired
+            .iter()
+            .filter(|param| !present.contains(*param))
+            .cloned()
+            .collect::<Vec<_>>();
+        // Parameters the user had set before are theirs, even when a mode wants them.
+        let owned = self
+            .desired
+            .iter()
+            .filter(|param| owns(param) || !present.contains(*param))
+            .map(|param| (key.to_string(), param.clone()))
+            .collect::<Vec<_>>();
+        self.now_owned.extend(owned);
+
+        let edit = Edit { add, remove };
+        if !edit.is_empty() {
+            let mut summary = format!("Kernel command line ({key})");
+            if !edit.add.is_empty() {
+                summary.push_str(&format!("; adding {}", edit.add.join(" ")));
+            }
+            if !edit.remove.is_empty() {
+                summary.push_str(&format!("; removing {}", edit.remove.join(" ")));
+            }
+            info!("{summary}");
+            self.notes.push(summary);
+        }
+        edit
+    }
+}
+
+/// rpm-ostree owns the command line on image-based systems; elsewhere every
+/// configuration that exists is kept in step, since kernel-install, GRUB and
+/// the boot entries may each be the one that is used.
+fn detect_sources() -> Vec<Source> {
+    if Path::new("/ostree").exists() || Path::new("/sysroot/ostree").exists() {
+        return vec![Source::RpmOstree];
+    }
+    let mut sources = Vec::new();
+    if Path::new(KERNEL_CMDLINE_PATH).is_file() {
+        sources.push(Source::KernelCmdline);
+    }
+    if Path::new(GRUB_DEFAULT_PATH).is_file() {
+        sources.push(Source::Grub);
+    }
+    if !boot_entries().is_empty() {
+        sources.push(Source::BootEntries);
+    }
+    sources
+}
+
+fn rpm_ostree_params() -> Vec<String> {
+    Command::new("rpm-ostree")
+        .arg("kargs")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .split_whitespace()
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn boot_entries() -> Vec<PathBuf> {
+    let mut entries = BOOT_ENTRY_DIRS
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.flatten())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "conf"))
+        .collect::<Vec<_>>();
+    entries.sort();
+    entries.dedup();
+    entries
+}
+
+fn edit_params(params: &str, remove: &[String], add: &[String]) -> String {
+    let mut tokens = params
+        .split_whitespace()
+        .filter(|param| !remove.iter().any(|removed| removed == param))
+        .collect::<Vec<_>>();
+    for param in add {
+        if !tokens.contains(&param.as_str()) {
+            tokens.push(param);
+        }
+    }
+    tokens.join(" ")
+}
+
+/// `/etc/default/grub` and the drop-ins sourced after it, in that order, with
+/// their content.
+fn grub_files() -> Vec<(PathBuf, String)> {
+    let mut drop_ins = fs::read_dir(GRUB_DROP_IN_DIR)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "cfg"))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    drop_ins.sort();
+    std::iter::once(PathBuf::from(GRUB_DEFAULT_PATH))
+        .chain(drop_ins)
+        .filter_map(|path| {
+            let content = fs::read_to_string(&path).ok()?;
+            Some((path, content))
+        })
+        .collect()
+}
+
+/// The assignment that takes effect: the last one of the preferred variable,
+/// as file index and line index.
+fn grub_target(files: &[(PathBuf, String)]) -> Option<(usize, usize)> {
+    GRUB_VARIABLES.iter().find_map(|variable| {
+        files.iter().enumerate().rev().find_map(|(file, (_, content))| {
+            let lines = content.lines().collect::<Vec<_>>();
+            let line = lines.iter().rposition(|line| {
+                GrubAssignment::parse(line).is_some_and(|assignment| assignment.variable == *variable)
+            })?;
+            Some((file, line))
+        })
+    })
+}
+
+/// A `GRUB_CMDLINE_LINUX*=` line, split so that only the value changes.
+struct GrubAssignment<'a> {
+    /// Indentation, `export ` and `NAME=`.
+    prefix: &'a str,
+    variable: &'static str,
+    quote: Option<char>,
+    value: &'a str,
+    /// Whatever follows the value, e.g. a comment.
+    rest: &'a str,
+}
+
+impl<'a> GrubAssignment<'a> {
+    /// `None` for other lines, and for values whose quote doesn't close on
+    /// the same line.
+    fn parse(line: &'a str) -> Option<Self> {
+        let (variable, prefix, raw) = Self::split(line)?;
+        let (quote, value, rest) = match raw.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let end = 1 + closing_quote(&raw[1..], quote)?;
+                (Some(quote), &raw[1..end], &raw[end + 1..])
+            }
+            _ => {
+                let end = raw.find(char::is_whitespace).unwrap_or(raw.len());
+                (None, &raw[..end], &raw[end..])
+            }
+        };
+        Some(GrubAssignment {
+            prefix,
+            variable,
+            quote,
+            value,
+            rest,
+        })
+    }
+
+    /// An assignment Kaede can't edit in place, since it continues on the
+    /// next line.
+    fn is_multiline(line: &'a str) -> bool {
+        Self::split(line).is_some() && Self::parse(line).is_none()
+    }
+
+    fn split(line: &'a str) -> Option<(&'static str, &'a str, &'a str)> {
+        let body = line.trim_start();
+        let body = body
+            .strip_prefix("export")
+            .filter(|rest| rest.starts_with(char::is_whitespace))
+            .map(str::trim_start)
+            .unwrap_or(body);
+        let variable = GRUB_VARIABLES.into_iter().find(|variable| {
+            body.strip_prefix(variable)
+                .is_some_and(|rest| rest.starts_with('='))
+        })?;
+        let (prefix, raw) = line.split_at(line.len() - body.len() + variable.len() + 1);
+        Some((variable, prefix, raw))
+    }
+}
+
+/// Byte index of the quote that ends a value; inside double quotes, a
+/// backslash escapes the next character as in the shell.
+fn closing_quote(value: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (index, c) in value.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' && quote == '"' {
+            escaped = true;
+        } else if c == quote {
+            return Some(index);
+        }
+    }
+    None
+}
+
+/// Removes `remove` from every assignment and adds `add` to the one on line
+/// `target`, or to a new `GRUB_CMDLINE_LINUX_DEFAULT` line when there is none.
+fn edit_grub(content: &str, remove: &[String], add: &[String], target: Option<usize>) -> String {
+    let mut lines = content
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            let Some(assignment) = GrubAssignment::parse(line) else {
+                return line.to_string();
+            };
+            let add = if target == Some(index) { add } else { &[] };
+            let value = edit_params(assignment.value, remove, add);
+            if value == assignment.value {
+                return line.to_string();
+            }
+            let quote = assignment.quote.unwrap_or('"');
+            format!("{}{quote}{value}{quote}{}", assignment.prefix, assignment.rest)
+        })
+        .collect::<Vec<_>>();
+    if target.is_none() && !add.is_empty() {
+        lines.push(format!("{}=\"{}\"", GRUB_VARIABLES[0], add.join(" ")));
+    }
+
+    let mut updated = lines.join("\n");
+    if content.ends_with('\n') || (target.is_none() && !add.is_empty()) {
+        updated.push('\n');
+    }
+    updated
+}
+
+/// The parameters on an entry's `options` line.
+fn options_line(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix("options")?;
+    rest.starts_with(char::is_whitespace).then(|| rest.trim())
+}
+
+/// Removes `remove` from every `options` line and adds `add` to the first.
+fn edit_boot_entry(content: &str, remove: &[String], add: &[String]) -> String {
+    let mut added = false;
+    let mut lines = content
+        .lines()
+        .map(|line| match options_line(line) {
+            Some(params) => {
+                let params = if added {
+                    edit_params(params, remove, &[])
+                } else {
+                    added = true;
+                    edit_params(params, remove, add)
+                };
+                format!("options {params}")
+            }
+            None => line.to_string(),
+        })
+        .collect::<Vec<_>>();
+    if !added && !add.is_empty() {
+        lines.push(format!("options {}", add.join(" ")));
+    }
+    let mut updated = lines.join("\n");
+    if content.ends_with('\n') || (!added && !add.is_empty()) {
+        updated.push('\n');
+    }
+    updated
+}
+
+fn rpm_ostree_kargs(add: &[String], remove: &[String]) -> Vec<String> {
+    let mut command = vec!["rpm-ostree".to_string(), "kargs".to_string()];
+    command.extend(add.iter().map(|param| format!("--append-if-missing={param}")));
+    command.extend(remove.iter().map(|param| format!("--delete-if-present={param}")));
+    command
+}
+
+fn grub_mkconfig() -> Option<Vec<String>> {
+    if let Some(update_grub) = find_program("update-grub") {
+        return Some(vec![update_grub.display().to_string()]);
+    }
+    let candidates = [
+        ("grub-mkconfig", "/boot/grub/grub.cfg"),
+        ("grub2-mkconfig", "/boot/grub2/grub.cfg"),
+    ];
+    candidates.into_iter().find_map(|(program, config)| {
+        let program = find_program(program)?;
+        Path::new(config).parent()?.is_dir().then(|| {
+            vec![
+                program.display().to_string(),
+                "-o".to_string(),
+                config.to_string(),
+            ]
+        })
+    })
+}
+
+fn run_all(commands: &[Vec<String>], output: &mut dyn FnMut(&str)) -> Result<(), String> {
+    for command in commands {
+        info!("Running {}", command.join(" "));
+        let status = run_with_output(Command::new(&command[0]).args(&command[1..]), output)
+            .map_err(|e| format!("failed to run {}: {e}", command[0]))?;
+        if !status.success() {
+            warn!("{} failed with status {:?}", command.join(" "), status.code());
+            return Err(format!(
+                "{} exited with status {}",
+                command.join(" "),
+                status.code().unwrap_or(-1)
+            ));
+        }
+    }
+    Ok(())
+}
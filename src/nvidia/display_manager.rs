@@ -0,0 +1,218 @@
+//! Login screen integration for the dedicated NVIDIA mode. An X11 greeter needs
+//! the same xrandr provider setup as the session, and every display manager
+//! has its own place to run it.
+
+use super::{
+    LIGHTDM_CONFIG_CONTENT, LIGHTDM_CONFIG_PATH, LIGHTDM_SCRIPT_PATH, LXDM_LOGIN_READY_PATH,
+    SDDM_XSETUP_PATH, XINITRC_SCRIPT_PATH,
+};
+use crate::transaction::Transaction;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+const SYSTEMD_UNIT_PATH: &str = "/etc/systemd/system/display-manager.service";
+/// Gentoo and Alpine: the `display-manager` service reads `DISPLAYMANAGER` from here.
+const OPENRC_CONFIG_PATH: &str = "/etc/conf.d/display-manager";
+/// Artix ships one OpenRC service per display manager.
+const OPENRC_RUNLEVELS: [&str; 2] = ["/etc/runlevels/default", "/etc/runlevels/boot"];
+const RUNIT_SERVICE_DIRS: [&str; 3] = [
+    "/var/service",
+    "/etc/runit/runsvdir/default",
+    "/etc/service",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayManager {
+    Gdm,
+    Gdm3,
+    Sddm,
+    Lightdm,
+    Greetd,
+    Ly,
+    Lxdm,
+    CosmicGreeter,
+}
+
+/// The outcome of looking for the enabled display manager.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DetectedDisplayManager {
+    Supported(DisplayManager),
+    /// A display manager is enabled, but not one Kaede knows how to configure.
+    Unsupported(String),
+    NotFound,
+}
+
+impl DisplayManager {
+    pub const ALL: [DisplayManager; 8] = [
+        DisplayManager::Gdm,
+        DisplayManager::Gdm3,
+        DisplayManager::Sddm,
+        DisplayManager::Lightdm,
+        DisplayManager::Greetd,
+        DisplayManager::Ly,
+        DisplayManager::Lxdm,
+        DisplayManager::CosmicGreeter,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DisplayManager::Gdm => "gdm",
+            DisplayManager::Gdm3 => "gdm3",
+            DisplayManager::Sddm => "sddm",
+            DisplayManager::Lightdm => "lightdm",
+            DisplayManager::Greetd => "greetd",
+            DisplayManager::Ly => "ly",
+            DisplayManager::Lxdm => "lxdm",
+            DisplayManager::CosmicGreeter => "cosmic-greeter",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DisplayManager::Gdm => "GDM",
+            DisplayManager::Gdm3 => "GDM3",
+            DisplayManager::Sddm => "SDDM",
+            DisplayManager::Lightdm => "LightDM",
+            DisplayManager::Greetd => "greetd",
+            DisplayManager::Ly => "Ly",
+            DisplayManager::Lxdm => "LXDM",
+            DisplayManager::CosmicGreeter => "COSMIC greeter",
+        }
+    }
+
+    pub(super) fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|dm| dm.as_str() == s)
+    }
+
+    /// Accepts service and executable names, e.g. `ly@tty2.service` or
+    /// `lxdm-binary`, as well as what [`Self::as_str`] returns.
+    fn from_service(name: &str) -> Option<Self> {
+        let name = name.trim_end_matches(".service");
+        let name = name.split('@').next().unwrap_or(name);
+        Self::from_str(name).or(match name {
+            "ly-dm" => Some(DisplayManager::Ly),
+            "lxdm-binary" => Some(DisplayManager::Lxdm),
+            "cosmic-greeter-start" => Some(DisplayManager::CosmicGreeter),
+            _ => None,
+        })
+    }
+}
+
+pub fn detect_display_manager() -> DetectedDisplayManager {
+    let Some(name) = systemd_display_manager()
+        .or_else(openrc_display_manager)
+        .or_else(runit_display_manager)
+    else {
+        warn!("Display Manager detection is not available");
+        return DetectedDisplayManager::NotFound;
+    };
+
+    match DisplayManager::from_service(&name) {
+        Some(dm) => {
+            info!("Found {} Display Manager", dm.as_str());
+            DetectedDisplayManager::Supported(dm)
+        }
+        None => {
+            warn!("Display Manager {name} is not supported");
+            DetectedDisplayManager::Unsupported(name)
+        }
+    }
+}
+
+/// The unit `display-manager.service` links to, or, for a hand-written unit or
+/// one named after a wrapper, the program it starts.
+fn systemd_display_manager() -> Option<String> {
+    let unit = fs::canonicalize(SYSTEMD_UNIT_PATH).ok()?;
+    let unit_name = unit.file_stem()?.to_string_lossy().to_string();
+    if DisplayManager::from_service(&unit_name).is_some() {
+        return Some(unit_name);
+    }
+
+    let content = fs::read_to_string(&unit).ok()?;
+    let program = content
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("ExecStart="))
+        .and_then(|exec| exec.split_whitespace().next())
+        .map(|program| program.trim_start_matches(['-', '@', '+', '!', ':']))
+        .unwrap_or_default();
+    let candidates = [
+        Some(PathBuf::from(program)),
+        fs::canonicalize(program).ok(),
+    ];
+    let executable = candidates
+        .into_iter()
+        .flatten()
+        .filter_map(|path| Some(path.file_name()?.to_string_lossy().to_string()))
+        .find(|name| DisplayManager::from_service(name).is_some());
+
+    match executable {
+        Some(name) => Some(name),
+        None if unit_name != "display-manager" => Some(unit_name),
+        None => Path::new(program)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string()),
+    }
+}
+
+fn openrc_display_manager() -> Option<String> {
+    fs::read_to_string(OPENRC_CONFIG_PATH)
+        .ok()
+        .and_then(|content| {
+            content.lines().find_map(|line| {
+                let value = line.trim().strip_prefix("DISPLAYMANAGER=")?;
+                Some(value.trim_matches(['"', '\'']).to_string())
+            })
+        })
+        .filter(|name| !name.is_empty())
+        .or_else(|| enabled_service(&OPENRC_RUNLEVELS))
+}
+
+fn runit_display_manager() -> Option<String> {
+    enabled_service(&RUNIT_SERVICE_DIRS)
+}
+
+fn enabled_service(dirs: &[&str]) -> Option<String> {
+    dirs.iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.flatten())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .find(|name| DisplayManager::from_service(name).is_some())
+}
+
+/// Stages the xrandr `script` wherever `dm` runs setup scripts.
+pub(super) fn stage_setup_script(
+    tx: &mut Transaction,
+    dm: DisplayManager,
+    script: String,
+    notes: &mut Vec<String>,
+) {
+    match dm {
+        DisplayManager::Sddm => replace_script(tx, SDDM_XSETUP_PATH, script),
+        DisplayManager::Lxdm => replace_script(tx, LXDM_LOGIN_READY_PATH, script),
+        DisplayManager::Lightdm => {
+            tx.write_executable(LIGHTDM_SCRIPT_PATH, script);
+            tx.write(LIGHTDM_CONFIG_PATH, LIGHTDM_CONFIG_CONTENT);
+        }
+        // Their greeters don't run X; X11 sessions go through xinit, which
+        // sources xinitrc.d.
+        DisplayManager::Greetd | DisplayManager::Ly => {
+            tx.write_executable(XINITRC_SCRIPT_PATH, script);
+        }
+        DisplayManager::CosmicGreeter => notes.push(
+            "The COSMIC greeter runs on Wayland, so there is no login screen script to set up"
+                .to_string(),
+        ),
+        DisplayManager::Gdm | DisplayManager::Gdm3 => {}
+    }
+}
+
+/// The display manager ships this script; the original is kept as `.bak` and
+/// put back by `cleanup`.
+fn replace_script(tx: &mut Transaction, path: &str, script: String) {
+    if let Some(existing) = tx.read(Path::new(path)) {
+        info!("Creating {path} backup");
+        tx.write(format!("{path}.bak"), existing);
+    }
+    tx.write_executable(path, script);
+}
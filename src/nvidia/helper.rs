@@ -0,0 +1,121 @@
+//! Runs the root-only NVIDIA operations through `kaede-helper` and `pkexec`,
+//! so the GUI itself never needs to run as root.
+
+use super::NvidiaSwitchConfig;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{debug, info};
+
+/// Packages that install the helper somewhere else set this at build time.
+const HELPER_PATH: &str = match option_env!("KAEDE_HELPER_PATH") {
+    Some(path) => path,
+    None => "/usr/libexec/kaede-helper",
+};
+
+/// `pkexec` exit codes for a dismissed and a refused authentication.
+const PKEXEC_DISMISSED: i32 = 126;
+const PKEXEC_NOT_AUTHORIZED: i32 = 127;
+
+/// Everything the helper prints, including the initramfs tool's output, is
+/// passed to `output` line by line.
+pub fn switch_graphics_mode(
+    config: &NvidiaSwitchConfig,
+    output: &mut dyn FnMut(&str),
+) -> Result<(), String> {
+    if running_as_root() {
+        return super::switch_graphics_mode(config, output);
+    }
+    let mut args = vec!["switch".to_string()];
+    args.extend(config.to_args());
+    run_helper(&args, output)
+}
+
+pub fn reset_all(output: &mut dyn FnMut(&str)) -> Result<(), String> {
+    if running_as_root() {
+        return super::reset_all(output);
+    }
+    run_helper(&["reset".to_string()], output)
+}
+
+/// `None` goes back to the detected initramfs tool.
+pub fn set_initramfs_command(
+    command: Option<&str>,
+    output: &mut dyn FnMut(&str),
+) -> Result<(), String> {
+    if running_as_root() {
+        return super::set_custom_command(command);
+    }
+    let mut args = vec!["set-initramfs-command".to_string()];
+    args.extend(command.map(str::to_string));
+    run_helper(&args, output)
+}
+
+pub fn reset_sddm(output: &mut dyn FnMut(&str)) -> Result<(), String> {
+    if running_as_root() {
+        return super::reset_sddm();
+    }
+    run_helper(&["reset-sddm".to_string()], output)
+}
+
+/// Restores a change set the helper backed up; see [`crate::backup::restore`].
+pub fn restore_backup(
+    id: &str,
+    only: Option<&Path>,
+    output: &mut dyn FnMut(&str),
+) -> Result<(), String> {
+    if running_as_root() {
+        for path in crate::backup::restore(id, only).map_err(|e| format!("{e:#}"))? {
+            output(&format!("Restored {}", path.display()));
+        }
+        return Ok(());
+    }
+    let mut args = vec!["restore".to_string(), id.to_string()];
+    if let Some(path) = only {
+        args.extend(["--file".to_string(), path.display().to_string()]);
+    }
+    run_helper(&args, output)
+}
+
+fn run_helper(args: &[String], output: &mut dyn FnMut(&str)) -> Result<(), String> {
+    let helper = helper_path();
+    info!(helper = %helper.display(), args = ?args, "running privileged helper");
+    let mut error = None;
+    let status = super::run_with_output(Command::new("pkexec").arg(&helper).args(args), &mut |line| {
+        debug!(output = line, "kaede-helper");
+        if let Some(message) = line.strip_prefix("kaede-helper: ") {
+            error = Some(message.to_string());
+        }
+        output(line);
+    })
+    .map_err(|e| format!("failed to run pkexec: {e}"))?;
+
+    if status.success() {
+        return Ok(());
+    }
+
+    match status.code() {
+        Some(PKEXEC_DISMISSED) => Err("authentication was cancelled".to_string()),
+        Some(PKEXEC_NOT_AUTHORIZED) => {
+            Err("not authorized to change the NVIDIA configuration".to_string())
+        }
+        _ => Err(error.unwrap_or_else(|| "the privileged helper failed".to_string())),
+    }
+}
+
+fn helper_path() -> PathBuf {
+    let installed = PathBuf::from(HELPER_PATH);
+    if installed.exists() {
+        return installed;
+    }
+    // Development builds keep the helper next to the `kaede` binary.
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join("kaede-helper")))
+        .filter(|path| path.exists())
+        .unwrap_or(installed)
+}
+
+fn running_as_root() -> bool {
+    std::fs::metadata("/proc/self").is_ok_and(|metadata| metadata.uid() == 0)
+}
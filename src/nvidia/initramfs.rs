@@ -0,0 +1,223 @@
+//! Rebuilding the initramfs after the module configuration changed. Which tool
+//! does that depends on how the system boots, not on the distribution, so each
+//! backend is detected by what is installed and configured.
+
+use crate::backup;
+use crate::transaction::Transaction;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{error, info, warn};
+
+/// Replaces the detected backend. The helper runs it as root, so it is only
+/// read from a root-owned file, which only the helper writes.
+pub const CUSTOM_COMMAND_PATH: &str = "/etc/kaede/initramfs-command";
+
+/// Programs are looked up here rather than in `PATH`, which differs between
+/// the GUI drawing up the plan and the helper under `pkexec`.
+const PROGRAM_DIRS: [&str; 4] = ["/usr/local/sbin", "/usr/local/bin", "/usr/sbin", "/usr/bin"];
+const KERNEL_INSTALL_CONFIGS: [&str; 2] = ["/etc/kernel/install.conf", "/usr/lib/kernel/install.conf"];
+const BOOSTER_REGENERATE: &str = "/usr/lib/booster/regenerate_images";
+
+/// Arch names images after the kernel package rather than the version, which
+/// `dracut --regenerate-all` doesn't know about.
+const DRACUT_ARCH_SCRIPT: &str = r#"for dir in /usr/lib/modules/*/; do
+  [ -r "$dir/pkgbase" ] || continue
+  kver=$(basename "$dir")
+  dracut --force --kver "$kver" "/boot/initramfs-$(cat "$dir/pkgbase").img"
+done"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitramfsBackend {
+    RpmOstree,
+    /// `kernel-install` with `layout=uki`, which rebuilds unified kernel images
+    /// through `ukify` and whichever initramfs generator it is set up with.
+    KernelInstallUki,
+    Mkinitcpio,
+    Booster,
+    InitramfsTools,
+    DracutRebuild,
+    DracutArch,
+    Dracut,
+    MakeInitrd,
+}
+
+impl InitramfsBackend {
+    pub fn label(self) -> &'static str {
+        match self {
+            InitramfsBackend::RpmOstree => "rpm-ostree",
+            InitramfsBackend::KernelInstallUki => "kernel-install (UKI)",
+            InitramfsBackend::Mkinitcpio => "mkinitcpio",
+            InitramfsBackend::Booster => "booster",
+            InitramfsBackend::InitramfsTools => "initramfs-tools",
+            InitramfsBackend::DracutRebuild => "dracut-rebuild",
+            InitramfsBackend::DracutArch => "dracut (Arch)",
+            InitramfsBackend::Dracut => "dracut",
+            InitramfsBackend::MakeInitrd => "make-initrd",
+        }
+    }
+
+    fn command(self) -> Vec<String> {
+        let command: &[&str] = match self {
+            InitramfsBackend::RpmOstree => &["rpm-ostree", "initramfs", "--enable", "--arg=--force"],
+            InitramfsBackend::KernelInstallUki => &["kernel-install", "add-all"],
+            InitramfsBackend::Mkinitcpio => &["mkinitcpio", "-P"],
+            InitramfsBackend::Booster => &[BOOSTER_REGENERATE],
+            InitramfsBackend::InitramfsTools => &["update-initramfs", "-u", "-k", "all"],
+            InitramfsBackend::DracutRebuild => &["dracut-rebuild"],
+            InitramfsBackend::DracutArch => &["sh", "-c", DRACUT_ARCH_SCRIPT],
+            InitramfsBackend::Dracut => &["dracut", "--force", "--regenerate-all"],
+            InitramfsBackend::MakeInitrd => &["make-initrd"],
+        };
+        let mut command = command.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        if let Some(program) = find_program(&command[0]) {
+            command[0] = program.display().to_string();
+        }
+        command
+    }
+}
+
+/// Checked in order, so a more specific setup wins over a tool that merely
+/// happens to be installed next to it.
+pub fn detect_backend() -> Option<InitramfsBackend> {
+    let backend = if Path::new("/ostree").exists() || Path::new("/sysroot/ostree").exists() {
+        Some(InitramfsBackend::RpmOstree)
+    } else if uki_layout() && find_program("kernel-install").is_some() {
+        Some(InitramfsBackend::KernelInstallUki)
+    } else if has_mkinitcpio_presets() && find_program("mkinitcpio").is_some() {
+        Some(InitramfsBackend::Mkinitcpio)
+    } else if Path::new(BOOSTER_REGENERATE).exists() {
+        Some(InitramfsBackend::Booster)
+    } else if find_program("update-initramfs").is_some() {
+        Some(InitramfsBackend::InitramfsTools)
+    } else if find_program("dracut-rebuild").is_some() {
+        Some(InitramfsBackend::DracutRebuild)
+    } else if find_program("dracut").is_some() && Path::new("/etc/arch-release").exists() {
+        Some(InitramfsBackend::DracutArch)
+    } else if find_program("dracut").is_some() {
+        Some(InitramfsBackend::Dracut)
+    } else if find_program("mkinitcpio").is_some() {
+        Some(InitramfsBackend::Mkinitcpio)
+    } else if find_program("make-initrd").is_some() {
+        Some(InitramfsBackend::MakeInitrd)
+    } else {
+        None
+    };
+    info!(backend = ?backend, "Detected initramfs backend");
+    backend
+}
+
+/// The custom command if one is set, otherwise the detected backend's. The
+/// custom command is split on whitespace and run without a shell.
+pub fn initramfs_command() -> Option<Vec<String>> {
+    match custom_command() {
+        Some(custom) => Some(custom.split_whitespace().map(str::to_string).collect()),
+        None => detect_backend().map(InitramfsBackend::command),
+    }
+}
+
+/// Ignored unless the file is owned by root and writable by nobody else.
+pub fn custom_command() -> Option<String> {
+    let metadata = fs::metadata(CUSTOM_COMMAND_PATH).ok()?;
+    if metadata.uid() != 0 || metadata.mode() & 0o022 != 0 {
+        warn!("Ignoring {CUSTOM_COMMAND_PATH}: it must be owned and writable only by root");
+        return None;
+    }
+    let command = fs::read_to_string(CUSTOM_COMMAND_PATH).ok()?;
+    let command = command.trim();
+    (!command.is_empty()).then(|| command.to_string())
+}
+
+/// `None` goes back to the detected backend.
+pub fn set_custom_command(command: Option<&str>) -> Result<(), String> {
+    let _backup = backup::begin("Set initramfs command");
+    let mut tx = Transaction::new();
+    match command {
+        Some(command) => {
+            validate_custom_command(command)?;
+            tx.write(CUSTOM_COMMAND_PATH, format!("{}\n", command.trim()));
+        }
+        None => tx.remove(CUSTOM_COMMAND_PATH),
+    }
+    tx.commit().map_err(|e| format!("{e:#}"))?;
+    info!(command = ?command, "Initramfs command set");
+    Ok(())
+}
+
+/// The first word must be an absolute path, so the command previewed in the
+/// plan is the one the helper runs as root. Quotes are rejected because the
+/// command is split on whitespace, which would break quoted arguments apart.
+pub fn validate_custom_command(command: &str) -> Result<(), String> {
+    if let Some(quote) = command.chars().find(|c| matches!(c, '"' | '\'' | '\\')) {
+        return Err(format!(
+            "the initramfs command can't contain {quote}; arguments are split on spaces"
+        ));
+    }
+    let program = command
+        .split_whitespace()
+        .next()
+        .ok_or("the initramfs command is empty")?;
+    if !program.starts_with('/') {
+        return Err(format!(
+            "the initramfs command must start with an absolute path, not '{program}'"
+        ));
+    }
+    if !Path::new(program).is_file() {
+        return Err(format!("{program} does not exist"));
+    }
+    Ok(())
+}
+
+pub(super) fn rebuild(output: &mut dyn FnMut(&str)) -> Result<(), String> {
+    let Some(command) = initramfs_command() else {
+        info!("No known initramfs tool detected; skipping initramfs rebuild");
+        return Ok(());
+    };
+
+    info!("Rebuilding the initramfs with {}", command.join(" "));
+    let mut child = Command::new(&command[0]);
+    child.args(&command[1..]);
+    let status = super::run_with_output(&mut child, output)
+        .map_err(|e| format!("failed to run {}: {e}", command[0]))?;
+
+    if status.success() {
+        info!("Successfully rebuilt the initramfs!");
+        Ok(())
+    } else {
+        error!("An error occurred while rebuilding the initramfs");
+        Err(format!(
+            "initramfs rebuild failed: {} exited with status {}",
+            command.join(" "),
+            status.code().unwrap_or(-1)
+        ))
+    }
+}
+
+pub(super) fn find_program(name: &str) -> Option<PathBuf> {
+    if name.starts_with('/') {
+        return Some(PathBuf::from(name)).filter(|path| path.is_file());
+    }
+    PROGRAM_DIRS
+        .iter()
+        .map(|dir| Path::new(dir).join(name))
+        .find(|path| path.is_file())
+}
+
+fn uki_layout() -> bool {
+    KERNEL_INSTALL_CONFIGS.iter().any(|path| {
+        fs::read_to_string(path).is_ok_and(|content| {
+            content
+                .lines()
+                .any(|line| line.split('#').next().unwrap_or_default().trim() == "layout=uki")
+        })
+    })
+}
+
+fn has_mkinitcpio_presets() -> bool {
+    fs::read_dir("/etc/mkinitcpio.d").is_ok_and(|entries| {
+        entries
+            .flatten()
+            .any(|entry| entry.path().extension().is_some_and(|ext| ext == "preset"))
+    })
+}
@@ -1,10 +1,30 @@
+use crate::backup;
+use crate::transaction::Transaction;
 use std::fs;
-use std::io;
+use std::io::{self, BufRead, BufReader, Read};
 use std::path::Path;
-use std::process::Command;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::thread;
 
 use tracing::{error, info, warn};
 
+mod display_manager;
+mod cmdline;
+pub mod helper;
+mod initramfs;
+mod plan;
+mod status;
+mod wayland;
+
+pub use display_manager::{detect_display_manager, DetectedDisplayManager, DisplayManager};
+pub use initramfs::{
+    custom_command, detect_backend, initramfs_command, set_custom_command,
+    validate_custom_command,
+};
+pub use plan::{FileChange, LineKind, ServiceAction, SwitchPlan};
+pub use status::get_status;
+
 pub const BLACKLIST_PATH: &str = "/etc/modprobe.d/blacklist-nvidia.conf";
 pub const UDEV_INTEGRATED_PATH: &str = "/etc/udev/rules.d/50-remove-nvidia.rules";
 pub const UDEV_PM_PATH: &str = "/etc/udev/rules.d/80-nvidia-pm.rules";
@@ -14,6 +34,10 @@ pub const MODESET_PATH: &str = "/etc/modprobe.d/nvidia.conf";
 pub const SDDM_XSETUP_PATH: &str = "/usr/share/sddm/scripts/Xsetup";
 pub const LIGHTDM_SCRIPT_PATH: &str = "/etc/lightdm/nvidia.sh";
 pub const LIGHTDM_CONFIG_PATH: &str = "/etc/lightdm/lightdm.conf.d/20-nvidia.conf";
+pub const LXDM_LOGIN_READY_PATH: &str = "/etc/lxdm/LoginReady";
+pub const XINITRC_SCRIPT_PATH: &str = "/etc/X11/xinit/xinitrc.d/90-kaede-nvidia.sh";
+
+const PERSISTENCED_SERVICE: &str = "nvidia-persistenced.service";
 
 const BLACKLIST_CONTENT: &str = r#"# Automatically generated by Kaede
 
@@ -210,30 +234,28 @@ pub enum GraphicsMode {
     Nvidia,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum DisplayManager {
-    Gdm,
-    Gdm3,
-    Sddm,
-    Lightdm,
-}
+impl GraphicsMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            GraphicsMode::Integrated => "Integrated",
+            GraphicsMode::Hybrid => "Hybrid",
+            GraphicsMode::Nvidia => "NVIDIA",
+        }
+    }
 
-impl DisplayManager {
-    fn as_str(self) -> &'static str {
+    pub fn as_str(self) -> &'static str {
         match self {
-            DisplayManager::Gdm => "gdm",
-            DisplayManager::Gdm3 => "gdm3",
-            DisplayManager::Sddm => "sddm",
-            DisplayManager::Lightdm => "lightdm",
+            GraphicsMode::Integrated => "integrated",
+            GraphicsMode::Hybrid => "hybrid",
+            GraphicsMode::Nvidia => "nvidia",
         }
     }
 
     fn from_str(s: &str) -> Option<Self> {
         match s {
-            "gdm" => Some(DisplayManager::Gdm),
-            "gdm3" => Some(DisplayManager::Gdm3),
-            "sddm" => Some(DisplayManager::Sddm),
-            "lightdm" => Some(DisplayManager::Lightdm),
+            "integrated" => Some(GraphicsMode::Integrated),
+            "hybrid" => Some(GraphicsMode::Hybrid),
+            "nvidia" => Some(GraphicsMode::Nvidia),
             _ => None,
         }
     }
@@ -262,11 +284,75 @@ impl Default for NvidiaSwitchConfig {
     }
 }
 
+impl NvidiaSwitchConfig {
+    /// Arguments for `kaede-helper switch`.
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = vec![self.mode.as_str().to_string()];
+        if let Some(dm) = self.display_manager {
+            args.extend(["--display-manager".to_string(), dm.as_str().to_string()]);
+        }
+        if self.enable_force_comp {
+            args.push("--force-composition".to_string());
+        }
+        if let Some(value) = self.coolbits_value {
+            args.extend(["--coolbits".to_string(), value.to_string()]);
+        }
+        if let Some(value) = self.rtd3_value {
+            args.extend(["--rtd3".to_string(), value.to_string()]);
+        }
+        if self.use_nvidia_current {
+            args.push("--nvidia-current".to_string());
+        }
+        args
+    }
+
+    /// Parses what [`Self::to_args`] produces, rejecting anything else.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let (mode, options) = args.split_first().ok_or("missing graphics mode")?;
+        let mut config = NvidiaSwitchConfig {
+            mode: GraphicsMode::from_str(mode)
+                .ok_or_else(|| format!("unknown graphics mode '{mode}'"))?,
+            ..Default::default()
+        };
+
+        let mut iter = options.iter();
+        while let Some(option) = iter.next() {
+            let mut value = || {
+                iter.next()
+                    .ok_or_else(|| format!("{option} requires a value"))
+            };
+            match option.as_str() {
+                "--display-manager" => {
+                    let value = value()?;
+                    config.display_manager = Some(
+                        DisplayManager::from_str(value)
+                            .ok_or_else(|| format!("unknown display manager '{value}'"))?,
+                    );
+                }
+                "--force-composition" => config.enable_force_comp = true,
+                "--coolbits" => config.coolbits_value = Some(parse_in_range(value()?, 0..=255)?),
+                "--rtd3" => config.rtd3_value = Some(parse_in_range(value()?, 0..=3)?),
+                "--nvidia-current" => config.use_nvidia_current = true,
+                other => return Err(format!("unexpected argument '{other}'")),
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn parse_in_range(value: &str, range: std::ops::RangeInclusive<i32>) -> Result<i32, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|value| range.contains(value))
+        .ok_or_else(|| format!("'{value}' is not between {} and {}", range.start(), range.end()))
+}
+
 pub fn get_current_mode() -> GraphicsMode {
     let blacklist = Path::new(BLACKLIST_PATH).exists();
     let udev_integrated = Path::new(UDEV_INTEGRATED_PATH).exists()
         || Path::new("/lib/udev/rules.d/50-remove-nvidia.rules").exists();
-    let xorg = Path::new(XORG_PATH).exists();
+    let xorg = Path::new(XORG_PATH).exists() || Path::new(wayland::DRM_RULES_PATH).exists();
     let modeset = Path::new(MODESET_PATH).exists();
 
     if blacklist && udev_integrated {
@@ -278,53 +364,182 @@ pub fn get_current_mode() -> GraphicsMode {
     }
 }
 
-pub fn switch_graphics_mode(config: &NvidiaSwitchConfig) -> Result<(), String> {
+/// Lines printed by the initramfs tool are passed to `output` as they arrive.
+pub fn switch_graphics_mode(
+    config: &NvidiaSwitchConfig,
+    output: &mut dyn FnMut(&str),
+) -> Result<(), String> {
     info!("Switching NVIDIA mode to {:?}", config.mode);
+    let _backup = backup::begin(&format!("Switch NVIDIA mode to {:?}", config.mode));
 
+    let previous = get_current_mode();
+    let staged = stage_switch(config)?;
+    for note in &staged.notes {
+        info!("{note}");
+    }
+    apply(staged.tx, &staged.kernel_params, output)?;
+    status::record_switch(previous);
+    for service in staged.services {
+        run_systemctl(service.action, service.unit);
+    }
+    Ok(())
+}
+
+/// What [`switch_graphics_mode`] would do, without changing anything. Only
+/// reads files, so it doesn't need root.
+pub fn plan_graphics_mode(config: &NvidiaSwitchConfig) -> Result<SwitchPlan, String> {
+    let staged = stage_switch(config)?;
+    let files = staged
+        .tx
+        .changes()
+        .filter_map(|(path, after)| {
+            let before = fs::read(path)
+                .ok()
+                .map(|content| String::from_utf8_lossy(&content).to_string());
+            let after = after.map(|content| String::from_utf8_lossy(content).to_string());
+            (before != after).then(|| FileChange {
+                path: path.to_path_buf(),
+                before,
+                after,
+            })
+        })
+        .collect();
+
+    Ok(SwitchPlan {
+        notes: staged.notes,
+        files,
+        services: staged.services,
+        kernel_commands: staged.kernel_params.commands(),
+        initramfs: initramfs_command(),
+    })
+}
+
+struct StagedSwitch {
+    tx: Transaction,
+    kernel_params: cmdline::KernelParams,
+    services: Vec<ServiceAction>,
+    /// What was detected about the system, worth reading before the switch.
+    notes: Vec<String>,
+}
+
+/// Nothing touches the disk until every file is staged, so a failure while
+/// gathering information leaves the current configuration alone.
+fn stage_switch(config: &NvidiaSwitchConfig) -> Result<StagedSwitch, String> {
+    let mut tx = Transaction::new();
+    let mut notes = Vec::new();
+    let mut params = Vec::new();
+    cleanup(&mut tx);
     match config.mode {
-        GraphicsMode::Integrated => switch_integrated(),
-        GraphicsMode::Hybrid => switch_hybrid(config),
-        GraphicsMode::Nvidia => switch_nvidia(config),
+        GraphicsMode::Integrated => {
+            stage_integrated(&mut tx);
+            params.extend(cmdline::blacklist_params());
+        }
+        GraphicsMode::Hybrid => {
+            stage_hybrid(&mut tx, config);
+            params.extend(cmdline::drm_params(config.use_nvidia_current, false));
+        }
+        GraphicsMode::Nvidia => stage_nvidia(&mut tx, config, &mut notes, &mut params)?,
     }
+    let kernel_params = cmdline::stage(&mut tx, &params, &mut notes);
+
+    let action = match config.mode {
+        GraphicsMode::Integrated => "disable",
+        GraphicsMode::Hybrid | GraphicsMode::Nvidia => "enable",
+    };
+    let services = vec![ServiceAction {
+        action,
+        unit: PERSISTENCED_SERVICE,
+    }];
+    Ok(StagedSwitch {
+        tx,
+        kernel_params,
+        services,
+        notes,
+    })
 }
 
-pub fn reset_all() -> Result<(), String> {
-    cleanup();
-    rebuild_initramfs()?;
+pub fn reset_all(output: &mut dyn FnMut(&str)) -> Result<(), String> {
+    let _backup = backup::begin("Reset NVIDIA configuration");
+    let previous = get_current_mode();
+    let mut tx = Transaction::new();
+    cleanup(&mut tx);
+    let kernel_params = cmdline::stage(&mut tx, &[], &mut Vec::new());
+    apply(tx, &kernel_params, output)?;
+    status::record_switch(previous);
     Ok(())
 }
 
 pub fn reset_sddm() -> Result<(), String> {
-    create_file(SDDM_XSETUP_PATH, SDDM_XSETUP_CONTENT, true)?;
+    let _backup = backup::begin("Reset SDDM Xsetup");
+    let mut tx = Transaction::new();
+    tx.write_executable(SDDM_XSETUP_PATH, SDDM_XSETUP_CONTENT);
+    tx.commit().map_err(|e| format!("{e:#}"))?;
     Ok(())
 }
 
-fn switch_integrated() -> Result<(), String> {
-    run_systemctl("disable", "nvidia-persistenced.service");
-
-    cleanup();
+/// Writes the staged files, applies the kernel command line and rebuilds the
+/// initramfs, putting everything back if any step fails.
+fn apply(
+    tx: Transaction,
+    kernel_params: &cmdline::KernelParams,
+    output: &mut dyn FnMut(&str),
+) -> Result<(), String> {
+    let applied = tx.commit().map_err(|e| format!("{e:#}"))?;
+    let mut rebuild_started = false;
+    let result = kernel_params.run(output).and_then(|()| {
+        rebuild_started = true;
+        initramfs::rebuild(output)
+    });
+    let Err(err) = result else {
+        return Ok(());
+    };
 
-    create_file(BLACKLIST_PATH, BLACKLIST_CONTENT, false)?;
-    create_file(UDEV_INTEGRATED_PATH, UDEV_INTEGRATED, false)?;
+    let mut failures = Vec::new();
+    match applied.rollback() {
+        // The failed rebuild may have left an image built from the new files.
+        Ok(()) if rebuild_started => {
+            if let Err(rebuild_err) = initramfs::rebuild(output) {
+                failures.push(format!("the initramfs may be stale: {rebuild_err}"));
+            }
+        }
+        Ok(()) => {}
+        Err(rollback_err) => {
+            failures.push(format!("{rollback_err:#}"));
+            if rebuild_started {
+                failures.push("the initramfs was not rebuilt and may be stale".to_string());
+            }
+        }
+    }
+    if let Err(undo_err) = kernel_params.undo(output) {
+        failures.push(undo_err);
+    }
+    if failures.is_empty() {
+        Err(format!("{err}; the previous configuration was restored"))
+    } else {
+        Err(format!(
+            "{err}; restoring the previous configuration also failed: {}",
+            failures.join("; ")
+        ))
+    }
+}
 
-    rebuild_initramfs()
+fn stage_integrated(tx: &mut Transaction) {
+    tx.write(BLACKLIST_PATH, BLACKLIST_CONTENT);
+    tx.write(UDEV_INTEGRATED_PATH, UDEV_INTEGRATED);
 }
 
-fn switch_hybrid(config: &NvidiaSwitchConfig) -> Result<(), String> {
+fn stage_hybrid(tx: &mut Transaction, config: &NvidiaSwitchConfig) {
     info!(
         "Enable PCI-Express Runtime D3 (RTD3) Power Management: {:?}",
         config.rtd3_value
     );
-    cleanup();
-
-    run_systemctl("enable", "nvidia-persistenced.service");
 
     match config.rtd3_value {
         None => {
             if config.use_nvidia_current {
-                create_file(MODESET_PATH, MODESET_CURRENT_CONTENT, false)?;
+                tx.write(MODESET_PATH, MODESET_CURRENT_CONTENT);
             } else {
-                create_file(MODESET_PATH, MODESET_CONTENT, false)?;
+                tx.write(MODESET_PATH, MODESET_CONTENT);
             }
         }
         Some(v) => {
@@ -333,23 +548,51 @@ fn switch_hybrid(config: &NvidiaSwitchConfig) -> Result<(), String> {
             } else {
                 MODESET_RTD3_FMT.replace("{}", &v.to_string())
             };
-            create_file(MODESET_PATH, &content, false)?;
-            create_file(UDEV_PM_PATH, UDEV_PM_CONTENT, false)?;
+            tx.write(MODESET_PATH, content);
+            tx.write(UDEV_PM_PATH, UDEV_PM_CONTENT);
         }
     }
-
-    rebuild_initramfs()
 }
 
-fn switch_nvidia(config: &NvidiaSwitchConfig) -> Result<(), String> {
+fn stage_nvidia(
+    tx: &mut Transaction,
+    config: &NvidiaSwitchConfig,
+    notes: &mut Vec<String>,
+    params: &mut Vec<String>,
+) -> Result<(), String> {
     info!(
         "Enable ForceCompositionPipeline: {}, Coolbits: {:?}",
         config.enable_force_comp, config.coolbits_value
     );
 
-    run_systemctl("enable", "nvidia-persistenced.service");
+    let sessions = wayland::Sessions::detect();
+    notes.push(sessions.describe());
+    params.extend(cmdline::drm_params(
+        config.use_nvidia_current,
+        !sessions.wayland.is_empty(),
+    ));
+
+    let modeset = if config.use_nvidia_current {
+        MODESET_CURRENT_CONTENT
+    } else {
+        MODESET_CONTENT
+    };
+    if sessions.wayland.is_empty() {
+        tx.write(MODESET_PATH, modeset);
+    } else {
+        // Wayland compositors need the DRM framebuffer console (driver 545+);
+        // older drivers ignore the unknown parameter.
+        tx.write(MODESET_PATH, modeset.replace("modeset=1", "modeset=1 fbdev=1"));
+        wayland::stage(tx, &sessions, notes)?;
+    }
 
-    cleanup();
+    if !sessions.wants_x11() {
+        notes.push(
+            "No X11 sessions are installed, so xorg.conf and the display manager scripts are skipped"
+                .to_string(),
+        );
+        return Ok(());
+    }
 
     let nvidia_gpu_pci_bus = get_nvidia_gpu_pci_bus()?;
     let igpu_vendor = get_igpu_vendor();
@@ -357,23 +600,17 @@ fn switch_nvidia(config: &NvidiaSwitchConfig) -> Result<(), String> {
     match igpu_vendor.as_deref() {
         Some("intel") => {
             let content = XORG_INTEL.replace("{}", &nvidia_gpu_pci_bus);
-            create_file(XORG_PATH, &content, false)?;
+            tx.write(XORG_PATH, content);
         }
         Some("amd") => {
             let content = XORG_AMD.replace("{}", &nvidia_gpu_pci_bus);
-            create_file(XORG_PATH, &content, false)?;
+            tx.write(XORG_PATH, content);
         }
         _ => {
             warn!("Could not determine iGPU vendor; skipping Xorg configuration");
         }
     }
 
-    if config.use_nvidia_current {
-        create_file(MODESET_PATH, MODESET_CURRENT_CONTENT, false)?;
-    } else {
-        create_file(MODESET_PATH, MODESET_CONTENT, false)?;
-    }
-
     if config.enable_force_comp || config.coolbits_value.is_some() {
         let mut extra = String::from(EXTRA_XORG_CONTENT);
         if config.enable_force_comp {
@@ -383,37 +620,32 @@ fn switch_nvidia(config: &NvidiaSwitchConfig) -> Result<(), String> {
             extra.push_str(&COOLBITS_FMT.replace("{}", &v.to_string()));
         }
         extra.push_str("EndSection\n");
-        create_file(EXTRA_XORG_PATH, &extra, false)?;
+        tx.write(EXTRA_XORG_PATH, extra);
     }
 
-    let dm = match config.display_manager.or_else(detect_display_manager) {
-        Some(dm) => dm,
-        None => {
-            warn!("Display Manager detection is not available; skipping DM-specific configuration");
-            rebuild_initramfs()?;
+    let detected = match config.display_manager {
+        Some(dm) => DetectedDisplayManager::Supported(dm),
+        None => detect_display_manager(),
+    };
+    let dm = match detected {
+        DetectedDisplayManager::Supported(dm) => dm,
+        DetectedDisplayManager::Unsupported(name) => {
+            notes.push(format!(
+                "Display manager {name} is not supported, so the login screen is left alone; pick one under Display Manager if it runs an X11 greeter"
+            ));
             return Ok(());
         }
-    };
-
-    if matches!(dm, DisplayManager::Sddm | DisplayManager::Lightdm) {
-        let igpu_vendor = igpu_vendor.unwrap_or_default();
-        let script = generate_xrandr_script(&igpu_vendor);
-
-        if matches!(dm, DisplayManager::Sddm) {
-            if Path::new(SDDM_XSETUP_PATH).exists() {
-                info!("Creating Xsetup backup");
-                if let Ok(existing) = fs::read_to_string(SDDM_XSETUP_PATH) {
-                    let _ = create_file(&format!("{SDDM_XSETUP_PATH}.bak"), &existing, false);
-                }
-            }
-            create_file(SDDM_XSETUP_PATH, &script, true)?;
-        } else if matches!(dm, DisplayManager::Lightdm) {
-            create_file(LIGHTDM_SCRIPT_PATH, &script, true)?;
-            create_file(LIGHTDM_CONFIG_PATH, LIGHTDM_CONFIG_CONTENT, false)?;
+        DetectedDisplayManager::NotFound => {
+            notes.push(
+                "No display manager was detected, so the login screen is left alone".to_string(),
+            );
+            return Ok(());
         }
-    }
+    };
 
-    rebuild_initramfs()
+    let script = generate_xrandr_script(&igpu_vendor.unwrap_or_default());
+    display_manager::stage_setup_script(tx, dm, script, notes);
+    Ok(())
 }
 
 fn run_systemctl(action: &str, service: &str) {
@@ -438,7 +670,7 @@ fn run_systemctl(action: &str, service: &str) {
     }
 }
 
-fn cleanup() {
+fn cleanup(tx: &mut Transaction) {
     let to_remove = [
         BLACKLIST_PATH,
         UDEV_INTEGRATED_PATH,
@@ -448,35 +680,23 @@ fn cleanup() {
         MODESET_PATH,
         LIGHTDM_SCRIPT_PATH,
         LIGHTDM_CONFIG_PATH,
+        XINITRC_SCRIPT_PATH,
         "/etc/X11/xorg.conf.d/90-nvidia.conf",
         "/lib/udev/rules.d/50-remove-nvidia.rules",
         "/lib/udev/rules.d/80-nvidia-pm.rules",
     ];
 
-    for path in &to_remove {
-        if let Err(err) = fs::remove_file(path).or_else(|e| {
-            if e.kind() == io::ErrorKind::NotFound {
-                Ok(())
-            } else {
-                Err(e)
-            }
-        }) {
-            error!("Failed to remove file '{path}': {err}");
-        } else {
-            info!("Removed file {path}");
-        }
+    for path in to_remove {
+        tx.remove(path);
     }
-
-    let backup_path = format!("{SDDM_XSETUP_PATH}.bak");
-    if Path::new(&backup_path).exists() {
-        info!("Restoring Xsetup backup");
-        if let Ok(contents) = fs::read_to_string(&backup_path) {
-            let _ = create_file(SDDM_XSETUP_PATH, &contents, false);
-        }
-        if let Err(err) = fs::remove_file(&backup_path) {
-            error!("Failed to remove file '{backup_path}': {err}");
-        } else {
-            info!("Removed file {backup_path}");
+    wayland::cleanup(tx);
+
+    for script in [SDDM_XSETUP_PATH, LXDM_LOGIN_READY_PATH] {
+        let backup_path = format!("{script}.bak");
+        if let Ok(contents) = fs::read(&backup_path) {
+            info!("Restoring {script} backup");
+            tx.write(script, contents);
+            tx.remove(backup_path);
         }
     }
 }
@@ -547,26 +767,6 @@ fn get_igpu_vendor() -> Option<String> {
     None
 }
 
-fn detect_display_manager() -> Option<DisplayManager> {
-    let path = "/etc/systemd/system/display-manager.service";
-    let content = fs::read_to_string(path).ok()?;
-    for line in content.lines() {
-        if let Some(rest) = line.strip_prefix("ExecStart=") {
-            let binary = Path::new(rest.trim())
-                .file_name()
-                .and_then(|s| s.to_str())
-                .unwrap_or("");
-            let dm = DisplayManager::from_str(binary);
-            if let Some(dm) = dm {
-                info!("Found {} Display Manager", dm.as_str());
-                return Some(dm);
-            }
-        }
-    }
-    warn!("Display Manager detection is not available");
-    None
-}
-
 fn generate_xrandr_script(igpu_vendor: &str) -> String {
     let provider = if igpu_vendor == "intel" {
         "modesetting".to_string()
@@ -614,62 +814,34 @@ fn get_amd_igpu_name() -> Option<String> {
     None
 }
 
-fn rebuild_initramfs() -> Result<(), String> {
-    let cmd = if Path::new("/ostree").exists() || Path::new("/sysroot/ostree").exists() {
-        Some(vec!["rpm-ostree", "initramfs", "--enable", "--arg=--force"])
-    } else if Path::new("/etc/debian_version").exists() {
-        Some(vec!["update-initramfs", "-u", "-k", "all"])
-    } else if Path::new("/etc/redhat-release").exists() || Path::new("/usr/bin/zypper").exists() {
-        Some(vec!["dracut", "--force", "--regenerate-all"])
-    } else if Path::new("/usr/lib/endeavouros-release").exists() && Path::new("/usr/bin/dracut").exists() {
-        Some(vec!["dracut-rebuild"])
-    } else if Path::new("/etc/altlinux-release").exists() {
-        Some(vec!["make-initrd"])
-    } else if Path::new("/etc/arch-release").exists() {
-        Some(vec!["mkinitcpio", "-P"])
-    } else {
-        None
-    };
-
-    let Some(base_cmd) = cmd else {
-        info!("No known initramfs tool detected; skipping initramfs rebuild");
-        return Ok(());
-    };
-
-    info!("Rebuilding the initramfs...");
-    let status = Command::new(base_cmd[0])
-        .args(&base_cmd[1..])
-        .status()
-        .map_err(|e| format!("failed to run initramfs command: {e}"))?;
-
-    if status.success() {
-        info!("Successfully rebuilt the initramfs!");
-        Ok(())
-    } else {
-        error!("An error occurred while rebuilding the initramfs");
-        Err("initramfs rebuild failed".to_string())
-    }
-}
-
-fn create_file(path: &str, content: &str, executable: bool) -> Result<(), String> {
-    let p = Path::new(path);
-    if let Some(parent) = p.parent() {
-        if let Err(err) = fs::create_dir_all(parent) {
-            return Err(format!("failed to create parent directory for {path}: {err}"));
-        }
+/// Runs `command` and passes every line it prints, on stdout or stderr, to
+/// `output` as soon as it is printed.
+fn run_with_output(command: &mut Command, output: &mut dyn FnMut(&str)) -> io::Result<ExitStatus> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let (tx, rx) = mpsc::channel();
+    let streams: [Option<Box<dyn Read + Send>>; 2] = [
+        child.stdout.take().map(|s| Box::new(s) as Box<dyn Read + Send>),
+        child.stderr.take().map(|s| Box::new(s) as Box<dyn Read + Send>),
+    ];
+    for stream in streams.into_iter().flatten() {
+        let tx = tx.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
     }
+    drop(tx);
 
-    fs::write(path, content).map_err(|e| format!("failed to create file '{path}': {e}"))?;
-    info!("Created file {path}");
-
-    if executable {
-        Command::new("chmod")
-            .arg("+x")
-            .arg(path)
-            .status()
-            .map_err(|e| format!("failed to add execution privilege to file {path}: {e}"))?;
+    for line in rx {
+        output(&line);
     }
-
-    Ok(())
+    child.wait()
 }
-
@@ -0,0 +1,125 @@
+//! What a graphics mode switch would change, for review before it runs.
+
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct FileChange {
+    pub path: PathBuf,
+    /// `None` when the file doesn't exist yet.
+    pub before: Option<String>,
+    /// `None` when the file is removed.
+    pub after: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ServiceAction {
+    pub action: &'static str,
+    pub unit: &'static str,
+}
+
+#[derive(Debug, Clone)]
+pub struct SwitchPlan {
+    /// What was detected about the system, e.g. the installed session types.
+    pub notes: Vec<String>,
+    pub files: Vec<FileChange>,
+    pub services: Vec<ServiceAction>,
+    /// Commands that apply the kernel command line, run before the initramfs
+    /// is rebuilt.
+    pub kernel_commands: Vec<String>,
+    /// `None` when no supported initramfs tool was found.
+    pub initramfs: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Heading,
+    Added,
+    Removed,
+    Unchanged,
+    Command,
+    Note,
+}
+
+impl SwitchPlan {
+    /// True when the configuration on disk already matches the requested mode.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// The plan as a diff-style listing: notes first, then every file with its changed lines,
+    /// then the commands that run afterwards.
+    pub fn lines(&self) -> Vec<(LineKind, String)> {
+        let mut lines = self
+            .notes
+            .iter()
+            .map(|note| (LineKind::Note, note.clone()))
+            .collect::<Vec<_>>();
+        for file in &self.files {
+            let heading = match (&file.before, &file.after) {
+                (None, _) => format!("{} (new file)", file.path.display()),
+                (_, None) => format!("{} (removed)", file.path.display()),
+                _ => file.path.display().to_string(),
+            };
+            lines.push((LineKind::Heading, heading));
+            lines.extend(diff_lines(
+                file.before.as_deref().unwrap_or_default(),
+                file.after.as_deref().unwrap_or_default(),
+            ));
+        }
+
+        for command in &self.kernel_commands {
+            lines.push((LineKind::Command, command.clone()));
+        }
+        for service in &self.services {
+            lines.push((
+                LineKind::Command,
+                format!("systemctl {} {}", service.action, service.unit),
+            ));
+        }
+        match &self.initramfs {
+            Some(command) => lines.push((LineKind::Command, command.join(" "))),
+            None => lines.push((
+                LineKind::Heading,
+                "No initramfs tool detected; the initramfs is not rebuilt".to_string(),
+            )),
+        }
+        lines
+    }
+}
+
+/// Line diff over the longest common subsequence. The files involved are a
+/// few dozen lines, so the quadratic table is fine.
+fn diff_lines(before: &str, after: &str) -> Vec<(LineKind, String)> {
+    let a = before.lines().collect::<Vec<_>>();
+    let b = after.lines().collect::<Vec<_>>();
+
+    let mut common = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            common[i][j] = if a[i] == b[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            out.push((LineKind::Unchanged, a[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if common[i + 1][j] >= common[i][j + 1] {
+            out.push((LineKind::Removed, a[i].to_string()));
+            i += 1;
+        } else {
+            out.push((LineKind::Added, b[j].to_string()));
+            j += 1;
+        }
+    }
+    out.extend(a[i..].iter().map(|line| (LineKind::Removed, line.to_string())));
+    out.extend(b[j..].iter().map(|line| (LineKind::Added, line.to_string())));
+    out
+}
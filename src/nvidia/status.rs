@@ -0,0 +1,243 @@
+//! What the running system is actually doing, as opposed to what the files
+//! in `/etc` say it will do after the next boot.
+
+use super::{cmdline, get_current_mode, GraphicsMode};
+use std::fs;
+use std::path::Path;
+use tracing::{debug, warn};
+
+const NVIDIA_VENDOR: &str = "0x10de";
+/// Written after every switch so the next status check knows whether the
+/// running kernel predates it.
+const SWITCH_MARKER_PATH: &str = "/var/lib/kaede/nvidia-switch";
+const BOOT_ID_PATH: &str = "/proc/sys/kernel/random/boot_id";
+const NVIDIA_MODULES: [&str; 2] = ["nvidia", "nvidia_current"];
+const MODESET_PARAMETERS: [&str; 2] = [
+    "/sys/module/nvidia_drm/parameters/modeset",
+    "/sys/module/nvidia_current_drm/parameters/modeset",
+];
+
+#[derive(Debug, Clone)]
+pub struct NvidiaStatus {
+    /// What the files in `/etc` select for the next boot.
+    pub configured: GraphicsMode,
+    /// What the running kernel is doing.
+    pub active: GraphicsMode,
+    /// An NVIDIA display controller is on the PCI bus.
+    pub gpu_present: bool,
+    pub module_loaded: bool,
+    /// Kernel driver bound to the NVIDIA GPU, e.g. `nvidia` or `nouveau`.
+    pub bound_driver: Option<String>,
+    /// `nvidia_drm.modeset`; `None` when `nvidia_drm` isn't loaded.
+    pub drm_modeset: Option<bool>,
+    /// Kaede switched modes since the last boot.
+    pub switched_this_boot: bool,
+    /// NVIDIA-related parameters on the running kernel's command line.
+    pub kernel_params: Vec<String>,
+}
+
+impl NvidiaStatus {
+    pub fn reboot_required(&self) -> bool {
+        self.active != self.configured
+    }
+
+    /// States that a reboot alone won't explain.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.switched_this_boot {
+            return warnings;
+        }
+
+        let nvidia_bound = self.bound_driver.as_deref() == Some("nvidia");
+        match self.configured {
+            GraphicsMode::Integrated => {
+                if self.module_loaded {
+                    warnings.push(
+                        "The nvidia module is loaded although the NVIDIA GPU is configured as disabled"
+                            .to_string(),
+                    );
+                }
+            }
+            GraphicsMode::Hybrid | GraphicsMode::Nvidia => {
+                if !self.gpu_present {
+                    warnings.push("No NVIDIA GPU was found on the PCI bus".to_string());
+                } else if !nvidia_bound {
+                    warnings.push(format!(
+                        "The NVIDIA GPU is driven by {} instead of nvidia; is the driver installed?",
+                        self.bound_driver.as_deref().unwrap_or("no driver")
+                    ));
+                }
+                let blacklisted = self.kernel_params.iter().any(|param| {
+                    param.starts_with("module_blacklist=") && param.contains("nvidia")
+                });
+                if blacklisted {
+                    warnings.push(
+                        "The kernel command line blacklists the nvidia module".to_string(),
+                    );
+                }
+                if nvidia_bound && self.drm_modeset == Some(false) {
+                    warnings.push(
+                        "nvidia_drm.modeset is off, so PRIME offload and Wayland won't work"
+                            .to_string(),
+                    );
+                }
+            }
+        }
+        warnings
+    }
+
+    /// e.g. "Hybrid active" or "Integrated configured — reboot required (Hybrid active)".
+    pub fn summary(&self) -> String {
+        if self.reboot_required() {
+            format!(
+                "{} configured — reboot required ({} active)",
+                self.configured.label(),
+                self.active.label()
+            )
+        } else {
+            format!("{} active", self.active.label())
+        }
+    }
+}
+
+pub fn get_status() -> NvidiaStatus {
+    let configured = get_current_mode();
+    let gpu = nvidia_gpu_driver();
+    let gpu_present = gpu.is_some();
+    let bound_driver = gpu.flatten();
+    let module_loaded = nvidia_module_loaded();
+    let marker = read_switch_marker();
+    let switched_this_boot = marker.is_some();
+
+    let active = match marker {
+        Some(previous) => previous,
+        None if !module_loaded || bound_driver.as_deref() != Some("nvidia") => {
+            GraphicsMode::Integrated
+        }
+        // The driver is running but the files say it shouldn't; whatever was
+        // configured at boot, it wasn't Integrated.
+        None if configured == GraphicsMode::Integrated => GraphicsMode::Hybrid,
+        None => configured,
+    };
+
+    let status = NvidiaStatus {
+        configured,
+        active,
+        gpu_present,
+        module_loaded,
+        bound_driver,
+        drm_modeset: drm_modeset(),
+        switched_this_boot,
+        kernel_params: cmdline::running_params(),
+    };
+    debug!(status = ?status, "NVIDIA status");
+    status
+}
+
+/// Remembers the mode that was active when this boot started, unless an
+/// earlier switch in the same boot already did.
+pub(super) fn record_switch(previous: GraphicsMode) {
+    if read_switch_marker().is_some() {
+        return;
+    }
+    let Some(boot_id) = boot_id() else {
+        return;
+    };
+    let content = format!("boot_id={boot_id}\nprevious={}\n", previous.as_str());
+    let path = Path::new(SWITCH_MARKER_PATH);
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(path, content));
+    if let Err(err) = result {
+        warn!("Failed to record the NVIDIA mode switch in {SWITCH_MARKER_PATH}: {err}");
+    }
+}
+
+/// The mode active at boot, if Kaede switched since then.
+fn read_switch_marker() -> Option<GraphicsMode> {
+    let content = fs::read_to_string(SWITCH_MARKER_PATH).ok()?;
+    let field = |key: &str| {
+        content
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            .map(str::trim)
+    };
+    if field("boot_id")? != boot_id()? {
+        return None;
+    }
+    GraphicsMode::from_str(field("previous")?)
+}
+
+fn boot_id() -> Option<String> {
+    fs::read_to_string(BOOT_ID_PATH)
+        .ok()
+        .map(|id| id.trim().to_string())
+}
+
+/// `None` when there is no NVIDIA GPU; `Some(None)` when it has no driver.
+fn nvidia_gpu_driver() -> Option<Option<String>> {
+    display_devices()
+        .into_iter()
+        .find(DisplayDevice::is_nvidia)
+        .map(|device| device.driver)
+}
+
+/// A display controller on the PCI bus.
+pub(super) struct DisplayDevice {
+    /// Sysfs address, e.g. `0000:01:00.0`.
+    pub address: String,
+    pub vendor: String,
+    pub driver: Option<String>,
+}
+
+impl DisplayDevice {
+    pub fn is_nvidia(&self) -> bool {
+        self.vendor == NVIDIA_VENDOR
+    }
+}
+
+pub(super) fn display_devices() -> Vec<DisplayDevice> {
+    let Ok(entries) = fs::read_dir("/sys/bus/pci/devices") else {
+        return Vec::new();
+    };
+    let mut devices = entries
+        .flatten()
+        .filter_map(|entry| {
+            let device = entry.path();
+            let attr = |name: &str| {
+                fs::read_to_string(device.join(name))
+                    .ok()
+                    .map(|value| value.trim().to_string())
+            };
+            if !attr("class")?.starts_with("0x03") {
+                return None;
+            }
+            Some(DisplayDevice {
+                address: entry.file_name().to_string_lossy().to_string(),
+                vendor: attr("vendor")?,
+                driver: fs::read_link(device.join("driver"))
+                    .ok()
+                    .and_then(|driver| Some(driver.file_name()?.to_string_lossy().to_string())),
+            })
+        })
+        .collect::<Vec<_>>();
+    devices.sort_by(|a, b| a.address.cmp(&b.address));
+    devices
+}
+
+fn nvidia_module_loaded() -> bool {
+    fs::read_to_string("/proc/modules").is_ok_and(|modules| {
+        modules
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .any(|name| NVIDIA_MODULES.contains(&name))
+    })
+}
+
+fn drm_modeset() -> Option<bool> {
+    MODESET_PARAMETERS.iter().find_map(|path| {
+        let value = fs::read_to_string(path).ok()?;
+        Some(matches!(value.trim(), "Y" | "1"))
+    })
+}
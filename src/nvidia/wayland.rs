@@ -0,0 +1,219 @@
+//! The Wayland half of the dedicated NVIDIA mode. `xorg.conf` and the Xsetup
+//! scripts only reach X11; Wayland compositors pick their primary GPU from udev
+//! tags and environment variables instead.
+
+use super::status::display_devices;
+use crate::transaction::Transaction;
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+pub const GDM_RULES_OVERRIDE_PATH: &str = "/etc/udev/rules.d/61-gdm.rules";
+pub const DRM_RULES_PATH: &str = "/etc/udev/rules.d/61-kaede-drm.rules";
+pub const ENVIRONMENT_PATH: &str = "/etc/environment.d/90-kaede-nvidia.conf";
+
+const GDM_RULES_PATHS: [&str; 2] = [
+    "/usr/lib/udev/rules.d/61-gdm.rules",
+    "/lib/udev/rules.d/61-gdm.rules",
+];
+const GENERATED_MARKER: &str = "# Automatically generated by Kaede";
+const SESSION_DIRS: [&str; 2] = ["/usr/share", "/usr/local/share"];
+
+// Compositors take colon-separated device lists, and /dev/dri/by-path names
+// contain colons, so the udev rule adds symlinks without them.
+const NVIDIA_DEVICE: &str = "/dev/dri/kaede-nvidia";
+const IGPU_DEVICE: &str = "/dev/dri/kaede-igpu";
+
+const GDM_RULES_OVERRIDE_CONTENT: &str = r#"# Automatically generated by Kaede
+
+# Masks GDM's 61-gdm.rules, which turns Wayland off on most NVIDIA systems.
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Compositor {
+    Gnome,
+    Plasma,
+    Hyprland,
+    /// Sway and the other wlroots compositors.
+    Wlroots,
+    Other,
+}
+
+impl Compositor {
+    fn label(self) -> &'static str {
+        match self {
+            Compositor::Gnome => "GNOME",
+            Compositor::Plasma => "Plasma",
+            Compositor::Hyprland => "Hyprland",
+            Compositor::Wlroots => "wlroots",
+            Compositor::Other => "other",
+        }
+    }
+
+    /// `session` is the session file name and its `Exec=` line, lowercased.
+    fn from_session(session: &str) -> Self {
+        if session.contains("gnome") {
+            Compositor::Gnome
+        } else if session.contains("plasma") || session.contains("kwin") {
+            Compositor::Plasma
+        } else if session.contains("hyprland") {
+            Compositor::Hyprland
+        } else if ["sway", "river", "wayfire", "labwc", "dwl"]
+            .iter()
+            .any(|name| session.contains(name))
+        {
+            Compositor::Wlroots
+        } else {
+            Compositor::Other
+        }
+    }
+}
+
+/// The session types a display manager offers, from the installed session files.
+pub(super) struct Sessions {
+    pub wayland: Vec<Compositor>,
+    pub x11: bool,
+}
+
+impl Sessions {
+    pub fn detect() -> Self {
+        let mut wayland = Vec::new();
+        for session in session_files("wayland-sessions") {
+            let compositor = Compositor::from_session(&session);
+            if !wayland.contains(&compositor) {
+                wayland.push(compositor);
+            }
+        }
+        let sessions = Sessions {
+            wayland,
+            x11: !session_files("xsessions").is_empty(),
+        };
+        info!(wayland = ?sessions.wayland, x11 = sessions.x11, "Detected session types");
+        sessions
+    }
+
+    /// Without any session files to go by, X11 is configured as it always was.
+    pub fn wants_x11(&self) -> bool {
+        self.x11 || self.wayland.is_empty()
+    }
+
+    pub fn describe(&self) -> String {
+        let wayland = match self.wayland.as_slice() {
+            [] => "none".to_string(),
+            compositors => compositors
+                .iter()
+                .map(|compositor| compositor.label())
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        let x11 = if self.x11 { "yes" } else { "none" };
+        format!("Sessions found: Wayland: {wayland}; X11: {x11}")
+    }
+
+    fn has(&self, compositor: Compositor) -> bool {
+        self.wayland.contains(&compositor)
+    }
+}
+
+/// Lowercased file name and `Exec=` line of every `.desktop` file in
+/// `<share>/<kind>`.
+fn session_files(kind: &str) -> Vec<String> {
+    SESSION_DIRS
+        .iter()
+        .filter_map(|share| fs::read_dir(Path::new(share).join(kind)).ok())
+        .flat_map(|entries| entries.flatten())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "desktop"))
+        .map(|path| {
+            let exec = fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| {
+                    content
+                        .lines()
+                        .find_map(|line| line.strip_prefix("Exec=").map(str::to_string))
+                })
+                .unwrap_or_default();
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            format!("{name} {exec}").to_lowercase()
+        })
+        .collect()
+}
+
+/// Makes the NVIDIA GPU the primary device for the detected compositors.
+/// Anything worth knowing before the switch is added to `notes`.
+pub(super) fn stage(
+    tx: &mut Transaction,
+    sessions: &Sessions,
+    notes: &mut Vec<String>,
+) -> Result<(), String> {
+    let devices = display_devices();
+    let nvidia = devices
+        .iter()
+        .find(|device| device.is_nvidia())
+        .ok_or("Could not find Nvidia GPU; try switching to hybrid mode first")?;
+    let igpu = devices.iter().find(|device| !device.is_nvidia());
+    info!(nvidia = %nvidia.address, igpu = ?igpu.map(|d| &d.address), "Staging Wayland GPU order");
+
+    let mut rules = format!(
+        "{GENERATED_MARKER}\n\n# Stable names for {NVIDIA_DEVICE} and {IGPU_DEVICE}\n\
+         SUBSYSTEM==\"drm\", KERNEL==\"card[0-9]*\", KERNELS==\"{}\", SYMLINK+=\"dri/kaede-nvidia\"",
+        nvidia.address
+    );
+    if sessions.has(Compositor::Gnome) {
+        rules.push_str(", TAG+=\"mutter-device-preferred-primary\"");
+    }
+    rules.push('\n');
+    if let Some(igpu) = igpu {
+        rules.push_str(&format!(
+            "SUBSYSTEM==\"drm\", KERNEL==\"card[0-9]*\", KERNELS==\"{}\", SYMLINK+=\"dri/kaede-igpu\"\n",
+            igpu.address
+        ));
+    }
+    tx.write(DRM_RULES_PATH, rules);
+
+    let devices = match igpu {
+        Some(_) => format!("{NVIDIA_DEVICE}:{IGPU_DEVICE}"),
+        None => NVIDIA_DEVICE.to_string(),
+    };
+    let variables = [
+        (Compositor::Plasma, "KWIN_DRM_DEVICES"),
+        (Compositor::Hyprland, "AQ_DRM_DEVICES"),
+        (Compositor::Wlroots, "WLR_DRM_DEVICES"),
+    ];
+    let mut environment = String::new();
+    for (compositor, variable) in variables {
+        if sessions.has(compositor) {
+            environment.push_str(&format!("{variable}={devices}\n"));
+        }
+    }
+    if !environment.is_empty() {
+        tx.write(ENVIRONMENT_PATH, format!("{GENERATED_MARKER}\n\n{environment}"));
+    }
+
+    if sessions.has(Compositor::Other) {
+        notes.push(
+            "Some Wayland sessions aren't recognized; they may still start on the integrated GPU"
+                .to_string(),
+        );
+    }
+
+    if GDM_RULES_PATHS.iter().any(|path| Path::new(path).exists()) {
+        match fs::read_to_string(GDM_RULES_OVERRIDE_PATH) {
+            Ok(existing) if !existing.starts_with(GENERATED_MARKER) => notes.push(format!(
+                "{GDM_RULES_OVERRIDE_PATH} was not written by Kaede and is left alone; GDM may still disable Wayland"
+            )),
+            _ => tx.write(GDM_RULES_OVERRIDE_PATH, GDM_RULES_OVERRIDE_CONTENT),
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn cleanup(tx: &mut Transaction) {
+    tx.remove(DRM_RULES_PATH);
+    tx.remove(ENVIRONMENT_PATH);
+    let generated = fs::read_to_string(GDM_RULES_OVERRIDE_PATH)
+        .is_ok_and(|content| content.starts_with(GENERATED_MARKER));
+    if generated {
+        tx.remove(GDM_RULES_OVERRIDE_PATH);
+    }
+}
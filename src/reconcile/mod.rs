@@ -0,0 +1,281 @@
+use crate::config::ConfigStore;
+use crate::gpu::gpu_for_choice;
+use crate::launcher::{
+    apply_launcher_override, read_launcher_state, record_override_source, remove_desktop_override,
+};
+use crate::models::{DesktopApp, GpuChoice, GpuInfo, SteamAccountScope};
+use crate::steam::is_steam_running;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone)]
+pub enum DriftStatus {
+    InSync,
+    /// On-disk state differs from the config; `observed` is the `DRI_PRIME`
+    /// found in a Kaede-managed override, `None` when no override is present.
+    Drifted { observed: Option<usize> },
+    GpuMissing,
+    /// The override matches the config but was built from an older version
+    /// of the application's `.desktop` file.
+    SourceChanged,
+    Unreadable(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct DriftReport {
+    pub app: DesktopApp,
+    pub choice: GpuChoice,
+    pub action_choices: BTreeMap<String, GpuChoice>,
+    pub steam_accounts: SteamAccountScope,
+    pub status: DriftStatus,
+}
+
+impl DriftReport {
+    pub fn needs_attention(&self) -> bool {
+        !matches!(self.status, DriftStatus::InSync)
+    }
+
+    pub fn describe(&self, gpus: &[GpuInfo]) -> String {
+        match &self.status {
+            DriftStatus::InSync => "In sync".to_string(),
+            DriftStatus::Drifted { observed } => {
+                let on_disk = match observed {
+                    Some(idx) => gpus
+                        .iter()
+                        .find(|g| g.dri_prime_index == Some(*idx))
+                        .map(|g| format!("{} (#{idx})", g.name_for_filter()))
+                        .unwrap_or_else(|| format!("GPU {idx}")),
+                    None => "no Kaede override".to_string(),
+                };
+                format!("Expected {}, found {on_disk}", self.choice.label())
+            }
+            DriftStatus::GpuMissing => {
+                let missing = std::iter::once(&self.choice)
+                    .chain(self.action_choices.values())
+                    .find(|c| **c != GpuChoice::Default && gpu_for_choice(gpus, c).is_none())
+                    .unwrap_or(&self.choice);
+                format!("Assigned GPU missing ({})", missing.label())
+            }
+            DriftStatus::SourceChanged => {
+                "Launcher outdated: the original .desktop file changed".to_string()
+            }
+            DriftStatus::Unreadable(err) => format!("Could not read launcher state: {err}"),
+        }
+    }
+}
+
+/// Reads back the launcher state of every saved assignment whose app is installed.
+pub fn check_assignments(
+    config: &ConfigStore,
+    apps: &[DesktopApp],
+    gpus: &[GpuInfo],
+) -> Vec<DriftReport> {
+    let mut reports = Vec::new();
+
+    let desktop_ids = config
+        .assignments()
+        .keys()
+        .chain(config.action_assignments().keys())
+        .collect::<BTreeSet<_>>();
+
+    for desktop_id in desktop_ids {
+        let Some(app) = apps.iter().find(|a| &a.desktop_id == desktop_id) else {
+            continue;
+        };
+
+        let choice = config.get_choice(desktop_id);
+        let action_choices = config.action_choices(desktop_id);
+        let steam_accounts = config.steam_account_scope(desktop_id);
+        let selected_gpu = gpu_for_choice(gpus, &choice);
+        let gpu_missing = std::iter::once(&choice)
+            .chain(action_choices.values())
+            .any(|c| *c != GpuChoice::Default && gpu_for_choice(gpus, c).is_none());
+        let status = if gpu_missing {
+            DriftStatus::GpuMissing
+        } else {
+            match read_launcher_state(
+                app,
+                &choice,
+                &action_choices,
+                &steam_accounts,
+                selected_gpu,
+                gpus,
+                config.use_env_wrapper(),
+            ) {
+                Ok(state) if state.in_sync => DriftStatus::InSync,
+                Ok(state) if app.override_stale && state.observed_dri_prime.is_some() => {
+                    DriftStatus::SourceChanged
+                }
+                Ok(state) => DriftStatus::Drifted {
+                    observed: state.observed_dri_prime,
+                },
+                Err(err) => DriftStatus::Unreadable(format!("{err:#}")),
+            }
+        };
+
+        if !matches!(status, DriftStatus::InSync) {
+            warn!(desktop_id = %desktop_id, status = ?status, "assignment drift detected");
+        }
+        reports.push(DriftReport {
+            app: app.clone(),
+            choice,
+            action_choices,
+            steam_accounts,
+            status,
+        });
+    }
+
+    reports
+}
+
+/// Writes the saved assignment back to disk.
+pub fn reapply(report: &DriftReport, gpus: &[GpuInfo], use_env_wrapper: bool) -> Result<()> {
+    if report.app.is_steam_game && is_steam_running() {
+        anyhow::bail!("Steam is running; close Steam before re-applying Steam games");
+    }
+
+    let selected_gpu = gpu_for_choice(gpus, &report.choice);
+    apply_launcher_override(
+        &report.app,
+        &report.choice,
+        &report.action_choices,
+        &report.steam_accounts,
+        selected_gpu,
+        gpus,
+        use_env_wrapper,
+    )?;
+    info!(desktop_id = %report.app.desktop_id, "assignment re-applied");
+    Ok(())
+}
+
+/// Writes the Steam assignments queued while Steam was running, using what is
+/// saved in the config now. Entries that fail are dropped from the queue and
+/// left to the drift check. Returns how many were applied, or `None` when
+/// nothing is queued or Steam is still running; the caller saves the config.
+pub fn apply_pending_steam_changes(
+    config: &mut ConfigStore,
+    apps: &[DesktopApp],
+    gpus: &[GpuInfo],
+) -> Option<usize> {
+    if config.pending_steam_changes().is_empty() || is_steam_running() {
+        return None;
+    }
+
+    let mut applied = 0;
+    for desktop_id in config.pending_steam_changes().clone() {
+        config.clear_steam_change(&desktop_id);
+        let Some(app) = apps.iter().find(|a| a.desktop_id == desktop_id) else {
+            warn!(desktop_id = %desktop_id, "dropping queued Steam change for missing app");
+            continue;
+        };
+
+        let choice = config.get_choice(&desktop_id);
+        match apply_launcher_override(
+            app,
+            &choice,
+            &config.action_choices(&desktop_id),
+            &config.steam_account_scope(&desktop_id),
+            gpu_for_choice(gpus, &choice),
+            gpus,
+            config.use_env_wrapper(),
+        ) {
+            Ok(()) => {
+                info!(desktop_id = %desktop_id, "queued Steam change applied");
+                applied += 1;
+            }
+            Err(err) => warn!(
+                desktop_id = %desktop_id,
+                error = %err,
+                "failed to apply queued Steam change"
+            ),
+        }
+    }
+    Some(applied)
+}
+
+/// Updates the config to match what is on disk. The caller saves the config.
+pub fn adopt(report: &DriftReport, gpus: &[GpuInfo], config: &mut ConfigStore) -> Result<()> {
+    let DriftStatus::Drifted { observed } = report.status else {
+        anyhow::bail!("{} has no readable on-disk state to adopt", report.app.name);
+    };
+
+    let choice = match observed {
+        None => GpuChoice::Default,
+        Some(idx) => {
+            let gpu = gpus
+                .iter()
+                .find(|g| g.dri_prime_index == Some(idx))
+                .with_context(|| format!("no GPU with DRI_PRIME index {idx} is present"))?;
+            GpuChoice::Gpu(gpu.stable_id())
+        }
+    };
+
+    info!(
+        desktop_id = %report.app.desktop_id,
+        gpu_choice = %choice.label(),
+        "adopting on-disk assignment"
+    );
+    config.set_choice(&report.app.desktop_id, choice);
+    Ok(())
+}
+
+/// Rebuilds Kaede desktop overrides whose source `.desktop` file changed since
+/// they were written. Overrides whose source is gone, or that no longer match
+/// an assignment, are removed. Returns how many launchers were touched.
+///
+/// Overrides from older versions that never recorded their source hash are
+/// left as they are; only the hash of the current source is added.
+pub fn regenerate_stale_overrides(
+    config: &ConfigStore,
+    apps: &[DesktopApp],
+    gpus: &[GpuInfo],
+) -> usize {
+    let mut touched = 0;
+
+    for app in apps.iter().filter(|a| a.override_source.is_some() && !a.override_stale) {
+        if let Err(err) = record_override_source(app) {
+            warn!(
+                desktop_id = %app.desktop_id,
+                error = %err,
+                "failed to record desktop override source"
+            );
+        }
+    }
+
+    for app in apps.iter().filter(|a| a.override_stale) {
+        let choice = config.get_choice(&app.desktop_id);
+        let action_choices = config.action_choices(&app.desktop_id);
+        let source_exists = app.override_source.as_deref().is_some_and(|p| p.is_file());
+
+        let unassigned = choice == GpuChoice::Default && action_choices.is_empty();
+
+        let result = if !source_exists || !app.uses_desktop_override() || unassigned {
+            info!(desktop_id = %app.desktop_id, "removing outdated desktop override");
+            remove_desktop_override(&app.desktop_id)
+        } else {
+            info!(desktop_id = %app.desktop_id, "regenerating outdated desktop override");
+            let selected_gpu = gpu_for_choice(gpus, &choice);
+            apply_launcher_override(
+                app,
+                &choice,
+                &action_choices,
+                &config.steam_account_scope(&app.desktop_id),
+                selected_gpu,
+                gpus,
+                config.use_env_wrapper(),
+            )
+        };
+
+        match result {
+            Ok(()) => touched += 1,
+            Err(err) => warn!(
+                desktop_id = %app.desktop_id,
+                error = %err,
+                "failed to regenerate desktop override"
+            ),
+        }
+    }
+
+    touched
+}
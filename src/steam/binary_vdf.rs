@@ -0,0 +1,237 @@
+//! Binary KeyValues, as used by `shortcuts.vdf`. Every entry is a type byte,
+//! a NUL-terminated key and a value; maps end with `0x08`.
+
+use anyhow::Result;
+
+const TYPE_MAP: u8 = 0x00;
+const TYPE_STRING: u8 = 0x01;
+const TYPE_INT: u8 = 0x02;
+const TYPE_FLOAT: u8 = 0x03;
+const TYPE_UINT64: u8 = 0x07;
+const MAP_END: u8 = 0x08;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Map(Vec<(String, Value)>),
+    Str(String),
+    Int(i32),
+    Float(f32),
+    UInt64(u64),
+}
+
+impl Value {
+    pub fn entries(&self) -> &[(String, Value)] {
+        match self {
+            Value::Map(entries) => entries,
+            _ => &[],
+        }
+    }
+
+    pub fn entries_mut(&mut self) -> Option<&mut Vec<(String, Value)>> {
+        match self {
+            Value::Map(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    /// Keys are matched case-insensitively; Steam has written both `AppName`
+    /// and `appname` over the years.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries()
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i32> {
+        match self {
+            Value::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Sets a string entry, keeping its position and key spelling when it exists.
+    pub fn set_str(&mut self, key: &str, value: &str) {
+        let Some(entries) = self.entries_mut() else {
+            return;
+        };
+        match entries.iter_mut().find(|(k, _)| k.eq_ignore_ascii_case(key)) {
+            Some((_, existing)) => *existing = Value::Str(value.to_string()),
+            None => entries.push((key.to_string(), Value::Str(value.to_string()))),
+        }
+    }
+}
+
+/// Parses a whole file, which is a single top-level map.
+pub fn parse(data: &[u8]) -> Result<Vec<(String, Value)>> {
+    let mut reader = Reader { data, pos: 0 };
+    let entries = reader.map()?;
+    if reader.pos != data.len() {
+        anyhow::bail!("trailing data at byte {}", reader.pos);
+    }
+    Ok(entries)
+}
+
+pub fn serialize(entries: &[(String, Value)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_map(&mut out, entries);
+    out
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn map(&mut self) -> Result<Vec<(String, Value)>> {
+        let mut entries = Vec::new();
+        loop {
+            let kind = self.byte()?;
+            if kind == MAP_END {
+                return Ok(entries);
+            }
+            let key = self.string()?;
+            let value = match kind {
+                TYPE_MAP => Value::Map(self.map()?),
+                TYPE_STRING => Value::Str(self.string()?),
+                TYPE_INT => Value::Int(i32::from_le_bytes(self.array()?)),
+                TYPE_FLOAT => Value::Float(f32::from_le_bytes(self.array()?)),
+                TYPE_UINT64 => Value::UInt64(u64::from_le_bytes(self.array()?)),
+                other => anyhow::bail!("unknown value type {other:#04x} for key '{key}'"),
+            };
+            entries.push((key, value));
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let byte = *self
+            .data
+            .get(self.pos)
+            .ok_or_else(|| anyhow::anyhow!("unexpected end of data"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + N)
+            .ok_or_else(|| anyhow::anyhow!("unexpected end of data at byte {}", self.pos))?;
+        self.pos += N;
+        Ok(bytes.try_into()?)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.data[self.pos..]
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| anyhow::anyhow!("unterminated string at byte {}", self.pos))?;
+        let value = std::str::from_utf8(&self.data[self.pos..self.pos + len])
+            .map_err(|_| anyhow::anyhow!("string at byte {} is not UTF-8", self.pos))?
+            .to_string();
+        self.pos += len + 1;
+        Ok(value)
+    }
+}
+
+fn write_map(out: &mut Vec<u8>, entries: &[(String, Value)]) {
+    for (key, value) in entries {
+        let kind = match value {
+            Value::Map(_) => TYPE_MAP,
+            Value::Str(_) => TYPE_STRING,
+            Value::Int(_) => TYPE_INT,
+            Value::Float(_) => TYPE_FLOAT,
+            Value::UInt64(_) => TYPE_UINT64,
+        };
+        out.push(kind);
+        write_string(out, key);
+        match value {
+            Value::Map(children) => write_map(out, children),
+            Value::Str(value) => write_string(out, value),
+            Value::Int(value) => out.extend_from_slice(&value.to_le_bytes()),
+            Value::Float(value) => out.extend_from_slice(&value.to_le_bytes()),
+            Value::UInt64(value) => out.extend_from_slice(&value.to_le_bytes()),
+        }
+    }
+    out.push(MAP_END);
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(value.as_bytes());
+    out.push(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHORTCUTS: &[u8] = include_bytes!("testdata/shortcuts.vdf");
+
+    #[test]
+    fn round_trips_shortcuts() {
+        let entries = parse(SHORTCUTS).unwrap();
+        assert_eq!(serialize(&entries), SHORTCUTS);
+
+        let shortcut = entries[0].1.get("0").unwrap();
+        assert_eq!(shortcut.get("appname").and_then(Value::as_str), Some("RetroArch"));
+        assert_eq!(shortcut.get("appid").and_then(Value::as_int), Some(0xC3A1_B2D4_u32 as i32));
+        assert_eq!(
+            shortcut.get("tags").unwrap().entries(),
+            [
+                ("0".to_string(), Value::Str("favorite".to_string())),
+                ("1".to_string(), Value::Str("Emulators".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips_every_value_type() {
+        let entries = vec![(
+            "root".to_string(),
+            Value::Map(vec![
+                ("name".to_string(), Value::Str("Ünïcode".to_string())),
+                ("int".to_string(), Value::Int(-7)),
+                ("float".to_string(), Value::Float(1.5)),
+                ("uint64".to_string(), Value::UInt64(u64::MAX - 1)),
+                ("empty".to_string(), Value::Map(Vec::new())),
+            ]),
+        )];
+        assert_eq!(parse(&serialize(&entries)).unwrap(), entries);
+    }
+
+    #[test]
+    fn set_str_keeps_position_and_key_spelling() {
+        let mut entries = parse(SHORTCUTS).unwrap();
+        let list = entries[0].1.entries_mut().unwrap();
+        let shortcut = &mut list[1].1;
+        shortcut.set_str("AppName", "Dwarf Fortress Classic");
+        shortcut.set_str("LaunchOptions", "DRI_PRIME=1 %command%");
+
+        let keys = shortcut.entries().iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys[..3], ["appname", "exe", "StartDir"]);
+        assert_eq!(
+            shortcut.get("appname").and_then(Value::as_str),
+            Some("Dwarf Fortress Classic")
+        );
+
+        let reparsed = parse(&serialize(&entries)).unwrap();
+        assert_eq!(reparsed, entries);
+        assert_eq!(reparsed[0].1.get("0"), parse(SHORTCUTS).unwrap()[0].1.get("0"));
+    }
+
+    #[test]
+    fn rejects_malformed_data() {
+        assert!(parse(&SHORTCUTS[..SHORTCUTS.len() - 1]).is_err());
+        assert!(parse(&[SHORTCUTS, b"\x08"].concat()).is_err());
+        assert!(parse(b"\x09key\x00\x08").is_err());
+        assert!(parse(b"\x01key\x00value").is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use super::{steam_roots, vdf};
+use crate::models::DesktopApp;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+const FLATPAK_ROOT: &str = ".var/app/com.valvesoftware.Steam";
+
+/// `StateFlags` bit set once an app is fully installed.
+const STATE_FULLY_INSTALLED: u32 = 4;
+
+/// Redistributables that ship without a `toolmanifest.vdf`.
+const NON_GAME_APP_IDS: [&str; 2] = ["228980", "1070560"];
+
+/// Installed games from every Steam library that aren't already covered by a
+/// `.desktop` shortcut in `known`.
+pub fn scan_games(known: &[DesktopApp]) -> Vec<DesktopApp> {
+    let mut seen = known
+        .iter()
+        .filter_map(|app| app.steam_app_id.clone())
+        .collect::<BTreeSet<_>>();
+
+    let mut games = Vec::new();
+    for (root, library) in libraries() {
+        let flatpak = root.to_string_lossy().contains(FLATPAK_ROOT);
+        let steamapps = library.join("steamapps");
+        let Ok(entries) = fs::read_dir(&steamapps) else {
+            continue;
+        };
+
+        let mut manifests = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with("appmanifest_") && n.ends_with(".acf"))
+            })
+            .collect::<Vec<_>>();
+        manifests.sort();
+
+        for path in manifests {
+            let Some(manifest) = read_manifest(&path) else {
+                continue;
+            };
+            if !manifest.installed() || is_tool(&steamapps, &manifest) {
+                debug!(app_id = %manifest.app_id, name = %manifest.name, "skipping Steam app");
+                continue;
+            }
+            if !seen.insert(manifest.app_id.clone()) {
+                continue;
+            }
+
+            let exec = if flatpak {
+                format!("flatpak run com.valvesoftware.Steam steam://rungameid/{}", manifest.app_id)
+            } else {
+                format!("steam steam://rungameid/{}", manifest.app_id)
+            };
+            games.push(DesktopApp {
+                desktop_id: format!("steam:{}", manifest.app_id),
+                path: path.clone(),
+                icon: Some(icon(&root, &manifest.app_id)),
+                name: manifest.name,
+                exec,
+                is_steam_game: true,
+                steam_app_id: Some(manifest.app_id),
+                steam_shortcut_id: None,
+                is_heroic_game: false,
+                heroic_platform: None,
+                heroic_app_name: None,
+                is_lutris_game: false,
+                lutris_slug: None,
+                is_bottles_app: false,
+                bottle_name: None,
+                bottle_program: None,
+                is_flatpak: false,
+                flatpak_app_id: None,
+                is_snap: false,
+                snap_name: None,
+                override_source: None,
+                override_stale: false,
+                actions: Vec::new(),
+            });
+        }
+    }
+    games
+}
+
+struct Manifest {
+    app_id: String,
+    name: String,
+    install_dir: Option<String>,
+    state_flags: u32,
+}
+
+impl Manifest {
+    fn installed(&self) -> bool {
+        self.state_flags & STATE_FULLY_INSTALLED != 0
+    }
+}
+
+fn read_manifest(path: &Path) -> Option<Manifest> {
+    let content = fs::read_to_string(path).ok()?;
+    let nodes = match vdf::parse(&content) {
+        Ok(nodes) => nodes,
+        Err(err) => {
+            warn!(path = %path.display(), error = %err, "failed to parse Steam app manifest");
+            return None;
+        }
+    };
+    let state = vdf::find(&nodes, "AppState")?;
+    let field = |key: &str| {
+        vdf::find(state.children(), key)
+            .and_then(vdf::Node::as_str)
+            .map(str::to_string)
+    };
+
+    let app_id = field("appid")?;
+    Some(Manifest {
+        name: field("name")
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| format!("Steam App {app_id}")),
+        install_dir: field("installdir"),
+        state_flags: field("StateFlags")
+            .and_then(|flags| flags.parse().ok())
+            .unwrap_or_default(),
+        app_id,
+    })
+}
+
+/// Proton, the Steam Linux Runtime and other compatibility tools install a
+/// `toolmanifest.vdf` next to their files.
+fn is_tool(steamapps: &Path, manifest: &Manifest) -> bool {
+    if NON_GAME_APP_IDS.contains(&manifest.app_id.as_str()) {
+        return true;
+    }
+    let has_tool_manifest = manifest
+        .install_dir
+        .as_deref()
+        .is_some_and(|dir| steamapps.join("common").join(dir).join("toolmanifest.vdf").exists());
+    has_tool_manifest
+        || manifest.name.starts_with("Proton ")
+        || manifest.name.starts_with("Steam Linux Runtime")
+}
+
+/// Every library folder with the Steam installation it belongs to, from
+/// `steamapps/libraryfolders.vdf`. The installation itself is always a library.
+fn libraries() -> Vec<(PathBuf, PathBuf)> {
+    let mut seen = BTreeSet::new();
+    let mut out = Vec::new();
+    for root in steam_roots() {
+        let Ok(canonical_root) = root.canonicalize() else {
+            continue;
+        };
+        if !seen.insert(canonical_root.clone()) {
+            continue;
+        }
+        out.push((canonical_root.clone(), canonical_root.clone()));
+
+        let mut folders = Vec::new();
+        let config = canonical_root.join("steamapps/libraryfolders.vdf");
+        if let Ok(content) = fs::read_to_string(&config) {
+            match vdf::parse(&content) {
+                Ok(nodes) => folders.extend(library_paths(&nodes)),
+                Err(err) => {
+                    warn!(path = %config.display(), error = %err, "failed to parse Steam libraryfolders.vdf")
+                }
+            }
+        }
+
+        // The root library is listed here too, and is already in `seen`.
+        for folder in folders {
+            let Ok(folder) = folder.canonicalize() else {
+                continue;
+            };
+            if seen.insert(folder.clone()) {
+                out.push((canonical_root.clone(), folder));
+            }
+        }
+    }
+    out
+}
+
+/// Current files list `"0" { "path" "..." }`; older ones list `"1" "path"`.
+fn library_paths(nodes: &[vdf::Node]) -> Vec<PathBuf> {
+    let Some(folders) = vdf::find(nodes, "libraryfolders") else {
+        return Vec::new();
+    };
+    folders
+        .children()
+        .iter()
+        .filter(|node| node.key.chars().all(|c| c.is_ascii_digit()))
+        .filter_map(|node| {
+            node.as_str()
+                .or_else(|| vdf::find(node.children(), "path").and_then(vdf::Node::as_str))
+        })
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// The icon Steam caches for its library view, or the icon name Steam
+/// installs for desktop shortcuts.
+fn icon(root: &Path, app_id: &str) -> String {
+    let cache = root.join("appcache/librarycache");
+    let legacy = cache.join(format!("{app_id}_icon.jpg"));
+    if legacy.is_file() {
+        return legacy.to_string_lossy().to_string();
+    }
+
+    // Newer clients keep per-app directories where the icon is named by its hash.
+    let hashed = fs::read_dir(cache.join(app_id)).ok().and_then(|entries| {
+        entries.flatten().map(|entry| entry.path()).find(|path| {
+            path.extension().is_some_and(|ext| ext == "jpg")
+                && path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .is_some_and(|stem| stem.len() == 40 && stem.chars().all(|c| c.is_ascii_hexdigit()))
+        })
+    });
+    match hashed {
+        Some(path) => path.to_string_lossy().to_string(),
+        None => format!("steam_icon_{app_id}"),
+    }
+}
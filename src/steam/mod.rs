@@ -1,87 +1,112 @@
-use crate::models::GpuChoice;
+use crate::gpu::parse_dri_prime;
+use crate::models::{OverrideState, SteamAccountScope};
+use crate::transaction::Transaction;
 use anyhow::{Context, Result};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::process::Command;
 use tracing::{debug, info, warn};
 
+mod binary_vdf;
+mod library;
+mod shortcuts;
+mod vdf;
+
+pub use library::scan_games;
+pub use shortcuts::{
+    apply_shortcut_launch_options, read_shortcut_state, scan_shortcuts, shortcut_id_from_game_id,
+};
+
 const KAEDE_STEAM_START: &str = "KAEDE_GPU_MANAGED=1";
 const KAEDE_STEAM_END: &str = "KAEDE_GPU_MANAGED_END=1";
+const LOCALCONFIG: &str = "config/localconfig.vdf";
+const STEAM_COMMAND: &str = "%command%";
+
+/// Offset between a SteamID64 and the account ID used for `userdata/<id>`.
+const STEAM_ID64_BASE: u64 = 76561197960265728;
+
+#[derive(Debug, Clone)]
+pub struct SteamAccount {
+    pub account_id: u32,
+    pub account_name: Option<String>,
+    pub persona_name: Option<String>,
+    pub most_recent: bool,
+}
+
+impl SteamAccount {
+    pub fn label(&self) -> String {
+        match (&self.persona_name, &self.account_name) {
+            (Some(persona), Some(account)) => format!("{persona} ({account})"),
+            (Some(name), None) | (None, Some(name)) => name.clone(),
+            (None, None) => format!("Account {}", self.account_id),
+        }
+    }
+}
 
+/// Writes the managed prefix for the accounts in `scope` and removes it from
+/// every other account, so narrowing the scope cleans up after itself.
 pub fn apply_steam_launch_options(
     app_id: &str,
-    choice: &GpuChoice,
+    dri_prime: Option<usize>,
     managed_env: &[String],
     use_env_wrapper: bool,
+    scope: &SteamAccountScope,
 ) -> Result<()> {
     if is_steam_running() {
         warn!("Steam appears to be running; it may overwrite localconfig.vdf changes on exit");
     }
 
-    let files = find_localconfig_files();
+    let files = scoped_userdata_files(LOCALCONFIG, scope);
     debug!(count = files.len(), "found Steam localconfig candidates");
     if files.is_empty() {
         warn!("no Steam localconfig.vdf files found");
         anyhow::bail!("no Steam localconfig.vdf files found");
     }
+    if !files.iter().any(|(_, in_scope)| *in_scope) {
+        anyhow::bail!("no Steam localconfig.vdf found for the selected Steam accounts");
+    }
 
+    let desired_block = build_managed_prefix(dri_prime, managed_env, use_env_wrapper);
     let mut matched_any = false;
-    let mut changed_any = false;
     let mut validated_any = false;
+    // Every account is written together, or none is.
+    let mut tx = Transaction::new();
 
-    for path in files {
-        debug!(path = %path.display(), app_id = app_id, "processing Steam config");
+    for (path, in_scope) in files {
+        debug!(
+            path = %path.display(),
+            app_id = app_id,
+            in_scope = in_scope,
+            "processing Steam config"
+        );
         let original = fs::read_to_string(&path)
             .with_context(|| format!("failed to read {}", path.display()))?;
-        let before = app_state_in_localconfig(&original, app_id);
-        if before.app_found {
-            matched_any = true;
+        let block = desired_block.as_deref().filter(|_| in_scope);
+        let (updated, changed) = update_localconfig_content(&original, app_id, |existing| {
+            apply_managed_block(existing, block)
+        });
+        if changed {
+            tx.write(&path, updated.clone());
+        }
+        if !in_scope {
+            continue;
         }
 
-        let (updated, changed) = update_localconfig_content(&original, app_id, choice, managed_env, use_env_wrapper);
-        let current_content = if changed {
-            write_backup_if_missing(&path, &original)?;
-            fs::write(&path, &updated)
-                .with_context(|| format!("failed to write {}", path.display()))?;
-            info!(path = %path.display(), app_id = app_id, "Steam LaunchOptions updated");
-            changed_any = true;
-            fs::read_to_string(&path)
-                .with_context(|| format!("failed to read back {}", path.display()))?
+        let after = app_state_in_localconfig(&updated, app_id);
+        if !after.app_found {
+            continue;
+        }
+        matched_any = true;
+        if validate_expected_state(after.launch_options.as_deref(), dri_prime) {
+            validated_any = true;
         } else {
-            debug!(
+            warn!(
                 path = %path.display(),
                 app_id = app_id,
-                "no Steam content changes needed for this config"
+                launch_options = ?after.launch_options,
+                "Steam LaunchOptions present but validation failed"
             );
-            original.clone()
-        };
-
-        let after = app_state_in_localconfig(&current_content, app_id);
-        if after.app_found {
-            matched_any = true;
-            if validate_expected_state(after.launch_options.as_deref(), choice) {
-                validated_any = true;
-                if changed {
-                    info!(
-                        path = %path.display(),
-                        app_id = app_id,
-                        "Steam LaunchOptions validation succeeded after write"
-                    );
-                } else {
-                    info!(
-                        path = %path.display(),
-                        app_id = app_id,
-                        "Steam LaunchOptions already in desired state"
-                    );
-                }
-            } else {
-                warn!(
-                    path = %path.display(),
-                    app_id = app_id,
-                    launch_options = ?after.launch_options,
-                    "Steam LaunchOptions present but validation failed"
-                );
-            }
         }
     }
 
@@ -95,26 +120,124 @@ pub fn apply_steam_launch_options(
             app_id
         );
     }
-    if !changed_any {
-        debug!(
-            app_id = app_id,
-            "Steam LaunchOptions required no file modifications"
-        );
+
+    tx.commit()?;
+    info!(app_id = app_id, "Steam LaunchOptions updated");
+    Ok(())
+}
+
+pub fn read_override_state(
+    app_id: &str,
+    dri_prime: Option<usize>,
+    scope: &SteamAccountScope,
+) -> Result<OverrideState> {
+    let mut found = false;
+    let mut in_sync = true;
+    let mut observed = None;
+
+    for (path, in_scope) in scoped_userdata_files(LOCALCONFIG, scope) {
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let state = app_state_in_localconfig(&content, app_id);
+        if !state.app_found {
+            continue;
+        }
+        if !in_scope {
+            if !validate_expected_state(state.launch_options.as_deref(), None) {
+                debug!(path = %path.display(), app_id = app_id, "managed Steam LaunchOptions outside scope");
+                in_sync = false;
+            }
+            continue;
+        }
+
+        found = true;
+        let launch_options = state.launch_options.as_deref();
+        if !validate_expected_state(launch_options, dri_prime) {
+            debug!(path = %path.display(), app_id = app_id, "Steam LaunchOptions drifted");
+            in_sync = false;
+        }
+        if observed.is_none() {
+            observed = launch_options.and_then(managed_dri_prime);
+        }
+    }
+
+    if !found && dri_prime.is_some() {
+        anyhow::bail!("Steam App ID {} not found in localconfig.vdf", app_id);
     }
 
+    Ok(OverrideState {
+        in_sync,
+        observed_dri_prime: observed,
+    })
+}
+
+/// LaunchOptions of the first account in `scope` that has the app, or `None`
+/// when no such account has it.
+pub fn launch_options(app_id: &str, scope: &SteamAccountScope) -> Option<String> {
+    scoped_userdata_files(LOCALCONFIG, scope)
+        .into_iter()
+        .filter(|(_, in_scope)| *in_scope)
+        .filter_map(|(path, _)| fs::read_to_string(path).ok())
+        .map(|content| app_state_in_localconfig(&content, app_id))
+        .find(|state| state.app_found)
+        .map(|state| state.launch_options.unwrap_or_default())
+}
+
+/// Replaces the user-owned part of the app's LaunchOptions in every account in
+/// `scope`, keeping the managed block each account already has.
+pub fn set_user_launch_options(app_id: &str, user: &str, scope: &SteamAccountScope) -> Result<()> {
+    if is_steam_running() {
+        anyhow::bail!("Steam is running; close Steam before editing launch options");
+    }
+
+    let mut matched_any = false;
+    let mut tx = Transaction::new();
+    for (path, in_scope) in scoped_userdata_files(LOCALCONFIG, scope) {
+        if !in_scope {
+            continue;
+        }
+        let original = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        if !app_state_in_localconfig(&original, app_id).app_found {
+            continue;
+        }
+        matched_any = true;
+
+        let (updated, changed) = update_localconfig_content(&original, app_id, |existing| {
+            compose_launch_options(user, existing.and_then(managed_block))
+        });
+        if changed {
+            tx.write(&path, updated);
+        }
+    }
+
+    if !matched_any {
+        anyhow::bail!("Steam App ID {} not found in localconfig.vdf", app_id);
+    }
+    tx.commit()?;
+    info!(app_id = app_id, "Steam user LaunchOptions updated");
     Ok(())
 }
 
-fn find_localconfig_files() -> Vec<PathBuf> {
+fn managed_dri_prime(launch_options: &str) -> Option<usize> {
+    let start = launch_options.find(KAEDE_STEAM_START)?;
+    let end = start + launch_options[start..].find(KAEDE_STEAM_END)?;
+    parse_dri_prime(&launch_options[start..end])
+}
+
+fn steam_roots() -> [PathBuf; 3] {
     let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-    let bases = [
+    [
         PathBuf::from(&home).join(".steam/steam"),
         PathBuf::from(&home).join(".local/share/Steam"),
         PathBuf::from(&home).join(".var/app/com.valvesoftware.Steam/data/Steam"),
-    ];
+    ]
+}
 
+/// `userdata/<id>/<relative>` of every account that has it, keyed by account ID.
+fn userdata_files(relative: &str) -> Vec<(u32, PathBuf)> {
     let mut out = Vec::new();
-    for base in bases {
+    for base in steam_roots() {
         let userdata = base.join("userdata");
         let Ok(entries) = fs::read_dir(userdata) else {
             continue;
@@ -122,12 +245,15 @@ fn find_localconfig_files() -> Vec<PathBuf> {
 
         for entry in entries.flatten() {
             let userdir = entry.path();
+            let Some(account_id) = entry.file_name().to_str().and_then(|n| n.parse().ok()) else {
+                continue;
+            };
             if !userdir.is_dir() {
                 continue;
             }
-            let cfg = userdir.join("config/localconfig.vdf");
+            let cfg = userdir.join(relative);
             if cfg.exists() {
-                out.push(cfg);
+                out.push((account_id, cfg));
             }
         }
     }
@@ -137,64 +263,188 @@ fn find_localconfig_files() -> Vec<PathBuf> {
     out
 }
 
-fn write_backup_if_missing(path: &Path, content: &str) -> Result<()> {
-    let backup = path.with_file_name("localconfig.vdf.kaede.bak");
-    if !backup.exists() {
-        fs::write(&backup, content)
-            .with_context(|| format!("failed to write backup {}", backup.display()))?;
-        info!(backup = %backup.display(), "Steam config backup created");
+/// Every `userdata/<id>/<relative>` with whether its account is part of `scope`.
+fn scoped_userdata_files(relative: &str, scope: &SteamAccountScope) -> Vec<(PathBuf, bool)> {
+    let recent = match scope {
+        SteamAccountScope::MostRecent => steam_accounts()
+            .into_iter()
+            .find(|account| account.most_recent)
+            .map(|account| account.account_id),
+        _ => None,
+    };
+    if matches!(scope, SteamAccountScope::MostRecent) && recent.is_none() {
+        debug!("no Steam login history; applying to every account");
     }
-    Ok(())
+
+    userdata_files(relative)
+        .into_iter()
+        .map(|(account_id, path)| {
+            let in_scope = match scope {
+                SteamAccountScope::All => true,
+                SteamAccountScope::Accounts(ids) => ids.contains(&account_id),
+                SteamAccountScope::MostRecent => recent.is_none_or(|id| id == account_id),
+            };
+            (path, in_scope)
+        })
+        .collect()
+}
+
+/// How `scope` reads in error messages.
+fn describe_scope(scope: &SteamAccountScope) -> String {
+    match scope {
+        SteamAccountScope::MostRecent => "the most recent Steam account".to_string(),
+        SteamAccountScope::All => "any Steam account".to_string(),
+        SteamAccountScope::Accounts(ids) => format!(
+            "Steam accounts {}",
+            ids.iter().map(u32::to_string).collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
+
+/// Accounts that have a `userdata` directory, named from `config/loginusers.vdf`.
+pub fn steam_accounts() -> Vec<SteamAccount> {
+    let mut logins: BTreeMap<u32, LoginUser> = BTreeMap::new();
+    for root in steam_roots() {
+        let Ok(content) = fs::read_to_string(root.join("config/loginusers.vdf")) else {
+            continue;
+        };
+        for user in parse_login_users(&content) {
+            logins.entry(user.account_id).or_insert(user);
+        }
+    }
+
+    // Older Steam versions don't write MostRecent; fall back to the last login time.
+    let most_recent = logins
+        .values()
+        .find(|user| user.most_recent)
+        .or_else(|| logins.values().max_by_key(|user| user.timestamp))
+        .map(|user| user.account_id);
+
+    let mut account_ids = userdata_files(LOCALCONFIG)
+        .into_iter()
+        .map(|(account_id, _)| account_id)
+        .collect::<Vec<_>>();
+    account_ids.dedup();
+
+    account_ids
+        .into_iter()
+        .map(|account_id| {
+            let login = logins.get(&account_id);
+            SteamAccount {
+                account_id,
+                account_name: login.and_then(|user| user.account_name.clone()),
+                persona_name: login.and_then(|user| user.persona_name.clone()),
+                most_recent: most_recent == Some(account_id),
+            }
+        })
+        .collect()
+}
+
+/// Parses a SteamID64 or a plain account ID.
+pub fn parse_account_id(value: &str) -> Option<u32> {
+    let id = value.trim().parse::<u64>().ok()?;
+    let id = if id >= STEAM_ID64_BASE { id - STEAM_ID64_BASE } else { id };
+    u32::try_from(id).ok()
+}
+
+#[derive(Debug)]
+struct LoginUser {
+    account_id: u32,
+    account_name: Option<String>,
+    persona_name: Option<String>,
+    most_recent: bool,
+    timestamp: u64,
+}
+
+fn parse_login_users(content: &str) -> Vec<LoginUser> {
+    let nodes = match vdf::parse(content) {
+        Ok(nodes) => nodes,
+        Err(err) => {
+            warn!(error = %err, "failed to parse Steam loginusers.vdf");
+            return Vec::new();
+        }
+    };
+    let Some(users) = vdf::find(&nodes, "users") else {
+        return Vec::new();
+    };
+
+    users
+        .children()
+        .iter()
+        .filter_map(|user| {
+            let steam_id = user.key.parse::<u64>().ok()?.checked_sub(STEAM_ID64_BASE)?;
+            let field = |key: &str| {
+                vdf::find(user.children(), key)
+                    .and_then(vdf::Node::as_str)
+                    .map(str::to_string)
+            };
+            Some(LoginUser {
+                account_id: u32::try_from(steam_id).ok()?,
+                account_name: field("AccountName"),
+                persona_name: field("PersonaName"),
+                most_recent: field("MostRecent").as_deref() == Some("1"),
+                timestamp: field("Timestamp")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_default(),
+            })
+        })
+        .collect()
 }
 
+/// Rewrites the app's LaunchOptions to `update(existing)`, adding the app when
+/// it is missing and the new value isn't empty.
 fn update_localconfig_content(
     content: &str,
     app_id: &str,
-    choice: &GpuChoice,
-    managed_env: &[String],
-    use_env_wrapper: bool,
+    update: impl Fn(Option<&str>) -> String,
 ) -> (String, bool) {
-    let apps_block = find_steam_apps_block(content).or_else(|| {
-        warn!("Steam apps block not found at canonical path; trying fallback global apps search");
-        find_block_by_key_in_range_ci(content, "apps", 0, content.len())
-    });
+    let nodes = match vdf::parse(content) {
+        Ok(nodes) => nodes,
+        Err(err) => {
+            warn!(error = %err, "failed to parse Steam localconfig; leaving it untouched");
+            return (content.to_string(), false);
+        }
+    };
 
-    let Some((apps_key, apps_open, apps_close)) = apps_block else {
+    let Some(apps) = find_apps_block(&nodes) else {
         warn!("Steam localconfig missing apps block");
         return (content.to_string(), false);
     };
 
-    let desired_prefix = build_managed_prefix(choice, managed_env, use_env_wrapper);
-    let (mut out, changed) = upsert_app_launch_options(
-        content,
-        apps_key,
-        apps_open,
-        apps_close,
-        app_id,
-        desired_prefix.as_deref(),
-    );
-
-    if !changed {
-        return (content.to_string(), false);
-    }
+    let mut out = content.to_string();
+    let Some(app) = vdf::find(apps.children(), app_id) else {
+        let launch_options = update(None);
+        if launch_options.is_empty() {
+            return (out, false);
+        }
+        vdf::append_block(&mut out, apps, app_id, &[("LaunchOptions", &launch_options)]);
+        return (out, true);
+    };
 
-    if content.ends_with('\n') && !out.ends_with('\n') {
-        out.push('\n');
+    let launch = vdf::find(app.children(), "LaunchOptions");
+    let existing = launch.and_then(vdf::Node::as_str);
+    let updated = update(existing);
+    match launch {
+        Some(_) if existing == Some(updated.as_str()) => return (out, false),
+        Some(node) if updated.is_empty() => vdf::remove(&mut out, node),
+        Some(node) => vdf::set_value(&mut out, node, &updated),
+        None if updated.is_empty() => return (out, false),
+        None => vdf::append_string(&mut out, app, "LaunchOptions", &updated),
     }
-
     (out, true)
 }
 
-fn find_steam_apps_block(content: &str) -> Option<(usize, usize, usize)> {
-    let (_, ulcs_open, ulcs_close) =
-        find_block_by_key_in_range_ci(content, "UserLocalConfigStore", 0, content.len())?;
-    let (_, software_open, software_close) =
-        find_block_by_key_in_range_ci(content, "Software", ulcs_open + 1, ulcs_close)?;
-    let (_, valve_open, valve_close) =
-        find_block_by_key_in_range_ci(content, "Valve", software_open + 1, software_close)?;
-    let (_, steam_open, steam_close) =
-        find_block_by_key_in_range_ci(content, "Steam", valve_open + 1, valve_close)?;
-    find_block_by_key_in_range_ci(content, "apps", steam_open + 1, steam_close)
+/// `UserLocalConfigStore/Software/Valve/Steam/apps`, or any `apps` block in
+/// files with an unexpected layout.
+fn find_apps_block(nodes: &[vdf::Node]) -> Option<&vdf::Node> {
+    vdf::find_path(
+        nodes,
+        &["UserLocalConfigStore", "Software", "Valve", "Steam", "apps"],
+    )
+    .or_else(|| {
+        warn!("Steam apps block not found at canonical path; trying fallback global apps search");
+        vdf::find_block(nodes, "apps")
+    })
 }
 
 pub fn is_steam_running() -> bool {
@@ -205,113 +455,64 @@ pub fn is_steam_running() -> bool {
         .unwrap_or(false)
 }
 
-fn upsert_app_launch_options(
-    content: &str,
-    apps_key: usize,
-    apps_open: usize,
-    apps_close: usize,
-    app_id: &str,
-    desired_prefix: Option<&str>,
-) -> (String, bool) {
-    let Some((app_key, app_open, app_close)) =
-        find_block_by_key_in_range(content, app_id, apps_open + 1, apps_close)
-    else {
-        if desired_prefix.is_none() {
-            return (content.to_string(), false);
-        }
-
-        let apps_indent = indentation_at(content, apps_key);
-        let app_indent = format!("{}\t", apps_indent);
-        let launch_indent = format!("{}\t", app_indent);
-        let launch = format!(
-            "{}\"LaunchOptions\"\t\t\"{}\"",
-            launch_indent,
-            apply_prefix_to_existing(None, desired_prefix)
-        );
-
-        let block = format!(
-            "\n{}\"{}\"\n{}{{\n{}\n{}}}",
-            app_indent, app_id, app_indent, launch, app_indent
-        );
+fn apply_managed_block(existing: Option<&str>, managed: Option<&str>) -> String {
+    let user = user_launch_options(existing.unwrap_or_default());
+    if managed.is_some() && !user.is_empty() {
+        debug!(user = %user, "preserving user Steam LaunchOptions around the managed block");
+    }
+    compose_launch_options(&user, managed)
+}
 
-        let mut out = content.to_string();
-        out.insert_str(apps_close, &block);
-        return (out, true);
+/// Combines user LaunchOptions with the managed block. The block opens the
+/// shell segment that runs `%command%`, so it also covers wrappers such as
+/// `gamemoderun`. Options without `%command%` are game arguments and go after
+/// the `%command%` the block introduces.
+fn compose_launch_options(user: &str, managed: Option<&str>) -> String {
+    let user = user.trim();
+    let Some(managed) = managed.map(str::trim).filter(|m| !m.is_empty()) else {
+        return user.to_string();
+    };
+    let Some(command) = user.find(STEAM_COMMAND) else {
+        return format!("{managed} {STEAM_COMMAND} {user}").trim().to_string();
     };
 
-    let app_indent = indentation_at(content, app_key);
-    let launch_indent_default = format!("{}\t", app_indent);
-
-    let (line_start, line_end, existing_value, line_indent) = find_launch_options_line(
-        content,
-        app_open + 1,
-        app_close,
-    )
-    .unwrap_or((app_close, app_close, None, launch_indent_default.clone()));
-
-    let updated_value = apply_prefix_to_existing(existing_value.as_deref(), desired_prefix);
-
-    if line_start < line_end {
-        if updated_value.is_empty() {
-            let mut out = content.to_string();
-            out.replace_range(line_start..line_end, "");
-            return (out, true);
-        }
-
-        if existing_value.as_deref() == Some(updated_value.as_str()) {
-            return (content.to_string(), false);
-        }
-
-        let new_line = format!(
-            "{}\"LaunchOptions\"\t\t\"{}\"\n",
-            line_indent, updated_value
-        );
-        let mut out = content.to_string();
-        out.replace_range(line_start..line_end, &new_line);
-        return (out, true);
-    }
-
-    if updated_value.is_empty() {
-        return (content.to_string(), false);
+    let (before, after) = user.split_at(segment_start(user, command));
+    let before = before.trim_end();
+    if before.is_empty() {
+        format!("{managed} {}", after.trim_start())
+    } else {
+        format!("{before} {managed} {}", after.trim_start())
     }
-
-    let insertion = format!(
-        "\n{}\"LaunchOptions\"\t\t\"{}\"",
-        launch_indent_default, updated_value
-    );
-    let mut out = content.to_string();
-    out.insert_str(app_close, &insertion);
-    (out, true)
 }
 
-fn apply_prefix_to_existing(existing: Option<&str>, desired_prefix: Option<&str>) -> String {
-    let existing = existing.unwrap_or_default().trim();
-    let tail = strip_managed_prefix(existing).trim().to_string();
-
-    match desired_prefix {
-        Some(prefix) => {
-            let tail = if tail.is_empty() {
-                "%command%".to_string()
-            } else {
-                tail
-            };
-            if tail != "%command%" {
-                debug!(tail = %tail, "preserving existing custom Steam LaunchOptions tail");
+/// Start of the shell segment holding byte `at`: just past the last `&&`, `||`,
+/// `;` or `|` before it that isn't quoted or escaped.
+fn segment_start(command_line: &str, at: usize) -> usize {
+    let mut start = 0;
+    let mut quote = None;
+    let mut chars = command_line[..at].char_indices().peekable();
+    while let Some((i, ch)) = chars.next() {
+        match (quote, ch) {
+            (Some(open), _) if ch == open => quote = None,
+            (Some('"') | None, '\\') => {
+                chars.next();
             }
-            format!("{} {}", prefix.trim(), tail).trim().to_string()
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(ch),
+            (None, ';' | '|') => start = i + 1,
+            (None, '&') if chars.next_if(|(_, next)| *next == '&').is_some() => start = i + 2,
+            (None, _) => {}
         }
-        None => tail,
     }
+    start
 }
 
-fn build_managed_prefix(choice: &GpuChoice, managed_env: &[String], use_env_wrapper: bool) -> Option<String> {
-    let GpuChoice::Gpu(idx) = choice else {
-        return None;
-    };
+fn build_managed_prefix(dri_prime: Option<usize>, managed_env: &[String], use_env_wrapper: bool) -> Option<String> {
+    let idx = dri_prime?;
     let vars = if managed_env.is_empty() {
         vec![format!("DRI_PRIME={idx}")]
     } else {
-        managed_env.to_vec()
+        managed_env.iter().map(|pair| quote_assignment(pair)).collect()
     };
 
     let prefix = if use_env_wrapper { "env " } else { "" };
@@ -324,50 +525,68 @@ fn build_managed_prefix(choice: &GpuChoice, managed_env: &[String], use_env_wrap
     ))
 }
 
-fn strip_managed_prefix(value: &str) -> String {
-    if let Some(start) = value.find(KAEDE_STEAM_START) {
-        if let Some(end_rel) = value[start..].find(KAEDE_STEAM_END) {
-            let end = start + end_rel + KAEDE_STEAM_END.len();
-            let mut out = String::new();
-            out.push_str(value[..start].trim_end());
-            if !out.is_empty() && !value[end..].trim().is_empty() {
-                out.push(' ');
-            }
-            out.push_str(value[end..].trim_start());
-            return out.trim().to_string();
-        } else {
-            // Fallback: if start marker exists but end marker is missing,
-            // we at least try to remove the start marker literal and hope for the best.
-            // This handles leftovers from potentially interrupted writes or old versions.
-            let mut out = value.to_string();
-            out.replace_range(start..start + KAEDE_STEAM_START.len(), "");
-            return out.trim().to_string();
+/// Steam runs LaunchOptions through a shell, so values such as
+/// `DXVK_FILTER_DEVICE_NAME=GeForce RTX 3060` need quoting.
+fn quote_assignment(pair: &str) -> String {
+    let Some((key, value)) = pair.split_once('=') else {
+        return pair.to_string();
+    };
+    let plain = value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ',' | ':' | '/'));
+    if plain {
+        pair.to_string()
+    } else {
+        format!("{key}='{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+/// Byte range of the managed block, including an `env` wrapper written with it.
+/// A start marker without an end marker (an interrupted write) covers only the marker.
+fn managed_block_range(value: &str) -> Option<std::ops::Range<usize>> {
+    let mut start = value.find(KAEDE_STEAM_START)?;
+    let end = match value[start..].find(KAEDE_STEAM_END) {
+        Some(rel) => start + rel + KAEDE_STEAM_END.len(),
+        None => start + KAEDE_STEAM_START.len(),
+    };
+
+    let head = value[..start].trim_end();
+    if let Some(env_start) = head.strip_suffix("env").map(str::len) {
+        let at_token_start = head[..env_start]
+            .chars()
+            .next_back()
+            .is_none_or(|c| c.is_whitespace() || matches!(c, ';' | '&' | '|'));
+        if at_token_start {
+            start = env_start;
         }
     }
+    Some(start..end)
+}
 
-    value.to_string()
+fn managed_block(value: &str) -> Option<&str> {
+    managed_block_range(value).map(|range| &value[range])
 }
 
-fn find_launch_options_line(
-    content: &str,
-    start: usize,
-    end: usize,
-) -> Option<(usize, usize, Option<String>, String)> {
-    let key = "\"LaunchOptions\"";
-    let rel = content[start..end].find(key)?;
-    let key_pos = start + rel;
-    let line_start = content[..key_pos].rfind('\n').map(|i| i + 1).unwrap_or(0);
-    let line_end = content[key_pos..]
-        .find('\n')
-        .map(|i| key_pos + i + 1)
-        .unwrap_or(content.len());
-    let line = &content[line_start..line_end];
-    let value = parse_launch_options_value(line);
-    let indent = line
-        .chars()
-        .take_while(|c| *c == '\t' || *c == ' ')
-        .collect::<String>();
-    Some((line_start, line_end, value, indent))
+/// The user-owned part of LaunchOptions, i.e. everything but the managed block.
+/// A `%command%` right after a leading block is the one the block introduced
+/// for options without it, so it is dropped as well.
+pub fn user_launch_options(value: &str) -> String {
+    let Some(range) = managed_block_range(value) else {
+        return value.trim().to_string();
+    };
+    let before = value[..range.start].trim();
+    let after = value[range.end..].trim();
+    let after = match after.strip_prefix(STEAM_COMMAND) {
+        Some(rest) if before.is_empty() && (rest.is_empty() || rest.starts_with(' ')) => {
+            rest.trim_start()
+        }
+        _ => after,
+    };
+    match (before.is_empty(), after.is_empty()) {
+        (true, _) => after.to_string(),
+        (false, true) => before.to_string(),
+        (false, false) => format!("{before} {after}"),
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -377,39 +596,29 @@ struct AppState {
 }
 
 fn app_state_in_localconfig(content: &str, app_id: &str) -> AppState {
-    let Some((_, apps_open, apps_close)) =
-        find_block_by_key_in_range_ci(content, "apps", 0, content.len())
-    else {
-        return AppState {
-            app_found: false,
-            launch_options: None,
-        };
-    };
-
-    let Some((_, app_open, app_close)) =
-        find_block_by_key_in_range(content, app_id, apps_open + 1, apps_close)
-    else {
-        return AppState {
-            app_found: false,
-            launch_options: None,
-        };
-    };
-
-    let launch_options = find_launch_options_line(content, app_open + 1, app_close)
-        .and_then(|(_, _, value, _)| value);
+    let app = vdf::parse(content)
+        .ok()
+        .and_then(|nodes| {
+            let apps = find_apps_block(&nodes)?;
+            vdf::find(apps.children(), app_id).cloned()
+        });
 
     AppState {
-        app_found: true,
-        launch_options,
+        app_found: app.is_some(),
+        launch_options: app.as_ref().and_then(|app| {
+            vdf::find(app.children(), "LaunchOptions")
+                .and_then(vdf::Node::as_str)
+                .map(|value| value.trim().to_string())
+        }),
     }
 }
 
-fn validate_expected_state(launch_options: Option<&str>, choice: &GpuChoice) -> bool {
-    match choice {
-        GpuChoice::Default => launch_options
+fn validate_expected_state(launch_options: Option<&str>, dri_prime: Option<usize>) -> bool {
+    match dri_prime {
+        None => launch_options
             .map(|v| !v.contains(KAEDE_STEAM_START) && !v.contains(KAEDE_STEAM_END))
             .unwrap_or(true),
-        GpuChoice::Gpu(idx) => launch_options
+        Some(idx) => launch_options
             .map(|v| {
                 v.contains(KAEDE_STEAM_START)
                     && v.contains(KAEDE_STEAM_END)
@@ -419,136 +628,86 @@ fn validate_expected_state(launch_options: Option<&str>, choice: &GpuChoice) ->
     }
 }
 
-fn parse_launch_options_value(line: &str) -> Option<String> {
-    let quote_positions = line.match_indices('"').map(|(i, _)| i).collect::<Vec<_>>();
-    if quote_positions.len() < 4 {
-        return None;
-    }
-
-    let start = quote_positions[2] + 1;
-    let end = quote_positions[3];
-    if end < start || end > line.len() {
-        return None;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn login_users_are_read_from_loginusers_vdf() {
+        let users = parse_login_users(include_str!("testdata/loginusers.vdf"));
+        assert_eq!(users.len(), 2);
+
+        let main = &users[0];
+        assert_eq!(main.account_id, 39734273);
+        assert_eq!(main.account_name.as_deref(), Some("kaede_main"));
+        assert_eq!(main.persona_name.as_deref(), Some("Kaede \"K\" Akamatsu"));
+        assert!(main.most_recent);
+        assert_eq!(main.timestamp, 1760000000);
+
+        let alt = &users[1];
+        assert_eq!(alt.account_id, 39734274);
+        assert!(!alt.most_recent);
     }
 
-    Some(line[start..end].trim().to_string())
-}
-
-fn indentation_at(content: &str, idx: usize) -> String {
-    let start = content[..idx].rfind('\n').map(|v| v + 1).unwrap_or(0);
-    content[start..idx]
-        .chars()
-        .take_while(|c| *c == '\t' || *c == ' ')
-        .collect::<String>()
-}
-
-fn find_block_by_key_in_range(
-    content: &str,
-    key: &str,
-    start: usize,
-    end: usize,
-) -> Option<(usize, usize, usize)> {
-    let needle = format!("\"{key}\"");
-    let mut search = start;
-
-    while search < end {
-        let rel = content[search..end].find(&needle)?;
-        let key_pos = search + rel;
-        let mut i = key_pos + needle.len();
-        let bytes = content.as_bytes();
-
-        while i < end && (bytes[i] as char).is_whitespace() {
-            i += 1;
-        }
-
-        if i >= end || bytes[i] != b'{' {
-            search = key_pos + needle.len();
-            continue;
-        }
-
-        let close = match_matching_brace(content, i, end)?;
-        return Some((key_pos, i, close));
+    #[test]
+    fn unparsable_loginusers_vdf_has_no_users() {
+        assert!(parse_login_users("\"users\"\n{\n\t\"76561198000000001\"\n").is_empty());
     }
 
-    None
-}
-
-fn find_block_by_key_in_range_ci(
-    content: &str,
-    key: &str,
-    start: usize,
-    end: usize,
-) -> Option<(usize, usize, usize)> {
-    let key_lower = key.to_ascii_lowercase();
-    let mut search = start;
-
-    while search < end {
-        let rel = content[search..end].find('\"')?;
-        let q1 = search + rel;
-        let q2_rel = content[q1 + 1..end].find('\"')?;
-        let q2 = q1 + 1 + q2_rel;
-        let token = &content[q1 + 1..q2];
-
-        if token.eq_ignore_ascii_case(&key_lower) {
-            let mut i = q2 + 1;
-            let bytes = content.as_bytes();
-            while i < end && (bytes[i] as char).is_whitespace() {
-                i += 1;
-            }
-            if i < end && bytes[i] == b'{' {
-                let close = match_matching_brace(content, i, end)?;
-                return Some((q1, i, close));
-            }
-        }
+    const BLOCK: &str = "KAEDE_GPU_MANAGED=1 DRI_PRIME=1 KAEDE_GPU_MANAGED_END=1";
 
-        search = q2 + 1;
+    #[test]
+    fn block_opens_the_segment_that_runs_the_game() {
+        let compose = |user| compose_launch_options(user, Some(BLOCK));
+        assert_eq!(compose(""), format!("{BLOCK} %command%"));
+        assert_eq!(compose("gamemoderun %command%"), format!("{BLOCK} gamemoderun %command%"));
+        assert_eq!(
+            compose("echo start; mangohud %command% -dx12"),
+            format!("echo start; {BLOCK} mangohud %command% -dx12")
+        );
+        assert_eq!(
+            compose("setup && gamemoderun %command% || true"),
+            format!("setup && {BLOCK} gamemoderun %command% || true")
+        );
+        assert_eq!(compose("%command% -novid"), format!("{BLOCK} %command% -novid"));
     }
 
-    None
-}
-
-fn match_matching_brace(content: &str, open: usize, end_limit: usize) -> Option<usize> {
-    let bytes = content.as_bytes();
-    if open >= end_limit || bytes[open] != b'{' {
-        return None;
+    #[test]
+    fn quoted_separators_do_not_split_segments() {
+        let compose = |user| compose_launch_options(user, Some(BLOCK));
+        assert_eq!(
+            compose(r#"WINEDLLOVERRIDES="dxgi=n;b" %command%"#),
+            format!(r#"{BLOCK} WINEDLLOVERRIDES="dxgi=n;b" %command%"#)
+        );
+        assert_eq!(
+            compose("PROTON_ARGS='a && b | c' gamemoderun %command%"),
+            format!("{BLOCK} PROTON_ARGS='a && b | c' gamemoderun %command%")
+        );
+        assert_eq!(
+            compose(r#"NAME="say \"hi;\"" %command%"#),
+            format!(r#"{BLOCK} NAME="say \"hi;\"" %command%"#)
+        );
+        assert_eq!(
+            compose(r"SEP=a\;b %command%"),
+            format!(r"{BLOCK} SEP=a\;b %command%")
+        );
+        assert_eq!(
+            compose("LABEL='x;y'; gamemoderun %command%"),
+            format!("LABEL='x;y'; {BLOCK} gamemoderun %command%")
+        );
     }
 
-    let mut depth = 0isize;
-    let mut in_string = false;
-    let mut escaped = false;
-
-    for (i, b) in bytes.iter().enumerate().take(end_limit).skip(open) {
-        let ch = *b as char;
-
-        if in_string {
-            if escaped {
-                escaped = false;
-                continue;
-            }
-            if ch == '\\' {
-                escaped = true;
-                continue;
-            }
-            if ch == '"' {
-                in_string = false;
-            }
-            continue;
-        }
-
-        if ch == '"' {
-            in_string = true;
-            continue;
-        }
-
-        if ch == '{' {
-            depth += 1;
-        } else if ch == '}' {
-            depth -= 1;
-            if depth == 0 {
-                return Some(i);
-            }
+    #[test]
+    fn options_without_command_round_trip() {
+        for user in ["", "-novid", "-novid -console +fps_max 144", "gamemoderun %command% -dx12"] {
+            let composed = compose_launch_options(user, Some(BLOCK));
+            assert_eq!(user_launch_options(&composed), user, "{composed}");
+            assert_eq!(apply_managed_block(Some(&composed), None), user);
         }
+        assert_eq!(user_launch_options(&format!("{BLOCK} %command%")), "");
+        assert_eq!(
+            user_launch_options(&format!("env {BLOCK} %command%-suffix")),
+            "%command%-suffix"
+        );
     }
-
-    None
 }
@@ -0,0 +1,275 @@
+use super::binary_vdf::{self, Value};
+use super::{
+    apply_managed_block, build_managed_prefix, describe_scope, managed_dri_prime,
+    scoped_userdata_files, userdata_files, validate_expected_state,
+};
+use crate::models::{DesktopApp, OverrideState, SteamAccountScope};
+use crate::transaction::Transaction;
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use tracing::{debug, info, warn};
+
+const SHORTCUTS: &str = "config/shortcuts.vdf";
+
+/// Low bits of the 64-bit game ID Steam uses in `steam://rungameid/` for shortcuts.
+const SHORTCUT_GAME_ID_FLAGS: u64 = 0x0200_0000;
+
+/// Non-Steam games added to Steam by any account, except those already covered
+/// by a `.desktop` shortcut in `known`.
+pub fn scan_shortcuts(known: &[DesktopApp]) -> Vec<DesktopApp> {
+    let mut seen = known
+        .iter()
+        .filter_map(|app| app.steam_shortcut_id)
+        .collect::<BTreeSet<_>>();
+
+    let mut apps = Vec::new();
+    for (_, path) in userdata_files(SHORTCUTS) {
+        let Some(entries) = read_shortcuts(&path) else {
+            continue;
+        };
+        for shortcut in shortcuts(&entries) {
+            let id = shortcut_id(shortcut);
+            if !seen.insert(id) {
+                continue;
+            }
+            let field = |key: &str| {
+                shortcut
+                    .get(key)
+                    .and_then(Value::as_str)
+                    .filter(|value| !value.is_empty())
+                    .map(str::to_string)
+            };
+            let exe = field("Exe").unwrap_or_default();
+
+            apps.push(DesktopApp {
+                desktop_id: format!("steam-shortcut:{id}"),
+                path: path.clone(),
+                name: field("AppName").unwrap_or_else(|| exe.trim_matches('"').to_string()),
+                icon: field("icon").map(|icon| icon.trim_matches('"').to_string()),
+                exec: format!("steam steam://rungameid/{}", game_id(id)),
+                is_steam_game: true,
+                steam_app_id: None,
+                steam_shortcut_id: Some(id),
+                is_heroic_game: false,
+                heroic_platform: None,
+                heroic_app_name: None,
+                is_lutris_game: false,
+                lutris_slug: None,
+                is_bottles_app: false,
+                bottle_name: None,
+                bottle_program: None,
+                is_flatpak: false,
+                flatpak_app_id: None,
+                is_snap: false,
+                snap_name: None,
+                override_source: None,
+                override_stale: false,
+                actions: Vec::new(),
+            });
+        }
+    }
+    apps
+}
+
+/// The shortcut ID encoded in a `steam://rungameid/<id>` game ID, if it names a shortcut.
+pub fn shortcut_id_from_game_id(game_id: &str) -> Option<u32> {
+    let game_id = game_id.parse::<u64>().ok()?;
+    if game_id <= u64::from(u32::MAX) {
+        return None;
+    }
+    u32::try_from(game_id >> 32).ok()
+}
+
+fn game_id(shortcut_id: u32) -> u64 {
+    (u64::from(shortcut_id) << 32) | SHORTCUT_GAME_ID_FLAGS
+}
+
+/// Writes the managed prefix into the shortcut's LaunchOptions for accounts in
+/// `scope` and removes it from every other account.
+pub fn apply_shortcut_launch_options(
+    shortcut: u32,
+    dri_prime: Option<usize>,
+    managed_env: &[String],
+    use_env_wrapper: bool,
+    scope: &SteamAccountScope,
+) -> Result<()> {
+    let desired_prefix = build_managed_prefix(dri_prime, managed_env, use_env_wrapper);
+    let mut found = false;
+    let mut found_in_scope = false;
+    let mut tx = Transaction::new();
+
+    for (path, in_scope) in scoped_userdata_files(SHORTCUTS, scope) {
+        let raw = fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        let mut entries = binary_vdf::parse(&raw)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        let prefix = desired_prefix.as_deref().filter(|_| in_scope);
+
+        let mut changed = false;
+        for entry in shortcuts_mut(&mut entries) {
+            if shortcut_id(entry) != shortcut {
+                continue;
+            }
+            found = true;
+            found_in_scope |= in_scope;
+            let existing = entry.get("LaunchOptions").and_then(Value::as_str);
+            let updated = apply_managed_block(existing, prefix);
+            if existing.unwrap_or_default() != updated {
+                entry.set_str("LaunchOptions", &updated);
+                changed = true;
+            }
+        }
+
+        if changed {
+            tx.write(&path, binary_vdf::serialize(&entries));
+        } else {
+            debug!(path = %path.display(), shortcut = shortcut, "no Steam shortcut changes needed");
+        }
+    }
+
+    if !found_in_scope && dri_prime.is_some() {
+        return Err(missing_shortcut(shortcut, found, scope));
+    }
+    tx.commit()?;
+    info!(shortcut = shortcut, "Steam shortcut LaunchOptions updated");
+    Ok(())
+}
+
+pub fn read_shortcut_state(
+    shortcut: u32,
+    dri_prime: Option<usize>,
+    scope: &SteamAccountScope,
+) -> Result<OverrideState> {
+    let mut found = false;
+    let mut found_in_scope = false;
+    let mut in_sync = true;
+    let mut observed = None;
+
+    for (path, in_scope) in scoped_userdata_files(SHORTCUTS, scope) {
+        let Some(entries) = read_shortcuts(&path) else {
+            continue;
+        };
+        for entry in shortcuts(&entries).filter(|entry| shortcut_id(entry) == shortcut) {
+            let launch_options = entry.get("LaunchOptions").and_then(Value::as_str);
+            found = true;
+            if !in_scope {
+                in_sync &= validate_expected_state(launch_options, None);
+                continue;
+            }
+
+            found_in_scope = true;
+            in_sync &= validate_expected_state(launch_options, dri_prime);
+            if observed.is_none() {
+                observed = launch_options.and_then(managed_dri_prime);
+            }
+        }
+    }
+
+    if !found_in_scope && dri_prime.is_some() {
+        return Err(missing_shortcut(shortcut, found, scope));
+    }
+
+    Ok(OverrideState {
+        in_sync,
+        observed_dri_prime: observed,
+    })
+}
+
+/// `found_elsewhere` tells whether an account outside `scope` has the shortcut.
+fn missing_shortcut(
+    shortcut: u32,
+    found_elsewhere: bool,
+    scope: &SteamAccountScope,
+) -> anyhow::Error {
+    if found_elsewhere {
+        anyhow::anyhow!(
+            "Steam shortcut {} belongs to other accounts, not {}",
+            shortcut,
+            describe_scope(scope)
+        )
+    } else {
+        anyhow::anyhow!("Steam shortcut {} not found in shortcuts.vdf", shortcut)
+    }
+}
+
+fn read_shortcuts(path: &Path) -> Option<Vec<(String, Value)>> {
+    let raw = fs::read(path).ok()?;
+    match binary_vdf::parse(&raw) {
+        Ok(entries) => Some(entries),
+        Err(err) => {
+            warn!(path = %path.display(), error = %err, "failed to parse Steam shortcuts.vdf");
+            None
+        }
+    }
+}
+
+fn shortcuts(entries: &[(String, Value)]) -> impl Iterator<Item = &Value> {
+    entries
+        .iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case("shortcuts"))
+        .flat_map(|(_, list)| list.entries())
+        .map(|(_, shortcut)| shortcut)
+}
+
+fn shortcuts_mut(entries: &mut [(String, Value)]) -> impl Iterator<Item = &mut Value> {
+    entries
+        .iter_mut()
+        .filter(|(key, _)| key.eq_ignore_ascii_case("shortcuts"))
+        .filter_map(|(_, list)| list.entries_mut())
+        .flat_map(|list| list.iter_mut())
+        .map(|(_, shortcut)| shortcut)
+}
+
+/// The stored `appid`, or for shortcuts written before Steam stored one, the
+/// ID Steam derives from the executable and name.
+fn shortcut_id(shortcut: &Value) -> u32 {
+    if let Some(id) = shortcut.get("appid").and_then(Value::as_int) {
+        return id as u32;
+    }
+    let field = |key: &str| shortcut.get(key).and_then(Value::as_str).unwrap_or_default();
+    crc32(format!("{}{}", field("Exe"), field("AppName")).as_bytes()) | 0x8000_0000
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHORTCUTS: &[u8] = include_bytes!("testdata/shortcuts.vdf");
+
+    #[test]
+    fn crc32_matches_the_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn game_ids_round_trip() {
+        assert_eq!(game_id(0xC3A1_B2D4), 14_096_744_932_282_728_448);
+        assert_eq!(shortcut_id_from_game_id("14096744932282728448"), Some(0xC3A1_B2D4));
+        assert_eq!(shortcut_id_from_game_id(&game_id(0x8000_0001).to_string()), Some(0x8000_0001));
+        // Plain Steam app IDs and junk are not shortcuts.
+        assert_eq!(shortcut_id_from_game_id("570"), None);
+        assert_eq!(shortcut_id_from_game_id("4294967295"), None);
+        assert_eq!(shortcut_id_from_game_id("steam"), None);
+    }
+
+    #[test]
+    fn reads_stored_and_derived_ids() {
+        let entries = binary_vdf::parse(SHORTCUTS).unwrap();
+        let ids = shortcuts(&entries).map(shortcut_id).collect::<Vec<_>>();
+        // The second shortcut predates `appid`; its ID comes from Exe and AppName.
+        assert_eq!(ids, [0xC3A1_B2D4, 0x8E43_FEDB]);
+    }
+}
@@ -0,0 +1,647 @@
+//! Text KeyValues (`.vdf`) parsing that keeps byte offsets, so edits can be
+//! spliced into the original file and everything else stays byte-for-byte.
+
+use anyhow::Result;
+use std::ops::Range;
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub key: String,
+    /// The key token, including quotes.
+    pub key_span: Range<usize>,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    /// `span` covers the value token, including quotes.
+    Str { value: String, span: Range<usize> },
+    /// `close` is the offset of the closing brace.
+    Block { children: Vec<Node>, close: usize },
+}
+
+impl Node {
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.value {
+            Value::Str { value, .. } => Some(value),
+            Value::Block { .. } => None,
+        }
+    }
+
+    pub fn children(&self) -> &[Node] {
+        match &self.value {
+            Value::Block { children, .. } => children,
+            Value::Str { .. } => &[],
+        }
+    }
+
+    /// Byte range of the whole entry, from the key to the end of the value.
+    fn span(&self) -> Range<usize> {
+        let end = match &self.value {
+            Value::Str { span, .. } => span.end,
+            Value::Block { close, .. } => close + 1,
+        };
+        self.key_span.start..end
+    }
+}
+
+/// Keys are matched case-insensitively, as Steam does.
+pub fn find<'a>(nodes: &'a [Node], key: &str) -> Option<&'a Node> {
+    nodes.iter().find(|node| node.key.eq_ignore_ascii_case(key))
+}
+
+pub fn find_path<'a>(nodes: &'a [Node], path: &[&str]) -> Option<&'a Node> {
+    let (first, rest) = path.split_first()?;
+    let node = find(nodes, first)?;
+    if rest.is_empty() {
+        Some(node)
+    } else {
+        find_path(node.children(), rest)
+    }
+}
+
+/// Depth-first search for the first block named `key`.
+pub fn find_block<'a>(nodes: &'a [Node], key: &str) -> Option<&'a Node> {
+    nodes.iter().find_map(|node| match node.value {
+        Value::Block { .. } if node.key.eq_ignore_ascii_case(key) => Some(node),
+        Value::Block { .. } => find_block(node.children(), key),
+        Value::Str { .. } => None,
+    })
+}
+
+pub fn parse(content: &str) -> Result<Vec<Node>> {
+    let mut lexer = Lexer { content, pos: 0 };
+    let nodes = parse_list(&mut lexer)?;
+    match lexer.next()? {
+        None => Ok(nodes),
+        Some((Token::Close, span)) => anyhow::bail!("unexpected '}}' at byte {}", span.start),
+        Some((_, span)) => anyhow::bail!("unexpected token at byte {}", span.start),
+    }
+}
+
+fn parse_list(lexer: &mut Lexer) -> Result<Vec<Node>> {
+    let mut nodes = Vec::new();
+    loop {
+        let checkpoint = lexer.pos;
+        let (key, key_span) = match lexer.next()? {
+            Some((Token::Str(key), span)) => (key, span),
+            Some((Token::Close, _)) | None => {
+                lexer.pos = checkpoint;
+                return Ok(nodes);
+            }
+            Some((Token::Open, span)) => {
+                anyhow::bail!("expected a key but found '{{' at byte {}", span.start)
+            }
+        };
+
+        let value = match lexer.next()? {
+            Some((Token::Str(value), span)) => {
+                lexer.skip_conditional()?;
+                Value::Str { value, span }
+            }
+            Some((Token::Open, open)) => {
+                let children = parse_list(lexer)?;
+                match lexer.next()? {
+                    Some((Token::Close, close)) => Value::Block {
+                        children,
+                        close: close.start,
+                    },
+                    _ => anyhow::bail!("unclosed block '{key}' at byte {}", open.start),
+                }
+            }
+            _ => anyhow::bail!("key '{key}' at byte {} has no value", key_span.start),
+        };
+        nodes.push(Node {
+            key,
+            key_span,
+            value,
+        });
+    }
+}
+
+#[derive(Debug)]
+enum Token {
+    Str(String),
+    Open,
+    Close,
+}
+
+struct Lexer<'a> {
+    content: &'a str,
+    pos: usize,
+}
+
+impl Lexer<'_> {
+    /// Skips whitespace and `//` comments.
+    fn skip_trivia(&mut self) {
+        let bytes = self.content.as_bytes();
+        loop {
+            while self.pos < bytes.len() && bytes[self.pos].is_ascii_whitespace() {
+                self.pos += 1;
+            }
+            let rest = &self.content[self.pos..];
+            if !rest.starts_with("//") {
+                break;
+            }
+            self.pos += rest.find('\n').unwrap_or(rest.len());
+        }
+    }
+
+    /// Skips a platform conditional such as `[$WIN32]`, which can only follow
+    /// a value on the same line.
+    fn skip_conditional(&mut self) -> Result<()> {
+        let rest = &self.content[self.pos..];
+        let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+        let offset = line.len() - line.trim_start().len();
+        if line[offset..].starts_with('[') {
+            let Some(end) = line.find(']') else {
+                anyhow::bail!("unclosed conditional at byte {}", self.pos + offset);
+            };
+            self.pos += end + 1;
+        }
+        Ok(())
+    }
+
+    /// Next token with its byte span; skips whitespace and `//` comments.
+    fn next(&mut self) -> Result<Option<(Token, Range<usize>)>> {
+        self.skip_trivia();
+        let bytes = self.content.as_bytes();
+        let start = self.pos;
+        let Some(&first) = bytes.get(start) else {
+            return Ok(None);
+        };
+        let token = match first {
+            b'{' => {
+                self.pos += 1;
+                Token::Open
+            }
+            b'}' => {
+                self.pos += 1;
+                Token::Close
+            }
+            b'"' => {
+                let mut value = String::new();
+                let mut chars = self.content[start + 1..].char_indices();
+                loop {
+                    let Some((i, ch)) = chars.next() else {
+                        anyhow::bail!("unterminated string at byte {start}");
+                    };
+                    match ch {
+                        '"' => {
+                            self.pos = start + 1 + i + 1;
+                            break;
+                        }
+                        '\\' => match chars.next() {
+                            Some((_, 'n')) => value.push('\n'),
+                            Some((_, 't')) => value.push('\t'),
+                            Some((_, escaped @ ('"' | '\\'))) => value.push(escaped),
+                            // Hand-edited paths like `C:\Games` keep their backslash.
+                            Some((_, other)) => {
+                                value.push('\\');
+                                value.push(other);
+                            }
+                            None => anyhow::bail!("unterminated string at byte {start}"),
+                        },
+                        other => value.push(other),
+                    }
+                }
+                Token::Str(value)
+            }
+            _ => {
+                let len = self.content[start..]
+                    .find(|c: char| c.is_whitespace() || matches!(c, '{' | '}' | '"'))
+                    .unwrap_or(self.content.len() - start);
+                self.pos += len;
+                Token::Str(self.content[start..self.pos].to_string())
+            }
+        };
+        Ok(Some((token, start..self.pos)))
+    }
+}
+
+/// Quotes `value` the way Steam writes it.
+pub fn quote(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            other => out.push(other),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Replaces the value of a string entry.
+pub fn set_value(content: &mut String, node: &Node, value: &str) {
+    if let Value::Str { span, .. } = &node.value {
+        content.replace_range(span.clone(), &quote(value));
+    }
+}
+
+/// Removes an entry, including its line when nothing else is on it.
+pub fn remove(content: &mut String, node: &Node) {
+    let mut span = node.span();
+    // A platform conditional after the value goes with it.
+    if let Value::Str { .. } = node.value {
+        let mut lexer = Lexer { content, pos: span.end };
+        if lexer.skip_conditional().is_ok() {
+            span.end = lexer.pos;
+        }
+    }
+    let line_start = content[..span.start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_end = content[span.end..]
+        .find('\n')
+        .map(|i| span.end + i + 1)
+        .unwrap_or(content.len());
+    let own_line = content[line_start..span.start].trim().is_empty()
+        && content[span.end..line_end].trim().is_empty();
+    if own_line {
+        content.replace_range(line_start..line_end, "");
+    } else {
+        content.replace_range(span, "");
+    }
+}
+
+/// Appends `"key" "value"` as the last entry of `block`.
+pub fn append_string(content: &mut String, block: &Node, key: &str, value: &str) {
+    insert_entry(content, block, |indent| {
+        vec![format!("{indent}{}\t\t{}", quote(key), quote(value))]
+    });
+}
+
+/// Appends a `"key" { ... }` block holding string `entries` as the last entry of `block`.
+pub fn append_block(content: &mut String, block: &Node, key: &str, entries: &[(&str, &str)]) {
+    insert_entry(content, block, |indent| {
+        let mut lines = vec![format!("{indent}{}", quote(key)), format!("{indent}{{")];
+        lines.extend(
+            entries
+                .iter()
+                .map(|(k, v)| format!("{indent}\t{}\t\t{}", quote(k), quote(v))),
+        );
+        lines.push(format!("{indent}}}"));
+        lines
+    });
+}
+
+/// Inserts the lines built for the child indentation before the closing brace of `block`.
+fn insert_entry(content: &mut String, block: &Node, lines: impl FnOnce(&str) -> Vec<String>) {
+    let Value::Block { close, .. } = block.value else {
+        return;
+    };
+    let indent = indentation(content, block.key_span.start);
+    let entry = lines(&format!("{indent}\t")).join("\n");
+
+    let close_line_start = content[..close].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    if content[close_line_start..close].trim().is_empty() {
+        content.insert_str(close_line_start, &format!("{entry}\n"));
+    } else {
+        content.insert_str(close, &format!("\n{entry}\n{indent}"));
+    }
+}
+
+fn indentation(content: &str, at: usize) -> String {
+    let line_start = content[..at].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    content[line_start..at]
+        .chars()
+        .take_while(|c| *c == '\t' || *c == ' ')
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCALCONFIG: &str = include_str!("testdata/localconfig.vdf");
+    const LOCALCONFIG_COMPACT: &str = include_str!("testdata/localconfig_compact.vdf");
+    const LIBRARYFOLDERS: &str = include_str!("testdata/libraryfolders.vdf");
+    const LOGINUSERS: &str = include_str!("testdata/loginusers.vdf");
+
+    const APPS: [&str; 5] = ["UserLocalConfigStore", "Software", "Valve", "Steam", "apps"];
+
+    fn app<'a>(nodes: &'a [Node], app_id: &str) -> &'a Node {
+        find(find_path(nodes, &APPS).unwrap().children(), app_id).unwrap()
+    }
+
+    /// Everything but `changed` (in the original) is still byte-identical.
+    fn assert_only_changed(original: &str, edited: &str, changed: Range<usize>) {
+        let tail = original.len() - changed.end;
+        assert_eq!(edited[..changed.start], original[..changed.start]);
+        assert_eq!(edited[edited.len() - tail..], original[changed.end..]);
+    }
+
+    #[test]
+    fn parses_localconfig() {
+        let nodes = parse(LOCALCONFIG).unwrap();
+        let launch = |app_id| {
+            find(app(&nodes, app_id).children(), "launchoptions").and_then(Node::as_str)
+        };
+        assert_eq!(launch("570"), Some("-novid -console"));
+        assert_eq!(
+            launch("1091500"),
+            Some(r#"gamemoderun %command% --title "Night City" C:\Games"#)
+        );
+        assert_eq!(launch("292030"), None);
+        assert_eq!(
+            find(app(&nodes, "292030").children(), "LastPlayed").and_then(Node::as_str),
+            Some("1700000000")
+        );
+
+        let steam = find_path(&nodes, &APPS[..4]).unwrap();
+        let sync = find(steam.children(), "LastPlayedTimesSyncTime").unwrap();
+        assert_eq!(sync.as_str(), Some("1760000000"));
+        assert_eq!(
+            find_path(&nodes, &["UserLocalConfigStore", "friends", "PersonaName"])
+                .and_then(Node::as_str),
+            Some("[KD] Kaede")
+        );
+    }
+
+    #[test]
+    fn parses_libraryfolders_and_loginusers() {
+        let nodes = parse(LIBRARYFOLDERS).unwrap();
+        let folders = find(&nodes, "libraryfolders").unwrap().children();
+        let paths = folders
+            .iter()
+            .filter_map(|folder| find(folder.children(), "path").and_then(Node::as_str))
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["/home/kaede/.local/share/Steam", "/mnt/games/SteamLibrary"]);
+        assert_eq!(
+            find_path(folders, &["1", "label"]).and_then(Node::as_str),
+            Some("Games [SSD]")
+        );
+
+        let nodes = parse(LOGINUSERS).unwrap();
+        let users = find(&nodes, "users").unwrap().children();
+        assert_eq!(users.len(), 3);
+        assert_eq!(
+            find(users[0].children(), "PersonaName").and_then(Node::as_str),
+            Some(r#"Kaede "K" Akamatsu"#)
+        );
+    }
+
+    #[test]
+    fn set_value_only_touches_the_value() {
+        let nodes = parse(LOCALCONFIG).unwrap();
+        let node = find(app(&nodes, "1091500").children(), "LaunchOptions").unwrap();
+        let Value::Str { span, .. } = &node.value else {
+            panic!("LaunchOptions is not a string");
+        };
+
+        let value = r#"KAEDE_GPU_MANAGED=1 DXVK_FILTER_DEVICE_NAME='GeForce "RTX"' \o/"#;
+        let mut edited = LOCALCONFIG.to_string();
+        set_value(&mut edited, node, value);
+        assert_only_changed(LOCALCONFIG, &edited, span.clone());
+
+        let nodes = parse(&edited).unwrap();
+        let node = find(app(&nodes, "1091500").children(), "LaunchOptions").unwrap();
+        assert_eq!(node.as_str(), Some(value));
+    }
+
+    #[test]
+    fn remove_drops_the_whole_line() {
+        let nodes = parse(LOCALCONFIG).unwrap();
+        let node = find(app(&nodes, "570").children(), "LaunchOptions").unwrap();
+        let mut edited = LOCALCONFIG.to_string();
+        remove(&mut edited, node);
+        assert_eq!(
+            edited,
+            LOCALCONFIG.replace("\t\t\t\t\t\t\"LaunchOptions\"\t\t\"-novid -console\"\n", "")
+        );
+    }
+
+    #[test]
+    fn remove_inside_a_line_keeps_the_rest_of_it() {
+        let nodes = parse(LOCALCONFIG).unwrap();
+        let node = find(app(&nodes, "292030").children(), "LastPlayed").unwrap();
+        let mut edited = LOCALCONFIG.to_string();
+        remove(&mut edited, node);
+        assert_eq!(
+            edited,
+            LOCALCONFIG.replace(r#"{   "LastPlayed"  "1700000000"   }"#, "{      }")
+        );
+    }
+
+    #[test]
+    fn appended_entries_follow_the_block_indentation() {
+        let nodes = parse(LOCALCONFIG).unwrap();
+        let mut edited = LOCALCONFIG.to_string();
+        append_string(&mut edited, app(&nodes, "570"), "LaunchOptions2", "x");
+        assert_eq!(
+            edited,
+            LOCALCONFIG.replace(
+                "\"-novid -console\"\n",
+                "\"-novid -console\"\n\t\t\t\t\t\t\"LaunchOptions2\"\t\t\"x\"\n"
+            )
+        );
+
+        let mut edited = LOCALCONFIG.to_string();
+        append_block(
+            &mut edited,
+            find_path(&nodes, &APPS).unwrap(),
+            "440",
+            &[("LaunchOptions", "%command% -dev")],
+        );
+        let block = "\t\t\t\t\t\"440\"\n\t\t\t\t\t{\n\t\t\t\t\t\t\"LaunchOptions\"\t\t\
+                     \"%command% -dev\"\n\t\t\t\t\t}\n";
+        let close = "\t\t\t\t}\n\t\t\t\t\"LastPlayedTimesSyncTime\"";
+        assert_eq!(edited, LOCALCONFIG.replace(close, &format!("{block}{close}")));
+        assert_eq!(
+            find(app(&parse(&edited).unwrap(), "440").children(), "LaunchOptions")
+                .and_then(Node::as_str),
+            Some("%command% -dev")
+        );
+    }
+
+    #[test]
+    fn appending_to_a_one_line_block_opens_it_up() {
+        let nodes = parse(LOCALCONFIG).unwrap();
+        let mut edited = LOCALCONFIG.to_string();
+        append_string(&mut edited, app(&nodes, "292030"), "LaunchOptions", "-windowed");
+        assert_eq!(
+            edited,
+            LOCALCONFIG.replace(
+                r#""1700000000"   }"#,
+                "\"1700000000\"   \n\t\t\t\t\t\t\"LaunchOptions\"\t\t\"-windowed\"\n\t\t\t\t\t}"
+            )
+        );
+        parse(&edited).unwrap();
+    }
+
+    #[test]
+    fn comments_and_odd_whitespace_are_skipped() {
+        let content = "// header\n\"a\"//c\n{\r\n  \"b\"\t \t\"1\"// trailing\n\n\n\
+                       \t\"c\" \"//not a comment\"\n}";
+        let nodes = parse(content).unwrap();
+        let a = find(&nodes, "a").unwrap().children();
+        assert_eq!(find(a, "b").and_then(Node::as_str), Some("1"));
+        assert_eq!(find(a, "c").and_then(Node::as_str), Some("//not a comment"));
+    }
+
+    #[test]
+    fn conditionals_only_follow_values() {
+        let nodes = parse("\"a\" \"1\" [$WIN32]\n\"b\" \"2\"\t[!$X360]\n").unwrap();
+        assert_eq!(find(&nodes, "a").and_then(Node::as_str), Some("1"));
+        assert_eq!(find(&nodes, "b").and_then(Node::as_str), Some("2"));
+
+        // Unquoted values may start with a bracket.
+        let nodes = parse("\"label\" [SSD]\n\"path\" \"/mnt\"\n").unwrap();
+        assert_eq!(find(&nodes, "label").and_then(Node::as_str), Some("[SSD]"));
+        assert_eq!(find(&nodes, "path").and_then(Node::as_str), Some("/mnt"));
+
+        // A bracket on the next line is the next key, not a conditional.
+        let nodes = parse("\"a\" \"1\"\n[b] \"2\"\n").unwrap();
+        assert_eq!(find(&nodes, "[b]").and_then(Node::as_str), Some("2"));
+
+        assert!(parse("\"a\" \"1\" [$WIN32\n").is_err());
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        assert!(parse("\"a\" {").is_err());
+        assert!(parse("\"a\" \"1\" }").is_err());
+        assert!(parse("\"a\"").is_err());
+        assert!(parse("\"a\" \"unterminated").is_err());
+        assert!(parse("{ \"a\" \"1\" }").is_err());
+    }
+
+    #[test]
+    fn unknown_escapes_are_kept() {
+        let nodes = parse(LOCALCONFIG_COMPACT).unwrap();
+        assert_eq!(
+            find(app(&nodes, "1245620").children(), "LaunchOptions").and_then(Node::as_str),
+            Some(r#"PROTON_LOG=1 %command% "C:\Games\Elden Ring" \\server\share"#)
+        );
+        assert_eq!(
+            find_path(&nodes, &["UserLocalConfigStore", "friends", "PersonaName"])
+                .and_then(Node::as_str),
+            Some("tab\there")
+        );
+        let nodes = parse(r#""a" "\q \\ \" \n""#).unwrap();
+        assert_eq!(find(&nodes, "a").and_then(Node::as_str), Some("\\q \\ \" \n"));
+    }
+
+    /// Deterministic xorshift, so the corpus test is reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn text(&mut self) -> String {
+            const PIECES: [&str; 14] = [
+                "%command%", " ", "-novid", "DRI_PRIME=1", "\"", "\\", "\t", "\n", "{", "}",
+                "//", "[$WIN32]", "エ", "C:\\Games",
+            ];
+            (0..self.below(5)).map(|_| PIECES[self.below(PIECES.len())]).collect()
+        }
+    }
+
+    type Entry<'a> = (Vec<String>, Option<String>, &'a Node);
+
+    /// Every entry in file order as its key path, string value (`None` for
+    /// blocks) and the node itself.
+    fn flatten<'a>(nodes: &'a [Node], path: &[String], out: &mut Vec<Entry<'a>>) {
+        for node in nodes {
+            let mut path = path.to_vec();
+            path.push(node.key.clone());
+            out.push((path.clone(), node.as_str().map(str::to_string), node));
+            flatten(node.children(), &path, out);
+        }
+    }
+
+    fn tree(content: &str) -> Vec<(Vec<String>, Option<String>)> {
+        let nodes = parse(content).unwrap();
+        let mut entries = Vec::new();
+        flatten(&nodes, &[], &mut entries);
+        entries.into_iter().map(|(path, value, _)| (path, value)).collect()
+    }
+
+    fn line_start(content: &str, at: usize) -> usize {
+        content[..at].rfind('\n').map(|i| i + 1).unwrap_or(0)
+    }
+
+    /// Random edits on every sample: each one must leave the rest of the file
+    /// byte-identical and the rest of the tree unchanged when parsed again.
+    #[test]
+    fn edits_keep_untouched_spans() {
+        let corpus = [
+            LOCALCONFIG.to_string(),
+            LOCALCONFIG.replace('\n', "\r\n"),
+            LOCALCONFIG_COMPACT.to_string(),
+            LIBRARYFOLDERS.to_string(),
+            LOGINUSERS.to_string(),
+        ];
+        let mut rng = Rng(0x6b61_6564_6521);
+        for sample in &corpus {
+            for _ in 0..200 {
+                let mut content = sample.clone();
+                for _ in 0..3 {
+                    let nodes = parse(&content).unwrap();
+                    let mut entries = Vec::new();
+                    flatten(&nodes, &[], &mut entries);
+                    if entries.is_empty() {
+                        break;
+                    }
+                    let index = rng.below(entries.len());
+                    let (path, value, node) = &entries[index];
+                    let subtree = 1 + entries[index + 1..]
+                        .iter()
+                        .take_while(|(other, ..)| {
+                            other.len() > path.len() && other.starts_with(path)
+                        })
+                        .count();
+                    let mut expected = entries
+                        .iter()
+                        .map(|(path, value, _)| (path.clone(), value.clone()))
+                        .collect::<Vec<_>>();
+
+                    let mut edited = content.clone();
+                    let allowed = match (value, rng.below(3)) {
+                        (Some(_), 0) => {
+                            let text = rng.text();
+                            set_value(&mut edited, node, &text);
+                            expected[index].1 = Some(text);
+                            match &node.value {
+                                Value::Str { span, .. } => span.clone(),
+                                Value::Block { .. } => unreachable!(),
+                            }
+                        }
+                        (None, 0) => {
+                            let (key, text) = (rng.text(), rng.text());
+                            append_string(&mut edited, node, &key, &text);
+                            let mut new_path = path.clone();
+                            new_path.push(key);
+                            expected.insert(index + subtree, (new_path, Some(text)));
+                            let Value::Block { close, .. } = node.value else {
+                                unreachable!();
+                            };
+                            line_start(&content, close)..close
+                        }
+                        _ => {
+                            remove(&mut edited, node);
+                            expected.drain(index..index + subtree);
+                            let span = node.span();
+                            let end = content[span.end..]
+                                .find('\n')
+                                .map_or(content.len(), |i| span.end + i + 1);
+                            line_start(&content, span.start)..end
+                        }
+                    };
+
+                    assert_only_changed(&content, &edited, allowed);
+                    assert_eq!(tree(&edited), expected, "after editing {path:?}:\n{edited}");
+                    content = edited;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,383 @@
+//! Multi-file changes that land together or not at all. Writes and removals
+//! are staged first, then applied one file at a time with write-to-temp +
+//! rename; if any file fails, the ones already changed are put back.
+
+use crate::backup;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use tracing::{debug, error, info};
+
+const DEFAULT_MODE: u32 = 0o644;
+const EXECUTABLE_MODE: u32 = 0o755;
+
+enum Change {
+    /// `mode` is kept from the existing file when `None`.
+    Write { content: Vec<u8>, mode: Option<u32> },
+    Remove,
+}
+
+#[derive(Default)]
+pub struct Transaction {
+    changes: Vec<(PathBuf, Change)>,
+}
+
+/// What a committed transaction replaced, so it can be undone.
+pub struct Applied {
+    previous: Vec<(PathBuf, Option<Previous>)>,
+}
+
+struct Previous {
+    content: Vec<u8>,
+    mode: u32,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, path: impl Into<PathBuf>, content: impl Into<Vec<u8>>) {
+        self.stage(
+            path.into(),
+            Change::Write {
+                content: content.into(),
+                mode: None,
+            },
+        );
+    }
+
+    pub fn write_executable(&mut self, path: impl Into<PathBuf>, content: impl Into<Vec<u8>>) {
+        self.stage(
+            path.into(),
+            Change::Write {
+                content: content.into(),
+                mode: Some(EXECUTABLE_MODE),
+            },
+        );
+    }
+
+    pub fn write_with_mode(
+        &mut self,
+        path: impl Into<PathBuf>,
+        content: impl Into<Vec<u8>>,
+        mode: u32,
+    ) {
+        self.stage(
+            path.into(),
+            Change::Write {
+                content: content.into(),
+                mode: Some(mode),
+            },
+        );
+    }
+
+    /// Removing a file that doesn't exist is not an error.
+    pub fn remove(&mut self, path: impl Into<PathBuf>) {
+        self.stage(path.into(), Change::Remove);
+    }
+
+    /// Content of `path` as it will be once the staged changes are applied.
+    pub fn read(&self, path: &Path) -> Option<Vec<u8>> {
+        match self.changes.iter().find(|(staged, _)| staged == path) {
+            Some((_, Change::Write { content, .. })) => Some(content.clone()),
+            Some((_, Change::Remove)) => None,
+            None => fs::read(path).ok(),
+        }
+    }
+
+    /// Staged changes in order, with the new content or `None` for a removal.
+    pub fn changes(&self) -> impl Iterator<Item = (&Path, Option<&[u8]>)> {
+        self.changes.iter().map(|(path, change)| match change {
+            Change::Write { content, .. } => (path.as_path(), Some(content.as_slice())),
+            Change::Remove => (path.as_path(), None),
+        })
+    }
+
+    /// A later change to the same file replaces the earlier one.
+    fn stage(&mut self, path: PathBuf, change: Change) {
+        match self.changes.iter_mut().find(|(staged, _)| *staged == path) {
+            Some((_, existing)) => *existing = change,
+            None => self.changes.push((path, change)),
+        }
+    }
+
+    /// Applies every staged change, backing each file up first. On failure the
+    /// files changed so far are restored and the original error is returned.
+    pub fn commit(self) -> Result<Applied> {
+        let mut applied = Applied {
+            previous: Vec::new(),
+        };
+        for (path, change) in self.changes {
+            let result = read_previous(&path).and_then(|previous| {
+                let changed = apply_change(&path, &change, previous.as_ref())?;
+                Ok(changed.then_some(previous))
+            });
+            match result {
+                Ok(Some(previous)) => applied.previous.push((path, previous)),
+                Ok(None) => {}
+                Err(err) => {
+                    if let Err(rollback_err) = applied.rollback() {
+                        error!(error = %format!("{rollback_err:#}"), "transaction rollback failed");
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        Ok(applied)
+    }
+}
+
+impl Applied {
+    /// Puts back every file the transaction changed, newest change first.
+    /// Keeps going past failures and reports all of them.
+    pub fn rollback(self) -> Result<()> {
+        let mut failures = Vec::new();
+        for (path, previous) in self.previous.into_iter().rev() {
+            let result = match &previous {
+                Some(previous) => atomic_write(&path, &previous.content, previous.mode),
+                None => remove_file(&path),
+            };
+            match result {
+                Ok(()) => info!(path = %path.display(), "change rolled back"),
+                Err(err) => failures.push(format!("{}: {err:#}", path.display())),
+            }
+        }
+        if !failures.is_empty() {
+            anyhow::bail!("failed to roll back {}", failures.join("; "));
+        }
+        Ok(())
+    }
+}
+
+fn read_previous(path: &Path) -> Result<Option<Previous>> {
+    match fs::metadata(path) {
+        Ok(metadata) => Ok(Some(Previous {
+            content: fs::read(path).with_context(|| format!("failed to read {}", path.display()))?,
+            mode: metadata.permissions().mode() & 0o7777,
+        })),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// Returns `false` when the file is already in the requested state.
+fn apply_change(path: &Path, change: &Change, previous: Option<&Previous>) -> Result<bool> {
+    match change {
+        Change::Write { content, mode } => {
+            let mode = mode.or(previous.map(|p| p.mode)).unwrap_or(DEFAULT_MODE);
+            if previous.is_some_and(|p| p.content == *content && p.mode == mode) {
+                return Ok(false);
+            }
+            backup::snapshot(path)?;
+            atomic_write(path, content, mode)?;
+            debug!(path = %path.display(), "file written");
+        }
+        Change::Remove => {
+            if previous.is_none() {
+                return Ok(false);
+            }
+            backup::snapshot(path)?;
+            remove_file(path)?;
+            debug!(path = %path.display(), "file removed");
+        }
+    }
+    Ok(true)
+}
+
+/// Writes next to `path` and renames over it, so readers never see a partial file.
+/// A symlink is written through to its target and stays a link.
+fn atomic_write(path: &Path, content: &[u8], mode: u32) -> Result<()> {
+    let path = &resolve_symlinks(path);
+    let parent = path
+        .parent()
+        .with_context(|| format!("{} has no parent directory", path.display()))?;
+    fs::create_dir_all(parent)
+        .with_context(|| format!("failed to create {}", parent.display()))?;
+    let name = path
+        .file_name()
+        .with_context(|| format!("{} has no file name", path.display()))?;
+    let temp = parent.join(format!(".{}.kaede-tmp", name.to_string_lossy()));
+
+    let result = write_temp(&temp, content, mode).and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result.with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Follows `path` through symlinks to the file they point at, which may not exist yet.
+fn resolve_symlinks(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf();
+    // Bounded like the kernel's own limit, so a link loop fails at write time.
+    for _ in 0..40 {
+        let Ok(target) = fs::read_link(&path) else {
+            break;
+        };
+        path = match path.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        };
+    }
+    path
+}
+
+fn write_temp(temp: &Path, content: &[u8], mode: u32) -> io::Result<()> {
+    let mut file = fs::File::create(temp)?;
+    file.write_all(content)?;
+    file.set_permissions(fs::Permissions::from_mode(mode))?;
+    file.sync_all()
+}
+
+fn remove_file(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err).with_context(|| format!("failed to remove {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for one test.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("kaede-test-{}", std::process::id()))
+            .join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o7777
+    }
+
+    #[test]
+    fn commits_every_file() {
+        let dir = scratch("commit");
+        let existing = dir.join("localconfig.vdf");
+        fs::write(&existing, "old").unwrap();
+        fs::set_permissions(&existing, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let mut tx = Transaction::new();
+        tx.write(&existing, "new");
+        tx.write(dir.join("nested/created.desktop"), "[Desktop Entry]\n");
+        tx.write(dir.join("staged-twice"), "first");
+        tx.write(dir.join("staged-twice"), "second");
+        assert_eq!(tx.read(&existing).as_deref(), Some(&b"new"[..]));
+        assert_eq!(tx.changes().count(), 3);
+        tx.commit().unwrap();
+
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "new");
+        assert_eq!(mode(&existing), 0o600);
+        assert_eq!(
+            fs::read_to_string(dir.join("nested/created.desktop")).unwrap(),
+            "[Desktop Entry]\n"
+        );
+        assert_eq!(mode(&dir.join("nested/created.desktop")), DEFAULT_MODE);
+        assert_eq!(fs::read_to_string(dir.join("staged-twice")).unwrap(), "second");
+        assert!(!dir.join(".localconfig.vdf.kaede-tmp").exists());
+    }
+
+    #[test]
+    fn rollback_restores_and_removes() {
+        let dir = scratch("rollback");
+        let existing = dir.join("existing");
+        let created = dir.join("created");
+        fs::write(&existing, "original").unwrap();
+        fs::set_permissions(&existing, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let mut tx = Transaction::new();
+        tx.write_executable(&existing, "changed");
+        tx.write(&created, "new");
+        let applied = tx.commit().unwrap();
+        assert_eq!(mode(&existing), EXECUTABLE_MODE);
+
+        applied.rollback().unwrap();
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "original");
+        assert_eq!(mode(&existing), 0o640);
+        assert!(!created.exists());
+    }
+
+    #[test]
+    fn removes_files() {
+        let dir = scratch("remove");
+        let existing = dir.join("override.desktop");
+        fs::write(&existing, "[Desktop Entry]\n").unwrap();
+
+        let mut tx = Transaction::new();
+        tx.remove(&existing);
+        tx.remove(dir.join("missing"));
+        assert_eq!(tx.read(&existing), None);
+        let applied = tx.commit().unwrap();
+        assert!(!existing.exists());
+
+        applied.rollback().unwrap();
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "[Desktop Entry]\n");
+        assert!(!dir.join("missing").exists());
+    }
+
+    #[test]
+    fn writes_executables() {
+        let dir = scratch("executable");
+        let wrapper = dir.join("kaede-wrapper");
+        fs::write(&wrapper, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&wrapper, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let mut tx = Transaction::new();
+        tx.write_executable(&wrapper, "#!/bin/sh\n");
+        tx.write_executable(dir.join("new-wrapper"), "#!/bin/sh\n");
+        tx.write_with_mode(dir.join("secret"), "token", 0o600);
+        tx.commit().unwrap();
+
+        assert_eq!(mode(&wrapper), EXECUTABLE_MODE);
+        assert_eq!(mode(&dir.join("new-wrapper")), EXECUTABLE_MODE);
+        assert_eq!(mode(&dir.join("secret")), 0o600);
+    }
+
+    #[test]
+    fn failed_commit_puts_back_earlier_files() {
+        let dir = scratch("failure");
+        let existing = dir.join("existing");
+        let created = dir.join("created");
+        let blocker = dir.join("not-a-directory");
+        fs::write(&existing, "original").unwrap();
+        fs::write(&blocker, "").unwrap();
+
+        let mut tx = Transaction::new();
+        tx.write(&existing, "changed");
+        tx.write(&created, "new");
+        tx.write(blocker.join("file"), "unreachable");
+        tx.write(dir.join("after"), "never written");
+        assert!(tx.commit().is_err());
+
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "original");
+        assert!(!created.exists());
+        assert!(!dir.join("after").exists());
+    }
+
+    #[test]
+    fn writes_through_symlinks() {
+        let dir = scratch("symlink");
+        fs::create_dir(dir.join("dotfiles")).unwrap();
+        let target = dir.join("dotfiles/localconfig.vdf");
+        let link = dir.join("localconfig.vdf");
+        fs::write(&target, "old").unwrap();
+        std::os::unix::fs::symlink("dotfiles/localconfig.vdf", &link).unwrap();
+
+        let mut tx = Transaction::new();
+        tx.write(&link, "new");
+        let applied = tx.commit().unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+
+        applied.rollback().unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "old");
+    }
+}
@@ -1,4 +1,4 @@
-use std::cell::{Cell, RefCell};
+use std::cell::RefCell;
 use std::rc::Rc;
 
 use adw::prelude::*;
@@ -10,10 +10,14 @@ use crate::models::{DesktopApp, GpuChoice, GpuInfo};
 use crate::steam::is_steam_running;
 
 use super::details::{
-    build_app_icon, build_gpu_choices, gpu_choice_label, selected_gpu_for_choice, AppDetailsWidgets,
+    assignment_label, build_app_icon, build_gpu_choices, gpu_choice_label, is_gpu_missing, selected_gpu_for_choice,
+    AppDetailsWidgets,
 };
 use super::util::clear_listbox;
 
+/// Session-level source filter: (steam, heroic, lutris, flatpak, native).
+pub(crate) type SourceFilter = (bool, bool, bool, bool, bool);
+
 pub(crate) fn rebuild_app_list(
     list: &gtk::ListBox,
     window: &adw::ApplicationWindow,
@@ -24,7 +28,7 @@ pub(crate) fn rebuild_app_list(
     filter: &str,
     details_widgets: &AppDetailsWidgets,
     selected_app_id: &Rc<RefCell<Option<String>>>,
-    ui_filter: (bool, bool, bool, bool), // (steam, heroic, flatpak, native)
+    ui_filter: SourceFilter,
 ) {
     clear_listbox(list);
     visible_apps.borrow_mut().clear();
@@ -33,8 +37,9 @@ pub(crate) fn rebuild_app_list(
     let cfg = config.borrow();
     let show_steam = cfg.show_steam_apps() && ui_filter.0;
     let show_heroic = cfg.show_heroic_apps() && ui_filter.1;
-    let show_flatpak = cfg.show_flatpak_apps() && ui_filter.2;
-    let show_native = ui_filter.3;
+    let show_lutris = cfg.show_lutris_apps() && ui_filter.2;
+    let show_flatpak = cfg.show_flatpak_apps() && ui_filter.3;
+    let show_native = ui_filter.4;
 
     for app in apps {
         if app.is_steam_game && !show_steam {
@@ -43,10 +48,15 @@ pub(crate) fn rebuild_app_list(
         if app.is_heroic_game && !show_heroic {
             continue;
         }
-        if app.is_flatpak && !show_flatpak {
+        if app.is_lutris_game && !show_lutris {
+            continue;
+        }
+        // Flatpak Lutris shortcuts are filtered as Lutris games.
+        if app.is_flatpak && !app.is_lutris_game && !show_flatpak {
             continue;
         }
-        let is_native = !app.is_steam_game && !app.is_heroic_game && !app.is_flatpak;
+        let is_native =
+            !(app.is_steam_game || app.is_heroic_game || app.is_lutris_game || app.is_flatpak);
         if is_native && !show_native {
             continue;
         }
@@ -73,17 +83,25 @@ pub(crate) fn rebuild_app_list(
         center.append(&name);
 
         let current_choice = config.borrow().get_choice(&app.desktop_id);
+        let pending = config.borrow().is_steam_change_pending(&app.desktop_id);
         let current = gtk::Label::new(Some(&format!(
             "Current: {}",
-            gpu_choice_label(gpus, &current_choice)
+            assignment_label(gpus, &current_choice, pending)
         )));
         current.set_xalign(0.0);
         current.add_css_class("caption");
+        if is_gpu_missing(gpus, &current_choice) {
+            current.add_css_class("warning");
+        }
         center.append(&current);
 
         row.append(&center);
 
-        let choices = build_gpu_choices(gpus);
+        let mut choices = build_gpu_choices(gpus);
+        if is_gpu_missing(gpus, &current_choice) {
+            // Keep the stale assignment selectable so the combo reflects the config.
+            choices.push((gpu_choice_label(gpus, &current_choice), current_choice.clone()));
+        }
         let combo = gtk::ComboBoxText::new();
         // Prevent accidental GPU changes when scrolling over the combo.
         let scroll_block =
@@ -106,16 +124,9 @@ pub(crate) fn rebuild_app_list(
             let config = config.clone();
             let gpus_shared = gpus_shared.clone();
             let window = window.clone();
-            let last_choice = Rc::new(RefCell::new(current_choice.clone()));
-            let suppress_change = Rc::new(Cell::new(false));
             let details_widgets = details_widgets.clone();
             let selected_app_id = selected_app_id.clone();
             combo.connect_changed(move |c| {
-                if suppress_change.get() {
-                    suppress_change.set(false);
-                    return;
-                }
-
                 let Some(idx) = c.active() else {
                     return;
                 };
@@ -134,26 +145,17 @@ pub(crate) fn rebuild_app_list(
                     selected_gpu = ?selected_gpu.as_ref().map(|g| g.name.clone()),
                     "changing GPU assignment"
                 );
-                if app.is_steam_game && is_steam_running() {
-                    warn!(
-                        app_name = %app.name,
-                        steam_app_id = ?app.steam_app_id,
-                        "Steam is running while changing a Steam game; blocking change"
-                    );
-                    show_steam_running_dialog(&window);
-                    let previous = last_choice.borrow().clone();
-                    let previous_idx = choices
-                        .iter()
-                        .position(|(_, ch)| *ch == previous)
-                        .unwrap_or(0);
-                    suppress_change.set(true);
-                    c.set_active(Some(previous_idx as u32));
-                    return;
+                // Steam rewrites localconfig.vdf on exit, so hold the change until it closes.
+                let steam_busy = app.is_steam_game && is_steam_running();
+                {
+                    let mut cfg = config.borrow_mut();
+                    cfg.set_choice(&app.desktop_id, choice.clone());
+                    if steam_busy {
+                        cfg.queue_steam_change(&app.desktop_id);
+                    } else {
+                        cfg.clear_steam_change(&app.desktop_id);
+                    }
                 }
-
-                config
-                    .borrow_mut()
-                    .set_choice(&app.desktop_id, choice.clone());
                 if let Err(err) = config.borrow().save() {
                     error!(
                         desktop_id = %app.desktop_id,
@@ -162,34 +164,51 @@ pub(crate) fn rebuild_app_list(
                     );
                 }
 
-                match apply_launcher_override(
-                    &app,
-                    &choice,
-                    selected_gpu.as_ref(),
-                    gpus_shared.as_ref(),
-                    config.borrow().use_env_wrapper(),
-                ) {
-                    Ok(()) => info!(
+                if steam_busy {
+                    info!(
                         app_name = %app.name,
-                        desktop_id = %app.desktop_id,
-                        "GPU assignment applied successfully"
-                    ),
-                    Err(err) => warn!(
-                        app_name = %app.name,
-                        desktop_id = %app.desktop_id,
-                        error = %err,
-                        "failed to apply GPU assignment override"
-                    ),
+                        steam_app_id = ?app.steam_app_id,
+                        "Steam is running; GPU assignment queued until Steam closes"
+                    );
+                    show_steam_pending_dialog(&window);
+                } else {
+                    let action_choices = config.borrow().action_choices(&app.desktop_id);
+                    let steam_accounts = config.borrow().steam_account_scope(&app.desktop_id);
+                    match apply_launcher_override(
+                        &app,
+                        &choice,
+                        &action_choices,
+                        &steam_accounts,
+                        selected_gpu.as_ref(),
+                        gpus_shared.as_ref(),
+                        config.borrow().use_env_wrapper(),
+                    ) {
+                        Ok(()) => info!(
+                            app_name = %app.name,
+                            desktop_id = %app.desktop_id,
+                            "GPU assignment applied successfully"
+                        ),
+                        Err(err) => warn!(
+                            app_name = %app.name,
+                            desktop_id = %app.desktop_id,
+                            error = %err,
+                            "failed to apply GPU assignment override"
+                        ),
+                    }
                 }
 
-                last_choice.replace(choice.clone());
                 current.set_text(&format!(
                     "Current: {}",
-                    gpu_choice_label(gpus_shared.as_ref(), &choice)
+                    assignment_label(gpus_shared.as_ref(), &choice, steam_busy)
                 ));
+                if is_gpu_missing(gpus_shared.as_ref(), &choice) {
+                    current.add_css_class("warning");
+                } else {
+                    current.remove_css_class("warning");
+                }
                 let selected = selected_app_id.borrow().clone();
                 if selected.as_deref() == Some(app.desktop_id.as_str()) {
-                    super::details::set_app_details(&details_widgets, &app, &choice, &gpus_shared);
+                    super::details::set_app_details(&details_widgets, &app, &config, &gpus_shared);
                 }
             });
         }
@@ -199,16 +218,17 @@ pub(crate) fn rebuild_app_list(
     }
 }
 
-fn show_steam_running_dialog(window: &adw::ApplicationWindow) {
+fn show_steam_pending_dialog(window: &adw::ApplicationWindow) {
     let dialog = gtk::MessageDialog::builder()
         .transient_for(window)
         .modal(true)
-        .message_type(gtk::MessageType::Warning)
+        .message_type(gtk::MessageType::Info)
         .text("Steam is running")
-        .secondary_text("Close Steam completely before changing GPU assignment for Steam games.")
+        .secondary_text(
+            "The assignment is saved and will be written to Steam once Steam closes completely.",
+        )
         .build();
     dialog.add_button("OK", gtk::ResponseType::Ok);
     dialog.connect_response(|d, _| d.close());
     dialog.present();
 }
-
@@ -0,0 +1,172 @@
+use std::path::PathBuf;
+
+use adw::prelude::*;
+use tracing::error;
+
+use super::nvidia_progress::run_with_progress_dialog;
+use crate::backup::{self, ChangeSet};
+use crate::datetime;
+use crate::nvidia::helper;
+
+pub(crate) fn show_backups_dialog(window: &adw::ApplicationWindow) {
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .title("Backups")
+        .default_width(620)
+        .default_height(480)
+        .build();
+
+    let wrapper = gtk::Box::new(gtk::Orientation::Vertical, 12);
+    wrapper.set_margin_top(18);
+    wrapper.set_margin_bottom(18);
+    wrapper.set_margin_start(18);
+    wrapper.set_margin_end(18);
+
+    let desc = gtk::Label::new(Some(
+        "Kaede saves a copy of every file before changing it. Restoring also backs up the current files first, so it can be undone from here too.",
+    ));
+    desc.set_wrap(true);
+    desc.set_wrap_mode(gtk::pango::WrapMode::WordChar);
+    desc.add_css_class("dim-label");
+    desc.set_xalign(0.0);
+    wrapper.append(&desc);
+
+    let sets = backup::list();
+    if sets.is_empty() {
+        let empty = gtk::Label::new(Some("No backups yet."));
+        empty.add_css_class("dim-label");
+        empty.set_vexpand(true);
+        wrapper.append(&empty);
+    } else {
+        let list = gtk::ListBox::new();
+        list.add_css_class("boxed-list");
+        list.set_selection_mode(gtk::SelectionMode::None);
+        for set in &sets {
+            list.append(&build_change_set_row(window, &dialog, set));
+        }
+
+        let scrolled = gtk::ScrolledWindow::builder()
+            .child(&list)
+            .hscrollbar_policy(gtk::PolicyType::Never)
+            .vexpand(true)
+            .build();
+        wrapper.append(&scrolled);
+    }
+    dialog.content_area().append(&wrapper);
+
+    dialog.add_button("Close", gtk::ResponseType::Close);
+    dialog.connect_response(|d, _| d.close());
+    dialog.present();
+}
+
+fn build_change_set_row(
+    window: &adw::ApplicationWindow,
+    dialog: &gtk::Dialog,
+    set: &ChangeSet,
+) -> adw::ExpanderRow {
+    let files = match set.files.len() {
+        1 => "1 file".to_string(),
+        n => format!("{n} files"),
+    };
+    let row = adw::ExpanderRow::builder()
+        .title(set.description.as_str())
+        .subtitle(format!("{} · {files}", datetime::local_label(set.created)).as_str())
+        .build();
+
+    let restore_all = gtk::Button::builder()
+        .label("Restore all")
+        .valign(gtk::Align::Center)
+        .build();
+    connect_restore(&restore_all, window, dialog, set, None);
+    row.add_action(&restore_all);
+
+    for file in &set.files {
+        let subtitle = if file.stored.is_some() {
+            "Saved copy"
+        } else {
+            "Did not exist; restoring removes it"
+        };
+        let file_row = adw::ActionRow::builder()
+            .title(file.original.display().to_string().as_str())
+            .subtitle(subtitle)
+            .build();
+        let restore = gtk::Button::builder()
+            .label("Restore")
+            .valign(gtk::Align::Center)
+            .build();
+        restore.add_css_class("flat");
+        connect_restore(&restore, window, dialog, set, Some(file.original.clone()));
+        file_row.add_suffix(&restore);
+        row.add_row(&file_row);
+    }
+    row
+}
+
+fn connect_restore(
+    button: &gtk::Button,
+    window: &adw::ApplicationWindow,
+    dialog: &gtk::Dialog,
+    set: &ChangeSet,
+    only: Option<PathBuf>,
+) {
+    let window = window.clone();
+    let dialog = dialog.clone();
+    let id = set.id.clone();
+    let system = set.system;
+    button.connect_clicked(move |_| {
+        dialog.close();
+        // The helper's change sets hold root-owned files.
+        if system {
+            let id = id.clone();
+            let only = only.clone();
+            run_with_progress_dialog(
+                &window,
+                "Restoring backup",
+                "Files restored. Reboot the system to apply NVIDIA changes.",
+                move |output| helper::restore_backup(&id, only.as_deref(), output),
+                |result| {
+                    if let Err(err) = result {
+                        error!(%err, "failed to restore backup");
+                    }
+                },
+            );
+            return;
+        }
+
+        let (message_type, text, secondary) = match backup::restore(&id, only.as_deref()) {
+            Ok(restored) => (
+                gtk::MessageType::Info,
+                "Files restored",
+                restored
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            Err(err) => {
+                error!(change_set = %id, error = %err, "failed to restore backup");
+                (gtk::MessageType::Error, "Restore failed", format!("{err:#}"))
+            }
+        };
+        show_message(&window, message_type, text, &secondary);
+    });
+}
+
+fn show_message(
+    window: &adw::ApplicationWindow,
+    message_type: gtk::MessageType,
+    text: &str,
+    secondary: &str,
+) {
+    let dlg = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(message_type)
+        .text(text)
+        .secondary_text(secondary)
+        .build();
+    dlg.add_button("OK", gtk::ResponseType::Ok);
+    dlg.connect_response(|d, _| d.close());
+    dlg.present();
+}
@@ -1,7 +1,16 @@
 use adw::prelude::*;
+use std::cell::RefCell;
 use std::path::Path;
+use std::rc::Rc;
+use tracing::{error, info, warn};
 
-use crate::models::{DesktopApp, GpuChoice, GpuInfo};
+use crate::config::ConfigStore;
+use crate::gpu::gpu_for_choice;
+use crate::launcher::{apply_launcher_override, user_launcher_path};
+use crate::models::{DesktopAction, DesktopApp, GpuChoice, GpuInfo, SteamAccountScope};
+use crate::steam::{self, is_steam_running, steam_accounts};
+
+use super::util::clear_listbox;
 
 #[derive(Clone)]
 pub(crate) struct AppDetailsWidgets {
@@ -12,35 +21,40 @@ pub(crate) struct AppDetailsWidgets {
     pub(crate) desktop_id_row: adw::ActionRow,
     pub(crate) path_row: adw::ActionRow,
     pub(crate) exec_row: adw::ActionRow,
+    pub(crate) actions_box: gtk::Box,
+    pub(crate) actions_list: gtk::ListBox,
+    pub(crate) steam_accounts_box: gtk::Box,
+    pub(crate) steam_accounts_list: gtk::ListBox,
+    pub(crate) launch_options_box: gtk::Box,
+    pub(crate) launch_options_list: gtk::ListBox,
     pub(crate) desktop_path_label: gtk::Label,
     pub(crate) desktop_open_button: gtk::Button,
     pub(crate) desktop_preview: gtk::TextView,
 }
 
-fn user_override_path(desktop_id: &str) -> Option<std::path::PathBuf> {
-    let home = std::env::var_os("HOME")?;
-    Some(
-        std::path::PathBuf::from(home)
-            .join(".local/share/applications")
-            .join(desktop_id),
-    )
-}
-
 pub(crate) fn set_app_details(
     details: &AppDetailsWidgets,
     app: &DesktopApp,
-    choice: &GpuChoice,
+    config: &Rc<RefCell<ConfigStore>>,
     gpus: &[GpuInfo],
 ) {
-    let override_path = user_override_path(&app.desktop_id)
-        .filter(|path| path.exists())
-        .unwrap_or_else(|| app.path.clone());
+    let choice = config.borrow().get_choice(&app.desktop_id);
     apply_icon_to_image(&details.icon, app.icon.as_deref(), 48);
     details.name.set_text(&app.name);
+    let pending = config.borrow().is_steam_change_pending(&app.desktop_id);
     details
         .assignment_row
-        .set_subtitle(&gpu_choice_label(gpus, choice));
-    if app.is_steam_game {
+        .set_subtitle(&assignment_label(gpus, &choice, pending));
+    if is_gpu_missing(gpus, &choice) {
+        details.assignment_row.add_css_class("warning");
+    } else {
+        details.assignment_row.remove_css_class("warning");
+    }
+    if let (true, Some(shortcut)) = (app.is_steam_game, app.steam_shortcut_id) {
+        details
+            .source_row
+            .set_subtitle(&format!("Non-Steam game in Steam ({shortcut})"));
+    } else if app.is_steam_game {
         let app_id = app.steam_app_id.as_deref().unwrap_or("unknown");
         details
             .source_row
@@ -51,11 +65,28 @@ pub(crate) fn set_app_details(
         details
             .source_row
             .set_subtitle(&format!("Heroic {platform} ({app_name})"));
+    } else if app.is_lutris_game {
+        let slug = app.lutris_slug.as_deref().unwrap_or("unknown");
+        details
+            .source_row
+            .set_subtitle(&format!("Lutris game ({slug})"));
+    } else if app.is_bottles_app {
+        let bottle = app.bottle_name.as_deref().unwrap_or("unknown");
+        let subtitle = match app.bottle_program.as_deref() {
+            Some(program) => format!("Bottles program ({bottle} / {program})"),
+            None => format!("Bottles bottle ({bottle})"),
+        };
+        details.source_row.set_subtitle(&subtitle);
     } else if app.is_flatpak {
         let app_id = app.flatpak_app_id.as_deref().unwrap_or("unknown");
         details
             .source_row
             .set_subtitle(&format!("Flatpak ({app_id})"));
+    } else if app.is_snap {
+        let snap_name = app.snap_name.as_deref().unwrap_or("unknown");
+        details
+            .source_row
+            .set_subtitle(&format!("Snap ({snap_name})"));
     } else {
         details
             .source_row
@@ -64,23 +95,382 @@ pub(crate) fn set_app_details(
     details
         .desktop_id_row
         .set_subtitle(&app.desktop_id);
-    let override_path_str = override_path.to_string_lossy().to_string();
-    details.path_row.set_subtitle(&override_path_str);
     details.exec_row.set_subtitle(&app.exec);
+    set_action_rows(details, app, config, gpus);
+    set_steam_account_rows(details, app, config, gpus);
+    set_launch_option_rows(details, app, config);
     // Do not show the file name in the row; only use tooltip on the button.
     details.desktop_path_label.set_visible(false);
     details.desktop_path_label.set_text("");
+    refresh_desktop_preview(details, app);
+}
+
+fn set_action_rows(
+    details: &AppDetailsWidgets,
+    app: &DesktopApp,
+    config: &Rc<RefCell<ConfigStore>>,
+    gpus: &[GpuInfo],
+) {
+    clear_listbox(&details.actions_list);
+
     details
-        .desktop_open_button
-        .set_tooltip_text(Some(&override_path_str));
+        .actions_box
+        .set_visible(app.uses_desktop_override() && !app.actions.is_empty());
+    if !app.uses_desktop_override() {
+        return;
+    }
+
+    let gpus = Rc::new(gpus.to_vec());
+    for action in &app.actions {
+        let row = adw::ActionRow::builder()
+            .title(action.name.as_str())
+            .subtitle(action.id.as_str())
+            .build();
+        row.add_suffix(&build_action_combo(details, app, action, config, &gpus));
+        details.actions_list.append(&row);
+    }
+}
+
+fn build_action_combo(
+    details: &AppDetailsWidgets,
+    app: &DesktopApp,
+    action: &DesktopAction,
+    config: &Rc<RefCell<ConfigStore>>,
+    gpus: &Rc<Vec<GpuInfo>>,
+) -> gtk::ComboBoxText {
+    let current = config
+        .borrow()
+        .action_choices(&app.desktop_id)
+        .get(&action.id)
+        .cloned();
+
+    let mut choices: Vec<(String, Option<GpuChoice>)> = vec![("Same as application".to_string(), None)];
+    choices.extend(
+        build_gpu_choices(gpus)
+            .into_iter()
+            .map(|(label, choice)| (label, Some(choice))),
+    );
+    if let Some(stale) = current.as_ref().filter(|c| is_gpu_missing(gpus, c)) {
+        choices.push((gpu_choice_label(gpus, stale), Some(stale.clone())));
+    }
+
+    let combo = gtk::ComboBoxText::new();
+    combo.set_valign(gtk::Align::Center);
+    let scroll_block = gtk::EventControllerScroll::new(gtk::EventControllerScrollFlags::VERTICAL);
+    scroll_block.connect_scroll(|_, _, _| glib::Propagation::Stop);
+    combo.add_controller(scroll_block);
+    for (label, _) in &choices {
+        combo.append_text(label);
+    }
+    let selected_index = choices
+        .iter()
+        .position(|(_, choice)| *choice == current)
+        .unwrap_or(0);
+    combo.set_active(Some(selected_index as u32));
+
+    let details = details.clone();
+    let app = app.clone();
+    let action_id = action.id.clone();
+    let config = config.clone();
+    let gpus = gpus.clone();
+    combo.connect_changed(move |c| {
+        let Some(idx) = c.active() else {
+            return;
+        };
+        let action_choice = choices.get(idx as usize).and_then(|(_, choice)| choice.clone());
+
+        config
+            .borrow_mut()
+            .set_action_choice(&app.desktop_id, &action_id, action_choice.clone());
+        if let Err(err) = config.borrow().save() {
+            error!(
+                desktop_id = %app.desktop_id,
+                error = %err,
+                "failed to save assignment config"
+            );
+        }
+
+        let cfg = config.borrow();
+        let choice = cfg.get_choice(&app.desktop_id);
+        info!(
+            desktop_id = %app.desktop_id,
+            action = %action_id,
+            gpu_choice = ?action_choice.as_ref().map(GpuChoice::label),
+            "changing desktop action GPU assignment"
+        );
+        match apply_launcher_override(
+            &app,
+            &choice,
+            &cfg.action_choices(&app.desktop_id),
+            &cfg.steam_account_scope(&app.desktop_id),
+            gpu_for_choice(&gpus, &choice),
+            &gpus,
+            cfg.use_env_wrapper(),
+        ) {
+            Ok(()) => refresh_desktop_preview(&details, &app),
+            Err(err) => warn!(
+                desktop_id = %app.desktop_id,
+                action = %action_id,
+                error = %err,
+                "failed to apply desktop action GPU assignment"
+            ),
+        }
+    });
+
+    combo
+}
+
+fn set_steam_account_rows(
+    details: &AppDetailsWidgets,
+    app: &DesktopApp,
+    config: &Rc<RefCell<ConfigStore>>,
+    gpus: &[GpuInfo],
+) {
+    clear_listbox(&details.steam_accounts_list);
+
+    let accounts = if app.is_steam_game {
+        steam_accounts()
+    } else {
+        Vec::new()
+    };
+    details.steam_accounts_box.set_visible(accounts.len() > 1);
+    if accounts.len() < 2 {
+        return;
+    }
 
-    // Load the .desktop file contents into the preview.
+    let scope = config.borrow().steam_account_scope(&app.desktop_id);
+    let follow_recent = scope == SteamAccountScope::MostRecent;
+    let recent_label = accounts
+        .iter()
+        .find(|account| account.most_recent)
+        .map(|account| account.label())
+        .unwrap_or_else(|| "No login history; applies to every account".to_string());
+
+    let recent_switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(follow_recent)
+        .build();
+    let recent_row = adw::ActionRow::builder()
+        .title("Most recent login")
+        .subtitle(recent_label.as_str())
+        .build();
+    recent_row.add_suffix(&recent_switch);
+    recent_row.set_activatable_widget(Some(&recent_switch));
+    details.steam_accounts_list.append(&recent_row);
+
+    let checks = accounts
+        .iter()
+        .map(|account| {
+            let check = gtk::CheckButton::new();
+            check.set_active(match &scope {
+                SteamAccountScope::MostRecent => account.most_recent,
+                SteamAccountScope::All => true,
+                SteamAccountScope::Accounts(ids) => ids.contains(&account.account_id),
+            });
+            check.set_sensitive(!follow_recent);
+            let row = adw::ActionRow::builder()
+                .title(account.label().as_str())
+                .subtitle(format!("Account ID {}", account.account_id).as_str())
+                .build();
+            row.add_prefix(&check);
+            row.set_activatable_widget(Some(&check));
+            details.steam_accounts_list.append(&row);
+            (account.account_id, check)
+        })
+        .collect::<Vec<_>>();
+    let checks = Rc::new(checks);
+
+    let on_change = {
+        let app = app.clone();
+        let config = config.clone();
+        let gpus = gpus.to_vec();
+        let recent_switch = recent_switch.clone();
+        let checks = checks.clone();
+        Rc::new(move || {
+            let follow_recent = recent_switch.is_active();
+            for (_, check) in checks.iter() {
+                check.set_sensitive(!follow_recent);
+            }
+            let ids = checks
+                .iter()
+                .filter(|(_, check)| check.is_active())
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>();
+            let scope = if follow_recent {
+                SteamAccountScope::MostRecent
+            } else if ids.is_empty() {
+                // Keep the previous scope until at least one account is picked.
+                return;
+            } else if ids.len() == checks.len() {
+                SteamAccountScope::All
+            } else {
+                SteamAccountScope::Accounts(ids)
+            };
+            apply_steam_account_scope(&app, &config, &gpus, scope);
+        })
+    };
+
+    {
+        let on_change = on_change.clone();
+        recent_switch.connect_active_notify(move |_| on_change());
+    }
+    for (_, check) in checks.iter() {
+        let on_change = on_change.clone();
+        check.connect_toggled(move |_| on_change());
+    }
+}
+
+fn apply_steam_account_scope(
+    app: &DesktopApp,
+    config: &Rc<RefCell<ConfigStore>>,
+    gpus: &[GpuInfo],
+    scope: SteamAccountScope,
+) {
+    config
+        .borrow_mut()
+        .set_steam_account_scope(&app.desktop_id, scope.clone());
+    if let Err(err) = config.borrow().save() {
+        error!(
+            desktop_id = %app.desktop_id,
+            error = %err,
+            "failed to save Steam account scope"
+        );
+    }
+
+    if is_steam_running() {
+        info!(
+            desktop_id = %app.desktop_id,
+            "Steam is running; account scope change queued until Steam closes"
+        );
+        config.borrow_mut().queue_steam_change(&app.desktop_id);
+        if let Err(err) = config.borrow().save() {
+            error!(error = %err, "failed to save pending Steam changes");
+        }
+        return;
+    }
+
+    let cfg = config.borrow();
+    let choice = cfg.get_choice(&app.desktop_id);
+    info!(
+        desktop_id = %app.desktop_id,
+        accounts = ?scope,
+        "changing Steam account scope"
+    );
+    if let Err(err) = apply_launcher_override(
+        app,
+        &choice,
+        &cfg.action_choices(&app.desktop_id),
+        &scope,
+        gpu_for_choice(gpus, &choice),
+        gpus,
+        cfg.use_env_wrapper(),
+    ) {
+        warn!(
+            desktop_id = %app.desktop_id,
+            error = %err,
+            "failed to apply Steam account scope"
+        );
+    }
+}
+
+fn set_launch_option_rows(
+    details: &AppDetailsWidgets,
+    app: &DesktopApp,
+    config: &Rc<RefCell<ConfigStore>>,
+) {
+    clear_listbox(&details.launch_options_list);
+
+    let scope = config.borrow().steam_account_scope(&app.desktop_id);
+    let current = match (app.is_steam_game, app.steam_app_id.as_deref()) {
+        (true, Some(app_id)) => steam::launch_options(app_id, &scope),
+        _ => None,
+    };
+    details.launch_options_box.set_visible(current.is_some());
+    let (Some(app_id), Some(current)) = (app.steam_app_id.clone(), current) else {
+        return;
+    };
+
+    let effective_row = adw::ActionRow::builder()
+        .title("Effective")
+        .subtitle(if current.is_empty() { "None" } else { current.as_str() })
+        .build();
+    details.launch_options_list.append(&effective_row);
+
+    let steam_running = is_steam_running();
+    let entry = gtk::Entry::builder()
+        .text(steam::user_launch_options(&current).as_str())
+        .placeholder_text("gamemoderun %command%")
+        .hexpand(true)
+        .valign(gtk::Align::Center)
+        .sensitive(!steam_running)
+        .build();
+    let apply = gtk::Button::builder()
+        .label("Apply")
+        .valign(gtk::Align::Center)
+        .sensitive(!steam_running)
+        .build();
+    let user_row = adw::ActionRow::builder()
+        .title("Yours")
+        .subtitle(if steam_running {
+            "Close Steam to edit"
+        } else {
+            "Kaede keeps its GPU variables in front of %command%"
+        })
+        .build();
+    user_row.add_suffix(&entry);
+    user_row.add_suffix(&apply);
+    details.launch_options_list.append(&user_row);
+
+    let on_apply = {
+        let details = details.clone();
+        let app = app.clone();
+        let config = config.clone();
+        let entry = entry.clone();
+        let user_row = user_row.clone();
+        Rc::new(move || {
+            let user = entry.text().to_string();
+            info!(
+                desktop_id = %app.desktop_id,
+                launch_options = %user,
+                "editing Steam LaunchOptions"
+            );
+            match steam::set_user_launch_options(&app_id, &user, &scope) {
+                Ok(()) => set_launch_option_rows(&details, &app, &config),
+                Err(err) => {
+                    warn!(
+                        desktop_id = %app.desktop_id,
+                        error = %err,
+                        "failed to edit Steam LaunchOptions"
+                    );
+                    user_row.set_subtitle(&format!("{err:#}"));
+                }
+            }
+        })
+    };
+    {
+        let on_apply = on_apply.clone();
+        apply.connect_clicked(move |_| on_apply());
+    }
+    entry.connect_activate(move |_| on_apply());
+}
+
+/// Points the path row, editor button and preview at the override if present.
+fn refresh_desktop_preview(details: &AppDetailsWidgets, app: &DesktopApp) {
+    let override_path = user_launcher_path(&app.desktop_id);
+    let path = if override_path.exists() {
+        override_path
+    } else {
+        app.path.clone()
+    };
     let buffer = gtk::TextBuffer::new(None::<&gtk::TextTagTable>);
-    match std::fs::read_to_string(&override_path) {
+    match std::fs::read_to_string(&path) {
         Ok(contents) => buffer.set_text(&contents),
         Err(err) => buffer.set_text(&format!("Failed to read desktop file:\n{err}")),
     }
     details.desktop_preview.set_buffer(Some(&buffer));
+    let path = path.to_string_lossy().to_string();
+    details.path_row.set_subtitle(&path);
+    details.desktop_open_button.set_tooltip_text(Some(&path));
 }
 
 pub(crate) fn set_app_details_empty(details: &AppDetailsWidgets, gpus: &[GpuInfo]) {
@@ -89,12 +479,17 @@ pub(crate) fn set_app_details_empty(details: &AppDetailsWidgets, gpus: &[GpuInfo
     details
         .assignment_row
         .set_subtitle(&gpu_choice_label(gpus, &GpuChoice::Default));
+    details.assignment_row.remove_css_class("warning");
     details
         .source_row
         .set_subtitle("Native desktop entry");
     details.desktop_id_row.set_subtitle("-");
     details.path_row.set_subtitle("-");
     details.exec_row.set_subtitle("-");
+    clear_listbox(&details.actions_list);
+    details.actions_box.set_visible(false);
+    clear_listbox(&details.steam_accounts_list);
+    details.steam_accounts_box.set_visible(false);
     details.desktop_path_label.set_visible(false);
     details.desktop_path_label.set_text("Open in external editor");
     details.desktop_open_button.set_tooltip_text(None);
@@ -105,6 +500,16 @@ pub(crate) fn set_app_details_empty(details: &AppDetailsWidgets, gpus: &[GpuInfo
     details.desktop_preview.set_buffer(Some(&buffer));
 }
 
+/// The assignment label, noting when it waits for Steam to close.
+pub(crate) fn assignment_label(gpus: &[GpuInfo], choice: &GpuChoice, pending: bool) -> String {
+    let label = gpu_choice_label(gpus, choice);
+    if pending {
+        format!("{label} (pending until Steam closes)")
+    } else {
+        label
+    }
+}
+
 pub(crate) fn build_app_icon(icon: Option<&str>, pixel_size: i32) -> gtk::Image {
     let image = gtk::Image::new();
     apply_icon_to_image(&image, icon, pixel_size);
@@ -153,7 +558,7 @@ pub(crate) fn build_gpu_choices(gpus: &[GpuInfo]) -> Vec<(String, GpuChoice)> {
     for gpu in gpus {
         if let Some(idx) = gpu.dri_prime_index {
             let pretty = pretty_gpu_name(gpu);
-            choices.push((format!("{pretty} (#{idx})"), GpuChoice::Gpu(idx)));
+            choices.push((format!("{pretty} (#{idx})"), GpuChoice::Gpu(gpu.stable_id())));
         }
     }
 
@@ -163,14 +568,21 @@ pub(crate) fn build_gpu_choices(gpus: &[GpuInfo]) -> Vec<(String, GpuChoice)> {
 pub(crate) fn gpu_choice_label(gpus: &[GpuInfo], choice: &GpuChoice) -> String {
     match choice {
         GpuChoice::Default => format!("Default GPU ({})", default_gpu_hint(gpus)),
-        GpuChoice::Gpu(idx) => gpus
-            .iter()
-            .find(|g| g.dri_prime_index == Some(*idx))
-            .map(|gpu| format!("{} (#{idx})", pretty_gpu_name(gpu)))
-            .unwrap_or_else(|| format!("GPU {idx}")),
+        _ => match gpu_for_choice(gpus, choice) {
+            Some(gpu) => format!(
+                "{} (#{})",
+                pretty_gpu_name(gpu),
+                gpu.dri_prime_index.unwrap_or(0)
+            ),
+            None => format!("Assigned GPU missing ({})", choice.label()),
+        },
     }
 }
 
+pub(crate) fn is_gpu_missing(gpus: &[GpuInfo], choice: &GpuChoice) -> bool {
+    *choice != GpuChoice::Default && gpu_for_choice(gpus, choice).is_none()
+}
+
 fn default_gpu_hint(gpus: &[GpuInfo]) -> String {
     let name = gpus
         .iter()
@@ -252,12 +664,6 @@ fn pretty_gpu_name(gpu: &GpuInfo) -> String {
 }
 
 pub(crate) fn selected_gpu_for_choice(gpus: &[GpuInfo], choice: &GpuChoice) -> Option<GpuInfo> {
-    let GpuChoice::Gpu(idx) = choice else {
-        return None;
-    };
-
-    gpus.iter()
-        .find(|g| g.dri_prime_index == Some(*idx))
-        .cloned()
+    gpu_for_choice(gpus, choice).cloned()
 }
 
@@ -0,0 +1,157 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use adw::prelude::*;
+use tracing::error;
+
+use crate::config::ConfigStore;
+use crate::models::{DesktopApp, GpuInfo};
+use crate::reconcile::{adopt, check_assignments, reapply, DriftReport, DriftStatus};
+
+use super::details::build_app_icon;
+
+const RESPONSE_REAPPLY: u16 = 1;
+const RESPONSE_ADOPT: u16 = 2;
+
+pub(crate) fn show_drift_dialog(
+    window: &adw::ApplicationWindow,
+    apps: &[DesktopApp],
+    gpus: &[GpuInfo],
+    config: &Rc<RefCell<ConfigStore>>,
+    on_changed: impl Fn() + 'static,
+) {
+    let reports = check_assignments(&config.borrow(), apps, gpus)
+        .into_iter()
+        .filter(DriftReport::needs_attention)
+        .collect::<Vec<_>>();
+
+    if reports.is_empty() {
+        show_message(
+            window,
+            gtk::MessageType::Info,
+            "Assignments in sync",
+            "Every saved GPU assignment matches the launcher files on disk.",
+        );
+        return;
+    }
+
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .title("Assignment drift")
+        .default_width(560)
+        .default_height(420)
+        .build();
+
+    let wrapper = gtk::Box::new(gtk::Orientation::Vertical, 12);
+    wrapper.set_margin_top(18);
+    wrapper.set_margin_bottom(18);
+    wrapper.set_margin_start(18);
+    wrapper.set_margin_end(18);
+
+    let desc = gtk::Label::new(Some(
+        "These launchers no longer match the saved GPU assignment. Steam, Heroic, Flatpak or a package update may have rewritten them.",
+    ));
+    desc.set_wrap(true);
+    desc.set_wrap_mode(gtk::pango::WrapMode::WordChar);
+    desc.add_css_class("dim-label");
+    desc.set_xalign(0.0);
+    wrapper.append(&desc);
+
+    let list = gtk::ListBox::new();
+    list.add_css_class("boxed-list");
+    list.set_selection_mode(gtk::SelectionMode::None);
+    for report in &reports {
+        let row = adw::ActionRow::builder()
+            .title(report.app.name.as_str())
+            .subtitle(report.describe(gpus).as_str())
+            .build();
+        row.add_prefix(&build_app_icon(report.app.icon.as_deref(), 32));
+        if !matches!(report.status, DriftStatus::Drifted { .. }) {
+            row.add_css_class("warning");
+        }
+        list.append(&row);
+    }
+
+    let scrolled = gtk::ScrolledWindow::builder()
+        .child(&list)
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .vexpand(true)
+        .build();
+    wrapper.append(&scrolled);
+    dialog.content_area().append(&wrapper);
+
+    dialog.add_button("Close", gtk::ResponseType::Close);
+    dialog.add_button("Adopt on-disk state", gtk::ResponseType::Other(RESPONSE_ADOPT));
+    let reapply_btn = dialog.add_button("Re-apply all", gtk::ResponseType::Other(RESPONSE_REAPPLY));
+    reapply_btn.add_css_class("suggested-action");
+
+    let window = window.clone();
+    let gpus = gpus.to_vec();
+    let config = config.clone();
+    dialog.connect_response(move |d, response| {
+        let mut errors = Vec::new();
+        match response {
+            gtk::ResponseType::Other(RESPONSE_REAPPLY) => {
+                let use_env_wrapper = config.borrow().use_env_wrapper();
+                for report in &reports {
+                    if matches!(report.status, DriftStatus::GpuMissing) {
+                        continue;
+                    }
+                    if let Err(err) = reapply(report, &gpus, use_env_wrapper) {
+                        errors.push(format!("{}: {err:#}", report.app.name));
+                    }
+                }
+            }
+            gtk::ResponseType::Other(RESPONSE_ADOPT) => {
+                let mut cfg = config.borrow_mut();
+                for report in &reports {
+                    if !matches!(report.status, DriftStatus::Drifted { .. }) {
+                        continue;
+                    }
+                    if let Err(err) = adopt(report, &gpus, &mut cfg) {
+                        errors.push(format!("{}: {err:#}", report.app.name));
+                    }
+                }
+                if let Err(err) = cfg.save() {
+                    errors.push(format!("failed to save config: {err:#}"));
+                }
+            }
+            _ => {
+                d.close();
+                return;
+            }
+        }
+
+        d.close();
+        on_changed();
+        if !errors.is_empty() {
+            error!(errors = ?errors, "failed to reconcile some assignments");
+            show_message(
+                &window,
+                gtk::MessageType::Error,
+                "Some assignments could not be reconciled",
+                &errors.join("\n"),
+            );
+        }
+    });
+    dialog.present();
+}
+
+fn show_message(
+    window: &adw::ApplicationWindow,
+    message_type: gtk::MessageType,
+    text: &str,
+    secondary: &str,
+) {
+    let dlg = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(message_type)
+        .text(text)
+        .secondary_text(secondary)
+        .build();
+    dlg.add_button("OK", gtk::ResponseType::Ok);
+    dlg.connect_response(|d, _| d.close());
+    dlg.present();
+}
@@ -2,6 +2,7 @@ use crate::config::ConfigStore;
 use crate::desktop::scan_desktop_entries;
 use crate::gpu::detect_gpus;
 use crate::models::{DesktopApp, GpuInfo};
+use crate::reconcile::{apply_pending_steam_changes, regenerate_stale_overrides};
 use adw::prelude::*;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -15,16 +16,22 @@ const APP_GITHUB: &str = "https://github.com/SterTheStar/kaede";
 const APP_LICENSE: &str = "GNU GPL-3.0";
 // Use the installed themed icon name so it works from the packaged build.
 const APP_ICON_PATH: &str = "com.kaede.gpu-manager";
+const STEAM_WATCH_INTERVAL_SECS: u32 = 5;
 
 mod about;
 mod app_list;
+pub(crate) mod backups;
 mod details;
+mod drift;
+mod nvidia_plan;
+mod nvidia_progress;
 mod settings;
 mod util;
 
 use self::about::show_about_dialog;
-use self::app_list::rebuild_app_list;
+use self::app_list::{rebuild_app_list, SourceFilter};
 use self::details::{set_app_details, set_app_details_empty, AppDetailsWidgets};
+use self::drift::show_drift_dialog;
 use self::settings::build_settings_widget;
 use self::util::{set_details_panel_visible, widget_is_descendant_of};
 
@@ -37,9 +44,10 @@ struct UiState {
 pub fn build_ui(app: &adw::Application) {
     let _ = adw::init();
 
+    let config = Rc::new(RefCell::new(ConfigStore::load()));
     let state = Rc::new(RefCell::new(UiState {
         gpus: detect_gpus(),
-        apps: scan_desktop_entries(),
+        apps: scan_desktop_entries(config.borrow().extra_scan_dirs()),
     }));
 
     // Add CSS for the update notification dot
@@ -61,11 +69,17 @@ pub fn build_ui(app: &adw::Application) {
         );
     }
 
-    let config = Rc::new(RefCell::new(ConfigStore::load()));
+    if let Err(err) = config
+        .borrow_mut()
+        .migrate_gpu_assignments(&state.borrow().gpus)
+    {
+        warn!(error = %err, "failed to migrate saved GPU assignments");
+    }
+    regenerate_outdated_launchers(&mut state.borrow_mut(), &config.borrow());
     let visible_apps: Rc<RefCell<Vec<DesktopApp>>> = Rc::new(RefCell::new(Vec::new()));
     let selected_app_id: Rc<RefCell<Option<String>>> = Rc::new(RefCell::new(None));
-    // (steam, heroic, flatpak, native) — session-level filter, independent of settings
-    let ui_filter: Rc<RefCell<(bool, bool, bool, bool)>> = Rc::new(RefCell::new((true, true, true, true)));
+    // (steam, heroic, lutris, flatpak, native) — session-level filter, independent of settings
+    let ui_filter: Rc<RefCell<SourceFilter>> = Rc::new(RefCell::new((true, true, true, true, true)));
     let filter_suspended: Rc<std::cell::Cell<bool>> = Rc::new(std::cell::Cell::new(false));
 
     let window = adw::ApplicationWindow::builder()
@@ -91,6 +105,10 @@ pub fn build_ui(app: &adw::Application) {
         .icon_name("view-refresh-symbolic")
         .tooltip_text("Refresh GPU and app scan")
         .build();
+    let drift_btn = gtk::Button::builder()
+        .icon_name("emblem-synchronizing-symbolic")
+        .tooltip_text("Check assignments against launcher files")
+        .build();
     let settings_btn = gtk::Button::builder()
         .icon_name("emblem-system-symbolic")
         .tooltip_text("NVIDIA graphics mode")
@@ -120,6 +138,7 @@ pub fn build_ui(app: &adw::Application) {
     header.pack_end(&settings_btn);
     header.pack_end(&about_btn);
     header.pack_end(&refresh_btn);
+    header.pack_end(&drift_btn);
 
     let search_btn = gtk::Button::builder()
         .icon_name("system-search-symbolic")
@@ -148,6 +167,8 @@ pub fn build_ui(app: &adw::Application) {
     steam_check.set_active(true);
     let heroic_check = gtk::CheckButton::with_label("Heroic games");
     heroic_check.set_active(true);
+    let lutris_check = gtk::CheckButton::with_label("Lutris games");
+    lutris_check.set_active(true);
     let flatpak_check = gtk::CheckButton::with_label("Flatpak apps");
     flatpak_check.set_active(true);
     let native_check = gtk::CheckButton::with_label("Native / Desktop");
@@ -163,6 +184,7 @@ pub fn build_ui(app: &adw::Application) {
 
     filter_popover_box.append(&steam_check);
     filter_popover_box.append(&heroic_check);
+    filter_popover_box.append(&lutris_check);
     filter_popover_box.append(&flatpak_check);
     filter_popover_box.append(&native_check);
     filter_popover_box.append(&filter_sep);
@@ -288,6 +310,52 @@ pub fn build_ui(app: &adw::Application) {
     details_list.append(&details_exec);
 
     summary_card.append(&details_list);
+
+    // Per-action GPU overrides; only shown for apps that declare desktop actions.
+    let details_actions_box = gtk::Box::new(gtk::Orientation::Vertical, 6);
+    details_actions_box.set_margin_top(6);
+    details_actions_box.set_visible(false);
+    let details_actions_title = gtk::Label::new(Some("Desktop actions"));
+    details_actions_title.set_xalign(0.0);
+    details_actions_title.add_css_class("heading");
+    details_actions_title.set_margin_start(4);
+    details_actions_box.append(&details_actions_title);
+    let details_actions = gtk::ListBox::new();
+    details_actions.add_css_class("boxed-list");
+    details_actions.set_selection_mode(gtk::SelectionMode::None);
+    details_actions_box.append(&details_actions);
+    summary_card.append(&details_actions_box);
+
+    // Steam account scope; only shown for Steam games when several accounts exist.
+    let details_steam_accounts_box = gtk::Box::new(gtk::Orientation::Vertical, 6);
+    details_steam_accounts_box.set_margin_top(6);
+    details_steam_accounts_box.set_visible(false);
+    let details_steam_accounts_title = gtk::Label::new(Some("Steam accounts"));
+    details_steam_accounts_title.set_xalign(0.0);
+    details_steam_accounts_title.add_css_class("heading");
+    details_steam_accounts_title.set_margin_start(4);
+    details_steam_accounts_box.append(&details_steam_accounts_title);
+    let details_steam_accounts = gtk::ListBox::new();
+    details_steam_accounts.add_css_class("boxed-list");
+    details_steam_accounts.set_selection_mode(gtk::SelectionMode::None);
+    details_steam_accounts_box.append(&details_steam_accounts);
+    summary_card.append(&details_steam_accounts_box);
+
+    // Steam LaunchOptions; only shown for Steam games.
+    let details_launch_options_box = gtk::Box::new(gtk::Orientation::Vertical, 6);
+    details_launch_options_box.set_margin_top(6);
+    details_launch_options_box.set_visible(false);
+    let details_launch_options_title = gtk::Label::new(Some("Launch options"));
+    details_launch_options_title.set_xalign(0.0);
+    details_launch_options_title.add_css_class("heading");
+    details_launch_options_title.set_margin_start(4);
+    details_launch_options_box.append(&details_launch_options_title);
+    let details_launch_options = gtk::ListBox::new();
+    details_launch_options.add_css_class("boxed-list");
+    details_launch_options.set_selection_mode(gtk::SelectionMode::None);
+    details_launch_options_box.append(&details_launch_options);
+    summary_card.append(&details_launch_options_box);
+
     details_outer.append(&summary_card);
 
     // Separate card for the .desktop file preview that takes the remaining height.
@@ -375,6 +443,12 @@ pub fn build_ui(app: &adw::Application) {
         desktop_id_row: details_id,
         path_row: details_path,
         exec_row: details_exec,
+        actions_box: details_actions_box,
+        actions_list: details_actions,
+        steam_accounts_box: details_steam_accounts_box,
+        steam_accounts_list: details_steam_accounts,
+        launch_options_box: details_launch_options_box,
+        launch_options_list: details_launch_options,
         desktop_path_label: desktop_path_label.clone(),
         desktop_open_button: desktop_open_button.clone(),
         desktop_preview: desktop_preview.clone(),
@@ -512,10 +586,9 @@ pub fn build_ui(app: &adw::Application) {
 
             let app = visible_apps.borrow().get(idx as usize).cloned();
             if let Some(app) = app {
-                let choice = config.borrow().get_choice(&app.desktop_id);
                 *selected_app_id.borrow_mut() = Some(app.desktop_id.clone());
                 let gpus = state.borrow().gpus.clone();
-                set_app_details(&details_widgets, &app, &choice, &gpus);
+                set_app_details(&details_widgets, &app, &config, &gpus);
                 set_details_panel_visible(&content, &details_revealer, &apps_scrolled, true);
             } else {
                 *selected_app_id.borrow_mut() = None;
@@ -533,6 +606,7 @@ pub fn build_ui(app: &adw::Application) {
         let search = search.clone();
         let search_btn_widget = search_btn.clone().upcast::<gtk::Widget>();
         let refresh_btn_widget = refresh_btn.clone().upcast::<gtk::Widget>();
+        let drift_btn_widget = drift_btn.clone().upcast::<gtk::Widget>();
         let settings_btn_widget = settings_btn.clone().upcast::<gtk::Widget>();
         let about_btn_widget = about_btn.clone().upcast::<gtk::Widget>();
         let filter_menu_btn_widget = filter_menu_btn.clone().upcast::<gtk::Widget>();
@@ -559,7 +633,8 @@ pub fn build_ui(app: &adw::Application) {
             let in_header = widget_is_descendant_of(&picked, &header_widget);
             let in_search_btn = widget_is_descendant_of(&picked, &search_btn_widget);
             let in_search_entry = widget_is_descendant_of(&picked, &search_widget);
-            let in_refresh = widget_is_descendant_of(&picked, &refresh_btn_widget);
+            let in_refresh = widget_is_descendant_of(&picked, &refresh_btn_widget)
+                || widget_is_descendant_of(&picked, &drift_btn_widget);
             let in_settings = widget_is_descendant_of(&picked, &settings_btn_widget);
             let in_about = widget_is_descendant_of(&picked, &about_btn_widget);
             let in_filter = widget_is_descendant_of(&picked, &filter_menu_btn_widget);
@@ -629,8 +704,9 @@ pub fn build_ui(app: &adw::Application) {
     }
     on_check_filter!(steam_check, 0);
     on_check_filter!(heroic_check, 1);
-    on_check_filter!(flatpak_check, 2);
-    on_check_filter!(native_check, 3);
+    on_check_filter!(lutris_check, 2);
+    on_check_filter!(flatpak_check, 3);
+    on_check_filter!(native_check, 4);
 
     // Clear filters: reset all checks + rebuild once
     {
@@ -649,9 +725,10 @@ pub fn build_ui(app: &adw::Application) {
             filter_suspended.set(true);
             steam_check.set_active(true);
             heroic_check.set_active(true);
+            lutris_check.set_active(true);
             flatpak_check.set_active(true);
             native_check.set_active(true);
-            *ui_filter.borrow_mut() = (true, true, true, true);
+            *ui_filter.borrow_mut() = (true, true, true, true, true);
             filter_suspended.set(false);
             filter_popover.popdown();
             let text = search.text().to_string();
@@ -673,6 +750,7 @@ pub fn build_ui(app: &adw::Application) {
         let back_btn = back_btn.clone();
         let settings_btn_ref = settings_btn.clone();
         let refresh_btn_ref = refresh_btn.clone();
+        let drift_btn_ref = drift_btn.clone();
         let about_btn_ref = about_btn.clone();
         let search_slot = search_slot.clone();
         let title = title.clone();
@@ -689,6 +767,7 @@ pub fn build_ui(app: &adw::Application) {
             back_btn.set_visible(true);
             settings_btn_ref.set_visible(false);
             refresh_btn_ref.set_visible(false);
+            drift_btn_ref.set_visible(false);
             about_btn_ref.set_visible(false);
             search_slot.set_visible(false);
             title.set_title("Settings");
@@ -700,6 +779,7 @@ pub fn build_ui(app: &adw::Application) {
         let back_btn_ref = back_btn.clone();
         let settings_btn = settings_btn.clone();
         let refresh_btn = refresh_btn.clone();
+        let drift_btn = drift_btn.clone();
         let about_btn = about_btn.clone();
         let search_slot = search_slot.clone();
         let title = title.clone();
@@ -710,9 +790,45 @@ pub fn build_ui(app: &adw::Application) {
             back_btn_ref.set_visible(false);
             settings_btn.set_visible(true);
             refresh_btn.set_visible(true);
+            drift_btn.set_visible(true);
             about_btn.set_visible(true);
             search_slot.set_visible(true);
             title.set_title("Kaede");
+            // Settings may have changed which apps are listed.
+            refresh_btn.emit_clicked();
+        });
+    }
+
+    {
+        let window = window.clone();
+        let state = state.clone();
+        let apps_box = apps_box.clone();
+        let visible_apps = visible_apps.clone();
+        let search = search.clone();
+        let config = config.clone();
+        let details_widgets = details_widgets.clone();
+        let selected_app_id = selected_app_id.clone();
+        let ui_filter = ui_filter.clone();
+        drift_btn.connect_clicked(move |_| {
+            let data = state.borrow();
+            let apps_box = apps_box.clone();
+            let window_c = window.clone();
+            let state = state.clone();
+            let config_c = config.clone();
+            let visible_apps = visible_apps.clone();
+            let search = search.clone();
+            let details_widgets = details_widgets.clone();
+            let selected_app_id = selected_app_id.clone();
+            let ui_filter = ui_filter.clone();
+            show_drift_dialog(&window, &data.apps, &data.gpus, &config, move || {
+                let text = search.text().to_string();
+                let data = state.borrow();
+                rebuild_app_list(
+                    &apps_box, &window_c, &data.apps, &data.gpus,
+                    &config_c, &visible_apps, &text, &details_widgets, &selected_app_id,
+                    *ui_filter.borrow(),
+                );
+            });
         });
     }
 
@@ -735,7 +851,11 @@ pub fn build_ui(app: &adw::Application) {
             {
                 let mut s = state.borrow_mut();
                 s.gpus = detect_gpus();
-                s.apps = scan_desktop_entries();
+                s.apps = scan_desktop_entries(config.borrow().extra_scan_dirs());
+                if let Err(err) = config.borrow_mut().migrate_gpu_assignments(&s.gpus) {
+                    warn!(error = %err, "failed to migrate saved GPU assignments");
+                }
+                regenerate_outdated_launchers(&mut s, &config.borrow());
             }
 
             let current_filter = search.text().to_string();
@@ -755,8 +875,7 @@ pub fn build_ui(app: &adw::Application) {
 
             if let Some(selected) = selected_app_id.borrow().clone() {
                 if let Some(app) = data.apps.iter().find(|a| a.desktop_id == selected).cloned() {
-                    let choice = config.borrow().get_choice(&app.desktop_id);
-                    set_app_details(&details_widgets, &app, &choice, &data.gpus);
+                    set_app_details(&details_widgets, &app, &config, &data.gpus);
                     set_details_panel_visible(&content, &details_revealer, &apps_scrolled, true);
                 } else {
                     set_app_details_empty(&details_widgets, &data.gpus);
@@ -772,6 +891,27 @@ pub fn build_ui(app: &adw::Application) {
     window.set_content(Some(&root));
     window.present();
 
+    // Write Steam assignments queued while Steam was running once it exits.
+    {
+        let state = state.clone();
+        let config = config.clone();
+        let refresh_btn = refresh_btn.clone();
+        glib::timeout_add_seconds_local(STEAM_WATCH_INTERVAL_SECS, move || {
+            let applied = {
+                let data = state.borrow();
+                apply_pending_steam_changes(&mut config.borrow_mut(), &data.apps, &data.gpus)
+            };
+            if let Some(applied) = applied {
+                info!(applied = applied, "Steam closed; queued Steam changes written");
+                if let Err(err) = config.borrow().save() {
+                    warn!(error = %err, "failed to save config after applying queued Steam changes");
+                }
+                refresh_btn.emit_clicked();
+            }
+            glib::ControlFlow::Continue
+        });
+    }
+
     // Startup update check
     if config.borrow().check_updates_at_startup() {
         info!("Starting background update check...");
@@ -816,3 +956,12 @@ pub fn build_ui(app: &adw::Application) {
         });
     }
 }
+
+fn regenerate_outdated_launchers(state: &mut UiState, config: &ConfigStore) {
+    if !config.regenerate_stale_overrides() {
+        return;
+    }
+    if regenerate_stale_overrides(config, &state.apps, &state.gpus) > 0 {
+        state.apps = scan_desktop_entries(config.extra_scan_dirs());
+    }
+}
@@ -0,0 +1,99 @@
+use adw::prelude::*;
+
+use crate::nvidia::{LineKind, SwitchPlan};
+
+/// Lists every file the switch writes or removes as a diff, plus the commands
+/// it runs, and calls `on_confirm` only when the user accepts.
+pub(crate) fn show_switch_plan_dialog(
+    window: &adw::ApplicationWindow,
+    plan: &SwitchPlan,
+    on_confirm: impl Fn() + 'static,
+) {
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .title("Review NVIDIA changes")
+        .default_width(680)
+        .default_height(520)
+        .build();
+
+    let wrapper = gtk::Box::new(gtk::Orientation::Vertical, 12);
+    wrapper.set_margin_top(18);
+    wrapper.set_margin_bottom(18);
+    wrapper.set_margin_start(18);
+    wrapper.set_margin_end(18);
+
+    let desc = gtk::Label::new(Some(
+        "Switching writes and removes these files, then runs the commands at the end. A reboot is needed afterwards.",
+    ));
+    desc.set_wrap(true);
+    desc.set_wrap_mode(gtk::pango::WrapMode::WordChar);
+    desc.add_css_class("dim-label");
+    desc.set_xalign(0.0);
+    wrapper.append(&desc);
+
+    let heading = gtk::TextTag::builder().weight(700).build();
+    let added = gtk::TextTag::builder().foreground("#26a269").build();
+    let removed = gtk::TextTag::builder().foreground("#c01c28").build();
+    let command = gtk::TextTag::builder().style(gtk::pango::Style::Italic).build();
+    let note = gtk::TextTag::builder().foreground("#77767b").build();
+    let tags = gtk::TextTagTable::new();
+    for tag in [&heading, &added, &removed, &command, &note] {
+        tags.add(tag);
+    }
+    let buffer = gtk::TextBuffer::new(Some(&tags));
+
+    for (index, (kind, line)) in plan.lines().into_iter().enumerate() {
+        let (prefix, tag) = match kind {
+            LineKind::Heading => ("", Some(&heading)),
+            LineKind::Added => ("+ ", Some(&added)),
+            LineKind::Removed => ("- ", Some(&removed)),
+            LineKind::Unchanged => ("  ", None),
+            LineKind::Command => ("$ ", Some(&command)),
+            LineKind::Note => ("", Some(&note)),
+        };
+        let mut text = String::new();
+        if index > 0 && matches!(kind, LineKind::Heading | LineKind::Command) {
+            text.push('\n');
+        }
+        text.push_str(prefix);
+        text.push_str(&line);
+        text.push('\n');
+
+        let mut end = buffer.end_iter();
+        match tag {
+            Some(tag) => buffer.insert_with_tags(&mut end, &text, &[tag]),
+            None => buffer.insert(&mut end, &text),
+        }
+    }
+
+    let view = gtk::TextView::builder()
+        .buffer(&buffer)
+        .editable(false)
+        .cursor_visible(false)
+        .monospace(true)
+        .top_margin(8)
+        .bottom_margin(8)
+        .left_margin(8)
+        .right_margin(8)
+        .build();
+    let scrolled = gtk::ScrolledWindow::builder()
+        .child(&view)
+        .vexpand(true)
+        .build();
+    scrolled.add_css_class("card");
+    wrapper.append(&scrolled);
+    dialog.content_area().append(&wrapper);
+
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    let switch_btn = dialog.add_button("Switch", gtk::ResponseType::Accept);
+    switch_btn.add_css_class("suggested-action");
+
+    dialog.connect_response(move |d, response| {
+        d.close();
+        if response == gtk::ResponseType::Accept {
+            on_confirm();
+        }
+    });
+    dialog.present();
+}
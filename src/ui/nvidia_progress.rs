@@ -0,0 +1,127 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use adw::prelude::*;
+
+enum Progress {
+    Line(String),
+    Done(Result<(), String>),
+}
+
+/// Runs `task` on a worker thread and shows every line it reports until it
+/// finishes. The dialog can't be closed before that; `on_done` then gets the
+/// result on the main thread.
+pub(crate) fn run_with_progress_dialog<F>(
+    window: &adw::ApplicationWindow,
+    title: &str,
+    success: &str,
+    task: F,
+    on_done: impl Fn(&Result<(), String>) + 'static,
+) where
+    F: FnOnce(&mut dyn FnMut(&str)) -> Result<(), String> + Send + 'static,
+{
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .title(title)
+        .default_width(680)
+        .default_height(420)
+        .build();
+
+    let wrapper = gtk::Box::new(gtk::Orientation::Vertical, 12);
+    wrapper.set_margin_top(18);
+    wrapper.set_margin_bottom(18);
+    wrapper.set_margin_start(18);
+    wrapper.set_margin_end(18);
+
+    let header = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+    let spinner = gtk::Spinner::new();
+    spinner.start();
+    let status = gtk::Label::new(Some("Waiting for authentication and running the changes…"));
+    status.set_wrap(true);
+    status.set_wrap_mode(gtk::pango::WrapMode::WordChar);
+    status.set_xalign(0.0);
+    status.set_hexpand(true);
+    header.append(&spinner);
+    header.append(&status);
+    wrapper.append(&header);
+
+    let buffer = gtk::TextBuffer::new(None);
+    let end = buffer.create_mark(None, &buffer.end_iter(), false);
+    let view = gtk::TextView::builder()
+        .buffer(&buffer)
+        .editable(false)
+        .cursor_visible(false)
+        .monospace(true)
+        .top_margin(8)
+        .bottom_margin(8)
+        .left_margin(8)
+        .right_margin(8)
+        .build();
+    let scrolled = gtk::ScrolledWindow::builder()
+        .child(&view)
+        .vexpand(true)
+        .build();
+    scrolled.add_css_class("card");
+    wrapper.append(&scrolled);
+    dialog.content_area().append(&wrapper);
+
+    dialog.add_button("Close", gtk::ResponseType::Close);
+    dialog.set_response_sensitive(gtk::ResponseType::Close, false);
+    dialog.connect_response(|d, _| d.close());
+
+    let running = Rc::new(Cell::new(true));
+    {
+        let running = running.clone();
+        dialog.connect_close_request(move |_| {
+            if running.get() {
+                glib::Propagation::Stop
+            } else {
+                glib::Propagation::Proceed
+            }
+        });
+    }
+    dialog.present();
+
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let line_tx = tx.clone();
+        let result = task(&mut |line| {
+            let _ = line_tx.send(Progress::Line(line.to_string()));
+        });
+        let _ = tx.send(Progress::Done(result));
+    });
+
+    let success = success.to_string();
+    glib::timeout_add_local(Duration::from_millis(100), move || loop {
+        let result = match rx.try_recv() {
+            Ok(Progress::Line(line)) => {
+                buffer.insert(&mut buffer.end_iter(), &format!("{line}\n"));
+                view.scroll_mark_onscreen(&end);
+                continue;
+            }
+            Ok(Progress::Done(result)) => result,
+            Err(mpsc::TryRecvError::Empty) => return glib::ControlFlow::Continue,
+            // The worker panicked before reporting a result.
+            Err(mpsc::TryRecvError::Disconnected) => {
+                Err("the operation stopped unexpectedly".to_string())
+            }
+        };
+
+        spinner.stop();
+        spinner.set_visible(false);
+        match &result {
+            Ok(()) => status.set_text(&success),
+            Err(err) => {
+                status.set_text(&format!("Failed: {err}"));
+                status.add_css_class("error");
+            }
+        }
+        running.set(false);
+        dialog.set_response_sensitive(gtk::ResponseType::Close, true);
+        on_done(&result);
+        return glib::ControlFlow::Break;
+    });
+}
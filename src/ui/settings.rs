@@ -1,11 +1,21 @@
 use adw::prelude::*;
 use std::cell::RefCell;
+use std::path::PathBuf;
 use std::rc::Rc;
-use tracing::error;
+use tracing::{error, info};
 
 use crate::config::ConfigStore;
 use crate::models::GpuInfo;
-use crate::nvidia::{get_current_mode, switch_graphics_mode, DisplayManager, GraphicsMode, NvidiaSwitchConfig, reset_all, reset_sddm};
+use crate::nvidia::helper::{reset_all, reset_sddm, set_initramfs_command, switch_graphics_mode};
+use crate::nvidia::{
+    custom_command, detect_backend, detect_display_manager, get_current_mode, get_status,
+    plan_graphics_mode, validate_custom_command, DetectedDisplayManager, DisplayManager,
+    GraphicsMode, NvidiaSwitchConfig,
+};
+
+use super::backups::show_backups_dialog;
+use super::nvidia_plan::show_switch_plan_dialog;
+use super::nvidia_progress::run_with_progress_dialog;
 
 fn has_nvidia_gpu(gpus: &[GpuInfo]) -> bool {
     gpus.iter().any(|g| {
@@ -76,6 +86,16 @@ pub(crate) fn build_settings_widget(
     show_heroic_row.set_activatable_widget(Some(&show_heroic_switch));
     app_list.append(&show_heroic_row);
 
+    let show_lutris_switch = gtk::Switch::builder().valign(gtk::Align::Center).build();
+    show_lutris_switch.set_active(config.borrow().show_lutris_apps());
+    let show_lutris_row = adw::ActionRow::builder()
+        .title("Show Lutris games")
+        .subtitle("Include games configured in Lutris")
+        .build();
+    show_lutris_row.add_suffix(&show_lutris_switch);
+    show_lutris_row.set_activatable_widget(Some(&show_lutris_switch));
+    app_list.append(&show_lutris_row);
+
     let show_flatpak_switch = gtk::Switch::builder().valign(gtk::Align::Center).build();
     show_flatpak_switch.set_active(config.borrow().show_flatpak_apps());
     let show_flatpak_row = adw::ActionRow::builder()
@@ -96,6 +116,16 @@ pub(crate) fn build_settings_widget(
     use_env_row.set_activatable_widget(Some(&use_env_switch));
     app_list.append(&use_env_row);
 
+    let regenerate_switch = gtk::Switch::builder().valign(gtk::Align::Center).build();
+    regenerate_switch.set_active(config.borrow().regenerate_stale_overrides());
+    let regenerate_row = adw::ActionRow::builder()
+        .title("Regenerate outdated launchers")
+        .subtitle("Rebuild Kaede launchers when the original .desktop file is updated by a package")
+        .build();
+    regenerate_row.add_suffix(&regenerate_switch);
+    regenerate_row.set_activatable_widget(Some(&regenerate_switch));
+    app_list.append(&regenerate_row);
+
     let check_updates_switch = gtk::Switch::builder().valign(gtk::Align::Center).build();
     check_updates_switch.set_active(config.borrow().check_updates_at_startup());
     let check_updates_row = adw::ActionRow::builder()
@@ -119,11 +149,43 @@ pub(crate) fn build_settings_widget(
 
     general_page.append(&app_list);
 
+    let folders_desc = gtk::Label::new(Some(
+        "Extra folders with .desktop files, scanned in addition to the XDG data directories.",
+    ));
+    folders_desc.set_wrap(true);
+    folders_desc.set_wrap_mode(gtk::pango::WrapMode::WordChar);
+    folders_desc.add_css_class("dim-label");
+    folders_desc.set_xalign(0.0);
+    folders_desc.set_margin_top(12);
+    general_page.append(&folders_desc);
+
+    let folders_list = gtk::ListBox::new();
+    folders_list.add_css_class("boxed-list");
+    folders_list.set_selection_mode(gtk::SelectionMode::None);
+    general_page.append(&folders_list);
+    let extra_dirs = Rc::new(RefCell::new(config.borrow().extra_scan_dirs().to_vec()));
+
     let reset_cfg_list = gtk::ListBox::new();
     reset_cfg_list.add_css_class("boxed-list");
     reset_cfg_list.set_selection_mode(gtk::SelectionMode::None);
     reset_cfg_list.append(&reset_cfg_row);
 
+    let backups_btn = gtk::Button::builder()
+        .label("Show")
+        .valign(gtk::Align::Center)
+        .build();
+    let backups_row = adw::ActionRow::builder()
+        .title("Backups")
+        .subtitle("Restore files Kaede changed, one at a time or a whole change at once")
+        .build();
+    backups_row.add_suffix(&backups_btn);
+    backups_row.set_activatable_widget(Some(&backups_btn));
+    reset_cfg_list.append(&backups_row);
+    {
+        let window = window.clone();
+        backups_btn.connect_clicked(move |_| show_backups_dialog(&window));
+    }
+
     let reset_desc = gtk::Label::new(Some("Maintenance and recovery options. Use with caution."));
     reset_desc.add_css_class("dim-label");
     reset_desc.set_xalign(0.0);
@@ -243,6 +305,14 @@ pub(crate) fn build_settings_widget(
     nvidia_desc.set_xalign(0.0);
     nvidia_page.append(&nvidia_desc);
 
+    let status_list = gtk::ListBox::new();
+    status_list.add_css_class("boxed-list");
+    status_list.set_selection_mode(gtk::SelectionMode::None);
+    if has_nvidia {
+        fill_status_rows(&status_list);
+        nvidia_page.append(&status_list);
+    }
+
     let list = gtk::ListBox::new();
     list.add_css_class("boxed-list");
     list.set_selection_mode(gtk::SelectionMode::None);
@@ -320,19 +390,97 @@ pub(crate) fn build_settings_widget(
     list.append(&nvidia_current_row);
 
     // Display Manager selection
-    let dm_dropdown =
-        gtk::DropDown::from_strings(&["Auto-detect", "GDM", "GDM3", "SDDM", "LightDM"]);
+    let dm_names = std::iter::once("Auto-detect")
+        .chain(DisplayManager::ALL.iter().map(|dm| dm.label()))
+        .collect::<Vec<_>>();
+    let dm_dropdown = gtk::DropDown::from_strings(&dm_names);
     dm_dropdown.set_valign(gtk::Align::Center);
     dm_dropdown.set_vexpand(false);
     dm_dropdown.set_selected(0);
+    let detected_dm = detect_display_manager();
+    let dm_subtitle = match &detected_dm {
+        DetectedDisplayManager::Supported(dm) => format!(
+            "NVIDIA mode: used for login screen configuration (detected {})",
+            dm.label()
+        ),
+        DetectedDisplayManager::Unsupported(name) => format!(
+            "{name} is not supported; NVIDIA mode leaves the login screen alone unless one is picked here"
+        ),
+        DetectedDisplayManager::NotFound => {
+            "No display manager detected; NVIDIA mode leaves the login screen alone unless one is picked here".to_string()
+        }
+    };
     let dm_row = adw::ActionRow::builder()
         .title("Display Manager")
-        .subtitle("NVIDIA mode: used for login screen configuration")
+        .subtitle(dm_subtitle.as_str())
         .build();
+    if !matches!(detected_dm, DetectedDisplayManager::Supported(_)) {
+        let icon = gtk::Image::from_icon_name("dialog-warning-symbolic");
+        icon.add_css_class("warning");
+        dm_row.add_prefix(&icon);
+    }
     dm_row.add_suffix(&dm_dropdown);
     dm_row.set_activatable_widget(Some(&dm_dropdown));
     list.append(&dm_row);
 
+    // Initramfs rebuild command, kept by the helper outside the user's config
+    let initramfs_entry = gtk::Entry::builder()
+        .placeholder_text("Auto-detect")
+        .text(custom_command().unwrap_or_default())
+        .valign(gtk::Align::Center)
+        .width_chars(24)
+        .build();
+    let initramfs_save_btn = gtk::Button::builder()
+        .label("Save")
+        .valign(gtk::Align::Center)
+        .sensitive(false)
+        .build();
+    let initramfs_subtitle = match detect_backend() {
+        Some(backend) => format!("Leave empty to use the detected tool ({})", backend.label()),
+        None => "No initramfs tool detected; enter the command that rebuilds it".to_string(),
+    };
+    let initramfs_row = adw::ActionRow::builder()
+        .title("Initramfs command")
+        .subtitle(initramfs_subtitle.as_str())
+        .build();
+    initramfs_row.add_suffix(&initramfs_entry);
+    initramfs_row.add_suffix(&initramfs_save_btn);
+    list.append(&initramfs_row);
+    {
+        let save_btn = initramfs_save_btn.clone();
+        let row = initramfs_row.clone();
+        initramfs_entry.connect_changed(move |entry| {
+            entry.remove_css_class("error");
+            row.set_subtitle(&initramfs_subtitle);
+            save_btn.set_sensitive(true);
+        });
+    }
+    {
+        let window = window.clone();
+        let entry = initramfs_entry.clone();
+        let row = initramfs_row.clone();
+        initramfs_save_btn.connect_clicked(move |btn| {
+            let text = entry.text().trim().to_string();
+            let command = (!text.is_empty()).then_some(text);
+            if let Some(Err(err)) = command.as_deref().map(validate_custom_command) {
+                entry.add_css_class("error");
+                row.set_subtitle(&err);
+                return;
+            }
+            let btn = btn.clone();
+            run_with_progress_dialog(
+                &window,
+                "Setting the initramfs command",
+                "The initramfs is rebuilt with this command from the next mode switch on.",
+                move |output| set_initramfs_command(command.as_deref(), output),
+                move |result| match result {
+                    Ok(()) => btn.set_sensitive(false),
+                    Err(err) => error!(%err, "failed to set the initramfs command"),
+                },
+            );
+        });
+    }
+
     let reset_btn = gtk::Button::with_label("Full reset");
     reset_btn.add_css_class("destructive-action");
     reset_btn.set_visible(false);
@@ -378,6 +526,8 @@ pub(crate) fn build_settings_widget(
         rtd3_dropdown.set_sensitive(false);
         nvidia_current_switch.set_sensitive(false);
         dm_dropdown.set_sensitive(false);
+        initramfs_entry.set_sensitive(false);
+        initramfs_save_btn.set_sensitive(false);
     }
 
     let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
@@ -450,10 +600,13 @@ pub(crate) fn build_settings_widget(
             });
         }};
     }
+    fill_folder_rows(&folders_list, &extra_dirs, &apply_btn);
     on_change!(show_steam_switch, connect_active_notify);
     on_change!(show_heroic_switch, connect_active_notify);
+    on_change!(show_lutris_switch, connect_active_notify);
     on_change!(show_flatpak_switch, connect_active_notify);
     on_change!(use_env_switch, connect_active_notify);
+    on_change!(regenerate_switch, connect_active_notify);
     on_change!(check_updates_switch, connect_active_notify);
     on_change!(mode_dropdown, connect_selected_notify);
     on_change!(force_switch, connect_active_notify);
@@ -471,67 +624,38 @@ pub(crate) fn build_settings_widget(
 
     {
         let window = window.clone();
+        let status_list = status_list.clone();
         reset_btn.connect_clicked(move |_| {
-            match reset_all() {
-                Ok(()) => {
-                    let dlg = gtk::MessageDialog::builder()
-                        .transient_for(&window)
-                        .modal(true)
-                        .message_type(gtk::MessageType::Info)
-                        .text("NVIDIA configuration reset")
-                        .secondary_text("All NVIDIA graphics configuration files managed by Kaede were removed.\nReboot the system to fully apply changes.")
-                        .build();
-                    dlg.add_button("OK", gtk::ResponseType::Ok);
-                    dlg.connect_response(|d, _| d.close());
-                    dlg.present();
-                }
-                Err(err) => {
-                    error!(%err, "failed to reset NVIDIA configuration");
-                    let dlg = gtk::MessageDialog::builder()
-                        .transient_for(&window)
-                        .modal(true)
-                        .message_type(gtk::MessageType::Error)
-                        .text("Failed to reset NVIDIA configuration")
-                        .secondary_text(&err)
-                        .build();
-                    dlg.add_button("Close", gtk::ResponseType::Close);
-                    dlg.connect_response(|d, _| d.close());
-                    dlg.present();
-                }
-            }
+            let status_list = status_list.clone();
+            run_with_progress_dialog(
+                &window,
+                "Resetting NVIDIA configuration",
+                "All NVIDIA graphics configuration files managed by Kaede were removed. Reboot the system to fully apply changes.",
+                reset_all,
+                move |result| {
+                    if let Err(err) = result {
+                        error!(%err, "failed to reset NVIDIA configuration");
+                    }
+                    fill_status_rows(&status_list);
+                },
+            );
         });
     }
 
     {
         let window = window.clone();
         reset_sddm_btn.connect_clicked(move |_| {
-            match reset_sddm() {
-                Ok(()) => {
-                    let dlg = gtk::MessageDialog::builder()
-                        .transient_for(&window)
-                        .modal(true)
-                        .message_type(gtk::MessageType::Info)
-                        .text("SDDM Xsetup reset")
-                        .secondary_text("The SDDM Xsetup script was restored to a minimal default.\nReboot the system to apply changes.")
-                        .build();
-                    dlg.add_button("OK", gtk::ResponseType::Ok);
-                    dlg.connect_response(|d, _| d.close());
-                    dlg.present();
-                }
-                Err(err) => {
-                    error!(%err, "failed to reset SDDM Xsetup");
-                    let dlg = gtk::MessageDialog::builder()
-                        .transient_for(&window)
-                        .modal(true)
-                        .message_type(gtk::MessageType::Error)
-                        .text("Failed to reset SDDM Xsetup")
-                        .secondary_text(&err)
-                        .build();
-                    dlg.add_button("Close", gtk::ResponseType::Close);
-                    dlg.connect_response(|d, _| d.close());
-                    dlg.present();
-                }
-            }
+            run_with_progress_dialog(
+                &window,
+                "Resetting SDDM Xsetup",
+                "The SDDM Xsetup script was restored to a minimal default. Reboot the system to apply changes.",
+                reset_sddm,
+                |result| {
+                    if let Err(err) = result {
+                        error!(%err, "failed to reset SDDM Xsetup");
+                    }
+                },
+            );
         });
     }
 
@@ -563,22 +687,20 @@ pub(crate) fn build_settings_widget(
             };
             let use_nvidia_current = nvidia_current_switch.is_active();
 
-            let display_manager = match dm_dropdown.selected() {
-                0 => None,
-                1 => Some(DisplayManager::Gdm),
-                2 => Some(DisplayManager::Gdm3),
-                3 => Some(DisplayManager::Sddm),
-                4 => Some(DisplayManager::Lightdm),
-                _ => None,
-            };
+            let display_manager = (dm_dropdown.selected() as usize)
+                .checked_sub(1)
+                .and_then(|index| DisplayManager::ALL.get(index).copied());
 
             {
                 let mut cfg = config.borrow_mut();
                 cfg.set_show_steam_apps(show_steam_switch.is_active());
                 cfg.set_show_heroic_apps(show_heroic_switch.is_active());
+                cfg.set_show_lutris_apps(show_lutris_switch.is_active());
                 cfg.set_show_flatpak_apps(show_flatpak_switch.is_active());
                 cfg.set_use_env_wrapper(use_env_switch.is_active());
+                cfg.set_regenerate_stale_overrides(regenerate_switch.is_active());
                 cfg.set_check_updates_at_startup(check_updates_switch.is_active());
+                cfg.set_extra_scan_dirs(extra_dirs.borrow().clone());
                 if let Err(err) = cfg.save() {
                     error!(%err, "failed to save app settings");
                 }
@@ -600,22 +722,173 @@ pub(crate) fn build_settings_widget(
                 use_nvidia_current,
             };
 
-            if let Err(err) = switch_graphics_mode(&nvidia_config) {
-                error!(%err, "failed to switch NVIDIA graphics mode");
-                let dlg = gtk::MessageDialog::builder()
-                    .transient_for(&window)
-                    .modal(true)
-                    .message_type(gtk::MessageType::Error)
-                    .text("Failed to switch NVIDIA graphics mode")
-                    .secondary_text(&err)
-                    .build();
-                dlg.add_button("Close", gtk::ResponseType::Close);
-                dlg.connect_response(|d, _| d.close());
-                dlg.present();
+            let plan = match plan_graphics_mode(&nvidia_config) {
+                Ok(plan) => plan,
+                Err(err) => {
+                    error!(%err, "failed to plan NVIDIA graphics mode switch");
+                    show_switch_error(&window, &err);
+                    return;
+                }
+            };
+            if plan.is_empty() {
+                info!(mode = ?nvidia_config.mode, "NVIDIA configuration already in place");
+                return;
             }
+
+            let parent = window.clone();
+            let status_list = status_list.clone();
+            show_switch_plan_dialog(&window, &plan, move || {
+                let nvidia_config = nvidia_config.clone();
+                let status_list = status_list.clone();
+                run_with_progress_dialog(
+                    &parent,
+                    "Switching NVIDIA mode",
+                    "The new graphics mode is configured. Reboot the system to apply it.",
+                    move |output| switch_graphics_mode(&nvidia_config, output),
+                    move |result| {
+                        if let Err(err) = result {
+                            error!(%err, "failed to switch NVIDIA graphics mode");
+                        }
+                        fill_status_rows(&status_list);
+                    },
+                );
+            });
         });
     }
 
     (root, switcher)
 }
 
+/// Configured vs. running mode, followed by any inconsistency worth a warning.
+fn fill_status_rows(list: &gtk::ListBox) {
+    while let Some(child) = list.first_child() {
+        list.remove(&child);
+    }
+
+    let status = get_status();
+    let driver = match (&status.bound_driver, status.gpu_present) {
+        (Some(driver), _) => format!("NVIDIA GPU driven by {driver}"),
+        (None, true) => "NVIDIA GPU without a driver".to_string(),
+        (None, false) => "NVIDIA GPU not on the PCI bus".to_string(),
+    };
+    let status_row = adw::ActionRow::builder()
+        .title(status.summary().as_str())
+        .subtitle(driver.as_str())
+        .build();
+    let icon = if status.reboot_required() {
+        "system-reboot-symbolic"
+    } else {
+        "emblem-ok-symbolic"
+    };
+    status_row.add_prefix(&gtk::Image::from_icon_name(icon));
+    list.append(&status_row);
+
+    let params = if status.kernel_params.is_empty() {
+        "None related to NVIDIA".to_string()
+    } else {
+        status.kernel_params.join(" ")
+    };
+    let params_row = adw::ActionRow::builder()
+        .title("Kernel parameters")
+        .subtitle(params.as_str())
+        .build();
+    params_row.set_subtitle_lines(0);
+    params_row.add_prefix(&gtk::Image::from_icon_name("utilities-terminal-symbolic"));
+    list.append(&params_row);
+
+    for warning in status.warnings() {
+        let row = adw::ActionRow::builder().title(warning.as_str()).build();
+        row.set_title_lines(0);
+        let icon = gtk::Image::from_icon_name("dialog-warning-symbolic");
+        icon.add_css_class("warning");
+        row.add_prefix(&icon);
+        list.append(&row);
+    }
+}
+
+fn show_switch_error(window: &adw::ApplicationWindow, err: &str) {
+    let dlg = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Error)
+        .text("Failed to switch NVIDIA graphics mode")
+        .secondary_text(err)
+        .build();
+    dlg.add_button("Close", gtk::ResponseType::Close);
+    dlg.connect_response(|d, _| d.close());
+    dlg.present();
+}
+
+fn fill_folder_rows(list: &gtk::ListBox, dirs: &Rc<RefCell<Vec<PathBuf>>>, apply_btn: &gtk::Button) {
+    while let Some(child) = list.first_child() {
+        list.remove(&child);
+    }
+
+    for (index, dir) in dirs.borrow().iter().enumerate() {
+        let row = adw::ActionRow::builder()
+            .title(dir.to_string_lossy().as_ref())
+            .build();
+        if !dir.is_dir() {
+            row.set_subtitle("Folder not found");
+        }
+        let remove_btn = gtk::Button::from_icon_name("user-trash-symbolic");
+        remove_btn.add_css_class("flat");
+        remove_btn.set_valign(gtk::Align::Center);
+        remove_btn.set_tooltip_text(Some("Remove folder"));
+        row.add_suffix(&remove_btn);
+        list.append(&row);
+
+        let list = list.clone();
+        let dirs = dirs.clone();
+        let apply_btn = apply_btn.clone();
+        remove_btn.connect_clicked(move |_| {
+            dirs.borrow_mut().remove(index);
+            apply_btn.set_label("Apply");
+            apply_btn.set_sensitive(true);
+            fill_folder_rows(&list, &dirs, &apply_btn);
+        });
+    }
+
+    let entry = gtk::Entry::builder()
+        .placeholder_text("/opt/example/share/applications")
+        .hexpand(true)
+        .valign(gtk::Align::Center)
+        .build();
+    let add_btn = gtk::Button::with_label("Add");
+    add_btn.set_valign(gtk::Align::Center);
+    let add_row = adw::ActionRow::builder().title("Add folder").build();
+    add_row.add_suffix(&entry);
+    add_row.add_suffix(&add_btn);
+    list.append(&add_row);
+
+    let add = {
+        let list = list.clone();
+        let dirs = dirs.clone();
+        let apply_btn = apply_btn.clone();
+        let entry = entry.clone();
+        move || {
+            let text = entry.text().trim().to_string();
+            let path = match text.strip_prefix("~/") {
+                Some(rest) => std::env::var_os("HOME")
+                    .map(|home| PathBuf::from(home).join(rest))
+                    .unwrap_or_else(|| PathBuf::from(&text)),
+                None => PathBuf::from(&text),
+            };
+            if !path.is_absolute() || !path.is_dir() || dirs.borrow().contains(&path) {
+                entry.add_css_class("error");
+                return;
+            }
+            dirs.borrow_mut().push(path);
+            apply_btn.set_label("Apply");
+            apply_btn.set_sensitive(true);
+            fill_folder_rows(&list, &dirs, &apply_btn);
+        }
+    };
+    let add = Rc::new(add);
+    {
+        let add = add.clone();
+        add_btn.connect_clicked(move |_| add());
+    }
+    entry.connect_activate(move |_| add());
+    entry.connect_changed(|e| e.remove_css_class("error"));
+}
@@ -0,0 +1,324 @@
+//! Minimal line-based access to block-style YAML as written by PyYAML
+//! (Lutris and Bottles configs). Edits only touch the lines of the mapping
+//! being changed, so comments and the rest of the file survive untouched.
+
+use anyhow::Result;
+
+/// Lines of the mapping under a key path, as `key_line`, `end` and entry indentation.
+/// The root mapping has no key line.
+struct Block {
+    key_line: Option<usize>,
+    start: usize,
+    end: usize,
+    indent: usize,
+}
+
+pub fn get(content: &str, path: &[&str]) -> Option<String> {
+    let (last, parents) = path.split_last()?;
+    let lines = split_lines(content);
+    let block = locate(&lines, parents)?;
+    let at = find_key(&lines, block.start, block.end, block.indent, last)?;
+    Some(unquote(value_of(&lines[at])))
+}
+
+/// Keys directly under the mapping at `path`.
+pub fn keys(content: &str, path: &[&str]) -> Vec<String> {
+    let lines = split_lines(content);
+    let Some(block) = locate(&lines, path) else {
+        return Vec::new();
+    };
+    lines[block.start..block.end]
+        .iter()
+        .filter(|line| indent_of(line) == Some(block.indent))
+        .filter_map(|line| key_of(line).map(str::to_string))
+        .collect()
+}
+
+/// Scalar `key: value` entries directly under the mapping at `path`.
+pub fn entries(content: &str, path: &[&str]) -> Vec<(String, String)> {
+    let lines = split_lines(content);
+    let Some(block) = locate(&lines, path) else {
+        return Vec::new();
+    };
+    lines[block.start..block.end]
+        .iter()
+        .filter(|line| indent_of(line) == Some(block.indent))
+        .filter_map(|line| Some((key_of(line)?.to_string(), unquote(value_of(line)))))
+        .collect()
+}
+
+/// Replaces the `managed` keys of the mapping at `path` with `desired`,
+/// creating the mapping when needed. An emptied mapping is written as `{}`.
+pub fn set_entries(
+    content: &str,
+    path: &[&str],
+    managed: &[&str],
+    desired: &[(String, String)],
+) -> Result<String> {
+    let mut lines = split_lines(content);
+
+    let mut parent = root_block(&lines);
+    for key in path {
+        let at = match find_key(&lines, parent.start, parent.end, parent.indent, key) {
+            Some(at) => at,
+            None if desired.is_empty() => return Ok(content.to_string()),
+            None => {
+                lines.insert(parent.end, format!("{}{key}:", " ".repeat(parent.indent)));
+                parent.end
+            }
+        };
+        open_mapping(&mut lines, at)?;
+        parent = child_block(&lines, at, parent.indent);
+    }
+
+    let mut kept = lines[parent.start..parent.end]
+        .iter()
+        .filter(|line| {
+            indent_of(line) != Some(parent.indent)
+                || !key_of(line).is_some_and(|key| managed.contains(&key))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    kept.extend(desired.iter().map(|(key, value)| {
+        format!("{}{key}: {}", " ".repeat(parent.indent), quote(value))
+    }));
+
+    if let (Some(key_line), true) = (
+        parent.key_line,
+        kept.iter().all(|line| indent_of(line).is_none()),
+    ) {
+        lines.drain(parent.start..parent.end);
+        lines[key_line].push_str(" {}");
+    } else {
+        lines.splice(parent.start..parent.end, kept);
+    }
+
+    let mut out = lines.join("\n");
+    if content.is_empty() || content.ends_with('\n') {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+fn split_lines(content: &str) -> Vec<String> {
+    content.lines().map(str::to_string).collect()
+}
+
+fn root_block(lines: &[String]) -> Block {
+    Block {
+        key_line: None,
+        start: 0,
+        end: lines.len(),
+        indent: 0,
+    }
+}
+
+fn child_block(lines: &[String], key_line: usize, parent_indent: usize) -> Block {
+    let end = block_end(lines, key_line, parent_indent);
+    Block {
+        key_line: Some(key_line),
+        start: key_line + 1,
+        end,
+        indent: lines[key_line + 1..end]
+            .iter()
+            .find_map(|line| indent_of(line))
+            .unwrap_or(parent_indent + 2),
+    }
+}
+
+fn locate(lines: &[String], path: &[&str]) -> Option<Block> {
+    path.iter().try_fold(root_block(lines), |parent, key| {
+        let at = find_key(lines, parent.start, parent.end, parent.indent, key)?;
+        Some(child_block(lines, at, parent.indent))
+    })
+}
+
+fn indent_of(line: &str) -> Option<usize> {
+    let trimmed = line.trim_start();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return None;
+    }
+    Some(line.len() - trimmed.len())
+}
+
+fn key_of(line: &str) -> Option<&str> {
+    line.trim_start()
+        .split_once(':')
+        .map(|(key, _)| key.trim().trim_matches(|c| c == '"' || c == '\''))
+}
+
+fn value_of(line: &str) -> &str {
+    line.split_once(':').map(|(_, v)| v.trim()).unwrap_or_default()
+}
+
+fn find_key(lines: &[String], from: usize, to: usize, indent: usize, key: &str) -> Option<usize> {
+    (from..to).find(|&i| indent_of(&lines[i]) == Some(indent) && key_of(&lines[i]) == Some(key))
+}
+
+/// End (exclusive) of the block under the key at `start`, not counting trailing blank lines.
+fn block_end(lines: &[String], start: usize, indent: usize) -> usize {
+    let mut end = start + 1;
+    for (i, line) in lines.iter().enumerate().skip(start + 1) {
+        match indent_of(line) {
+            Some(n) if n <= indent => break,
+            Some(_) => end = i + 1,
+            None => {}
+        }
+    }
+    end
+}
+
+/// Turns `key: {}` into `key:` so children can be added; rejects other inline values.
+fn open_mapping(lines: &mut [String], at: usize) -> Result<()> {
+    match value_of(&lines[at]) {
+        "" => Ok(()),
+        "{}" => {
+            let key_end = lines[at].find(':').unwrap_or(lines[at].len());
+            lines[at].truncate(key_end + 1);
+            Ok(())
+        }
+        other => anyhow::bail!(
+            "unsupported inline YAML value for '{}': {other}",
+            key_of(&lines[at]).unwrap_or_default()
+        ),
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+fn unquote(value: &str) -> String {
+    if let Some(inner) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+        return inner.replace("''", "'");
+    }
+    if let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        return inner.replace("\\\"", "\"").replace("\\\\", "\\");
+    }
+    value.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LUTRIS: &str = "\
+game:
+  exe: /games/witcher3/bin/witcher3.exe
+  prefix: /games/witcher3
+# Added by hand
+system:
+  env:
+    # keep the overlay off
+    MANGOHUD: '0'
+    DRI_PRIME: '0'
+  prefer_system_libs: true
+name: The Witcher 3
+";
+
+    const MANAGED: [&str; 2] = ["DRI_PRIME", "KAEDE_GPU_MANAGED"];
+    const ENV: [&str; 2] = ["system", "env"];
+
+    fn pairs(values: &[(&str, &str)]) -> Vec<(String, String)> {
+        values
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_nested_values() {
+        assert_eq!(get(LUTRIS, &["game", "prefix"]).as_deref(), Some("/games/witcher3"));
+        assert_eq!(get(LUTRIS, &["name"]).as_deref(), Some("The Witcher 3"));
+        assert_eq!(keys(LUTRIS, &["system"]), ["env", "prefer_system_libs"]);
+        assert_eq!(entries(LUTRIS, &ENV), pairs(&[("MANGOHUD", "0"), ("DRI_PRIME", "0")]));
+    }
+
+    #[test]
+    fn inserts_missing_mappings() {
+        let content = "game:\n  exe: /usr/bin/supertux2\n";
+        let desired = pairs(&[("DRI_PRIME", "1"), ("KAEDE_GPU_MANAGED", "1")]);
+        let updated = set_entries(content, &ENV, &MANAGED, &desired).unwrap();
+        assert_eq!(
+            updated,
+            "game:\n  exe: /usr/bin/supertux2\n\
+             system:\n  env:\n    DRI_PRIME: '1'\n    KAEDE_GPU_MANAGED: '1'\n"
+        );
+        assert_eq!(entries(&updated, &ENV), desired);
+    }
+
+    #[test]
+    fn replaces_managed_keys_and_keeps_the_rest() {
+        let desired = pairs(&[("DRI_PRIME", "1"), ("KAEDE_GPU_MANAGED", "1")]);
+        let updated = set_entries(LUTRIS, &ENV, &MANAGED, &desired).unwrap();
+        assert_eq!(
+            updated,
+            LUTRIS.replace(
+                "    DRI_PRIME: '0'\n",
+                "    DRI_PRIME: '1'\n    KAEDE_GPU_MANAGED: '1'\n"
+            )
+        );
+        assert_eq!(set_entries(&updated, &ENV, &MANAGED, &desired).unwrap(), updated);
+    }
+
+    #[test]
+    fn removes_managed_keys_and_keeps_the_rest() {
+        let updated = set_entries(LUTRIS, &ENV, &MANAGED, &[]).unwrap();
+        assert_eq!(updated, LUTRIS.replace("    DRI_PRIME: '0'\n", ""));
+    }
+
+    #[test]
+    fn writes_an_emptied_mapping_as_braces() {
+        let content = "Name: Gaming\nEnvironment_Variables:\n  DRI_PRIME: '1'\nRunner: wine\n";
+        let updated = set_entries(content, &["Environment_Variables"], &MANAGED, &[]).unwrap();
+        assert_eq!(updated, "Name: Gaming\nEnvironment_Variables: {}\nRunner: wine\n");
+
+        let desired = pairs(&[("DRI_PRIME", "1")]);
+        let restored =
+            set_entries(&updated, &["Environment_Variables"], &MANAGED, &desired).unwrap();
+        assert_eq!(restored, content);
+    }
+
+    #[test]
+    fn leaves_missing_mappings_alone_when_nothing_is_desired() {
+        let content = "game:\n  exe: /usr/bin/supertux2\n";
+        assert_eq!(set_entries(content, &ENV, &MANAGED, &[]).unwrap(), content);
+    }
+
+    #[test]
+    fn rejects_inline_values() {
+        let content = "system:\n  env: [DRI_PRIME]\n";
+        let desired = pairs(&[("DRI_PRIME", "1")]);
+        assert!(set_entries(content, &ENV, &MANAGED, &desired).is_err());
+    }
+
+    #[test]
+    fn quotes_values() {
+        let desired = pairs(&[("DRI_PRIME", "it's: #1"), ("KAEDE_GPU_MANAGED", "")]);
+        let updated = set_entries("", &ENV, &MANAGED, &desired).unwrap();
+        assert_eq!(
+            updated,
+            "system:\n  env:\n    DRI_PRIME: 'it''s: #1'\n    KAEDE_GPU_MANAGED: ''\n"
+        );
+        assert_eq!(entries(&updated, &ENV), desired);
+        assert_eq!(unquote(r#""C:\\Games \"GOTY\"""#), r#"C:\Games "GOTY""#);
+    }
+
+    #[test]
+    fn follows_the_existing_indentation() {
+        let content = "system:\n    env:\n        MANGOHUD: '0'\n    gamemode: true\n";
+        let desired = pairs(&[("DRI_PRIME", "1")]);
+        let updated = set_entries(content, &ENV, &MANAGED, &desired).unwrap();
+        assert_eq!(
+            updated,
+            "system:\n    env:\n        MANGOHUD: '0'\n        DRI_PRIME: '1'\n    gamemode: true\n"
+        );
+
+        let content = "system:\n    gamemode: true\n";
+        let updated = set_entries(content, &ENV, &MANAGED, &desired).unwrap();
+        assert_eq!(
+            updated,
+            "system:\n    gamemode: true\n    env:\n      DRI_PRIME: '1'\n"
+        );
+    }
+}